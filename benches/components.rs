@@ -0,0 +1,46 @@
+//!
+//! Renders a document that uses a component many times and prints the time per run.
+//! Run with `cargo bench --bench components`.
+//!
+
+use std::time::Instant;
+
+use vtx::render_html;
+
+const RUNS : u32 = 5;
+
+const USES : usize = 5_000;
+
+fn document() -> String {
+
+    let mut src = String::from(r#"<Component Card title>
+<div class="card">
+<h3>${title}</h3>
+<div class="body">${children}</div>
+<p>Some <b>markup</b> in the <em>body</em> of the component.</p>
+</div>
+</Component>
+"#);
+
+    for i in 0..USES {
+        src.push_str(&format!(
+            "<Card title=\"Card {}\">Children with <b>markup</b>, a [link](https://example.com), and *emphasis*.</Card>\n", 
+            i
+        ));
+    }
+
+    src
+}
+
+fn main() {
+
+    let src = document();
+
+    let start = Instant::now();
+
+    for _ in 0..RUNS {
+        std::hint::black_box(render_html(std::hint::black_box(&src)).unwrap());
+    }
+
+    println!("components: {} uses in {:?}", USES, start.elapsed() / RUNS);
+}
@@ -0,0 +1,68 @@
+//!
+//! Parses a multi-megabyte document and prints the time per run.
+//! Also parses deeply nested envs, which should take about as long when they are never closed.
+//! Run with `cargo bench --bench parse`.
+//!
+
+use std::time::Instant;
+
+use vtx::parse::parse;
+
+const RUNS : u32 = 5;
+
+fn document() -> String {
+
+    let example = include_str!("../example/example.vtx");
+
+    // mostly prose with some markup, similar to a long article
+    let paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed do eiusmod tempor \
+        incididunt ut labore et dolore magna aliqua, with some *emphasis*, `code`, and $x^2$ math. \
+        Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea. Äöü ✓\n\n";
+
+    let mut src = example.to_string();
+
+    while src.len() < 4_000_000 {
+        src.push_str("## Section\n\n");
+        src.push_str(&paragraph.repeat(20));
+        src.push_str("<div class=\"box\">\n");
+        src.push_str(&paragraph.repeat(5));
+        src.push_str("</div>\n\n");
+    }
+
+    src
+}
+
+fn bench(name : &str, src : &str) {
+
+    let start = Instant::now();
+
+    for _ in 0..RUNS {
+        std::hint::black_box(parse(std::hint::black_box(src)));
+    }
+
+    let elapsed = start.elapsed() / RUNS;
+
+    println!(
+        "{}: {:.1} MB in {:?} ({:.1} MB/s)", 
+        name,
+        src.len() as f64 / 1e6, 
+        elapsed, 
+        src.len() as f64 / 1e6 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+
+    bench("parse", &document());
+
+    // every env is nested in the previous one, which needs a larger stack
+    std::thread::Builder::new()
+        .stack_size(1 << 30)
+        .spawn(|| {
+            bench("nested", &("<p>x".repeat(5_000) + &"</p>".repeat(5_000)));
+            bench("unclosed", &"<p>x".repeat(5_000));
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,77 @@
+//!
+//! Emits a large tree with the HTMLEmitter, once through a boxed visitor (transform)
+//! and once through a generic one (transform_one), and prints the fastest run of both.
+//! Runs alternate between both to even out noise.
+//! Run with `cargo bench --bench transform`.
+//!
+
+use std::time::{Duration, Instant};
+
+use vtx::document::visit::{transform, transform_one, Visitor};
+use vtx::document::Node;
+use vtx::parse::parse;
+use vtx::visitors::html_emit::HTMLEmitter;
+
+const RUNS : u32 = 10;
+
+fn document() -> Node {
+
+    let paragraph = "<p>Some <b>bold</b>, <em>emphasized</em>, and <a href=\"#\">linked</a> text.</p>\n";
+
+    let mut src = String::new();
+
+    while src.len() < 4_000_000 {
+        src.push_str("<section>\n");
+        src.push_str(&paragraph.repeat(10));
+        src.push_str("</section>\n");
+    }
+
+    parse(&src).0
+}
+
+fn dynamic(document : Node) {
+
+    let mut html = String::new();
+
+    let emitter : Box<dyn Visitor + '_> = Box::new(HTMLEmitter::new(|s : &str| html.push_str(s)));
+
+    transform(document, &mut vec![emitter], 1).unwrap();
+
+    std::hint::black_box(html);
+}
+
+fn generic(document : Node) {
+
+    let mut html = String::new();
+
+    transform_one(document, &mut HTMLEmitter::new(|s : &str| html.push_str(s)), 1).unwrap();
+
+    std::hint::black_box(html);
+}
+
+/** Time of running f on a copy of the document. */
+fn measure(document : &Node, f : fn(Node)) -> Duration {
+
+    let document = document.clone();
+
+    let start = Instant::now();
+
+    f(document);
+
+    start.elapsed()
+}
+
+fn main() {
+
+    let document = document();
+
+    let mut fastest = [Duration::MAX; 2];
+
+    for _ in 0..RUNS {
+        fastest[0] = fastest[0].min(measure(&document, dynamic));
+        fastest[1] = fastest[1].min(measure(&document, generic));
+    }
+
+    println!("transform (dyn): {:?}", fastest[0]);
+    println!("transform_one (generic): {:?}", fastest[1]);
+}
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(src) = std::str::from_utf8(data) {
+        vtx::parse::parse(src);
+
+        let config = vtx::parse::ParseConfig { error_nodes: true, latex_delimiters: true, ..Default::default() };
+
+        vtx::parse::parse_with_config(src, &config);
+    }
+});
@@ -0,0 +1,131 @@
+//!
+//! Merging of attribute maps, e.g. component defaults with the attributes of a usage.
+//!
+
+use std::fmt;
+
+use crate::document::*;
+
+/// Attributes whose values are space separated lists of tokens.
+const TOKEN_LIST_ATTRS : [&str; 2] = ["class", "rel"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /** Values of the merged attributes replace existing ones. */
+    Override,
+    /** Existing values are kept. */
+    Keep,
+    /** Conflicting keys are an error. */
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttrConflict {
+    pub key: String,
+}
+
+impl fmt::Display for AttrConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Attribute \"{}\" is defined more than once.", self.key)
+    }
+}
+
+impl std::error::Error for AttrConflict {}
+
+pub trait MergeAttrs {
+
+    ///
+    /// Adds the attributes of other in order.
+    /// Token list attributes like class are concatenated if both values are text,
+    /// other conflicts are resolved using the policy.
+    ///
+    fn merge(&mut self, other : EnvNodeAttrs, policy : MergePolicy) -> Result<(), AttrConflict>;
+
+}
+
+fn text(value : &Option<Node>) -> Option<&str> {
+    match value {
+        Some(Node { kind: NodeKind::Leaf(LeafNode::Text(text)), .. }) => Some(text),
+        _ => None,
+    }
+}
+
+impl MergeAttrs for EnvNodeAttrs {
+
+    fn merge(&mut self, other : EnvNodeAttrs, policy : MergePolicy) -> Result<(), AttrConflict> {
+
+        for (key, value) in other {
+
+            let Some(existing) = self.get_mut(&key) else {
+                self.insert(key, value);
+                continue;
+            };
+
+            if TOKEN_LIST_ATTRS.contains(&key.as_str()) {
+                if let (Some(a), Some(b)) = (text(existing), text(&value)) {
+
+                    let tokens = a.split_whitespace().chain(b.split_whitespace()).collect::<Vec<&str>>().join(" ");
+
+                    *existing = Some(Node::new(NodeKind::Leaf(LeafNode::Text(tokens)), NodePosition::Inserted));
+
+                    continue;
+                }
+            }
+
+            match policy {
+                MergePolicy::Override => *existing = value,
+                MergePolicy::Keep => {},
+                MergePolicy::Error => return Err(AttrConflict { key }),
+            }
+        }
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn attrs(pairs : Vec<(&str, Option<&str>)>) -> EnvNodeAttrs {
+        EnvNodeHeader::generate_attrs(pairs)
+    }
+
+    fn values(attrs : &EnvNodeAttrs) -> Vec<(&str, Option<&str>)> {
+        attrs.iter().map(|(key, value)| (key.as_str(), text(value))).collect()
+    }
+
+    #[test]
+    fn conflict_policies() {
+
+        let defaults = || attrs(vec![("variant", Some("primary")), ("disabled", None)]);
+
+        let mut merged = defaults();
+        merged.merge(attrs(vec![("variant", Some("danger")), ("id", Some("x"))]), MergePolicy::Override).unwrap();
+
+        assert_eq!(values(&merged), [("variant", Some("danger")), ("disabled", None), ("id", Some("x"))]);
+
+        let mut merged = defaults();
+        merged.merge(attrs(vec![("variant", Some("danger")), ("id", Some("x"))]), MergePolicy::Keep).unwrap();
+
+        assert_eq!(values(&merged), [("variant", Some("primary")), ("disabled", None), ("id", Some("x"))]);
+
+        let mut merged = defaults();
+        let error = merged.merge(attrs(vec![("variant", Some("danger"))]), MergePolicy::Error).unwrap_err();
+
+        assert_eq!(error.to_string(), "Attribute \"variant\" is defined more than once.");
+    }
+
+    #[test]
+    fn token_lists() {
+
+        let mut merged = attrs(vec![("class", Some("btn  btn-primary")), ("rel", Some("noopener"))]);
+
+        merged.merge(attrs(vec![("class", Some("large")), ("rel", Some("noreferrer"))]), MergePolicy::Error).unwrap();
+
+        assert_eq!(values(&merged), [("class", Some("btn btn-primary large")), ("rel", Some("noopener noreferrer"))]);
+    }
+
+}
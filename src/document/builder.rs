@@ -0,0 +1,130 @@
+//!
+//! Fluent construction of nodes, e.g. for visitors that insert new elements:
+//!
+//! NodeBuilder::env("div")
+//!     .attr("class", "box")
+//!     .child(NodeBuilder::text("Hello"))
+//!     .build()
+//!
+//! Built nodes have a new id and are positioned as NodePosition::Inserted.
+//!
+
+use std::collections::VecDeque;
+
+use crate::document::*;
+
+pub struct NodeBuilder {
+    header: EnvNodeHeader,
+    /** None for self-closing envs. */
+    children: Option<VecDeque<Node>>,
+    position: NodePosition,
+}
+
+impl NodeBuilder {
+
+    /** Env without children, e.g. <div></div>. */
+    pub fn env(name : &str) -> Self {
+        Self {
+            header: EnvNodeHeader::new(name, EnvNodeAttrs::new()),
+            children: Some(VecDeque::new()),
+            position: NodePosition::Inserted,
+        }
+    }
+
+    /** Self-closing env, e.g. <img/>. Adding children turns it into an open env. */
+    pub fn self_closing(name : &str) -> Self {
+        Self { children: None, ..Self::env(name) }
+    }
+
+    /** Fragment whose children are spliced into its parent. */
+    pub fn fragment() -> Self {
+        Self {
+            header: EnvNodeHeader::from_kind(EnvNodeHeaderKind::Fragment, EnvNodeAttrs::new()),
+            ..Self::env("")
+        }
+    }
+
+    /** Text node. */
+    pub fn text(text : impl Into<String>) -> Node {
+        Node::new(NodeKind::Leaf(LeafNode::Text(text.into())), NodePosition::Inserted)
+    }
+
+    /** Adds an attribute with a text value. */
+    pub fn attr(mut self, key : &str, value : impl Into<String>) -> Self {
+        self.header.attrs.insert(key.to_string(), Some(Self::text(value)));
+        self
+    }
+
+    /** Adds an attribute without a value, e.g. hidden. */
+    pub fn flag(mut self, key : &str) -> Self {
+        self.header.attrs.insert(key.to_string(), None);
+        self
+    }
+
+    /** Adds all attributes. */
+    pub fn attrs(mut self, attrs : EnvNodeAttrs) -> Self {
+        self.header.attrs.extend(attrs);
+        self
+    }
+
+    pub fn child(mut self, child : Node) -> Self {
+        self.children.get_or_insert_with(VecDeque::new).push_back(child);
+        self
+    }
+
+    pub fn children(mut self, children : impl IntoIterator<Item = Node>) -> Self {
+        self.children.get_or_insert_with(VecDeque::new).extend(children);
+        self
+    }
+
+    /** Position of the built node, e.g. the position of the node it replaces. */
+    pub fn position(mut self, position : NodePosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn build(self) -> Node {
+
+        let env = match self.children {
+            Some(children) => EnvNode::new_open(self.header, children),
+            None => EnvNode::new_self_closing(self.header),
+        };
+
+        Node::new(NodeKind::Env(env), self.position)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::parse;
+
+    #[test]
+    fn subtree() {
+
+        let figure = NodeBuilder::env("figure")
+            .attr("class", "wide")
+            .flag("hidden")
+            .child(NodeBuilder::self_closing("img").attr("src", "a.png").build())
+            .child(
+                NodeBuilder::env("figcaption")
+                    .children([NodeBuilder::text("A "), NodeBuilder::env("em").child(NodeBuilder::text("b")).build()])
+                    .build()
+            )
+            .build();
+
+        let (expected, _) = parse(r#"<figure class="wide" hidden><img src="a.png"/><figcaption>A <em>b</em></figcaption></figure>"#);
+
+        assert_eq!(Some(&figure), expected.descendants().next());
+
+        assert!(matches!(figure.position, NodePosition::Inserted));
+        assert_ne!(figure.id, NodeBuilder::env("figure").build().id);
+
+        let fragment = NodeBuilder::fragment().child(NodeBuilder::text("x")).build();
+
+        assert_eq!(fragment, Node::new(NodeKind::new_fragment([NodeBuilder::text("x")].into()), NodePosition::Inserted));
+    }
+
+}
@@ -0,0 +1,285 @@
+//!
+//! Structural comparison of two versions of a document, e.g. an original and the
+//! original a translation was based on.
+//!
+//! Changes that only concern the structure can be applied to a translation automatically,
+//! while content changes need to be translated again.
+//!
+
+use std::collections::VecDeque;
+
+use crate::document::*;
+
+/// Indices of the children leading from the root to a node.
+pub type NodePath = Vec<usize>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeChange {
+    /** Node that only exists in the new tree (path in the new tree). */
+    Added(NodePath),
+    /** Node that only exists in the old tree (path in the old tree). */
+    Removed(NodePath),
+    /** Unchanged node that has been moved. */
+    Moved { old: NodePath, new: NodePath },
+    /** Node with the same structure and different text. */
+    ContentChanged { old: NodePath, new: NodePath },
+    /** Node with different attributes or markup. */
+    StructureChanged { old: NodePath, new: NodePath },
+}
+
+fn children(node : &Node) -> Option<&VecDeque<Node>> {
+    match &node.kind {
+        NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => Some(children),
+        _ => None,
+    }
+}
+
+/** Text of the node and all nodes below it without surrounding whitespace. */
+fn text(node : &Node) -> String {
+    std::iter::once(node)
+        .chain(node.descendants())
+        .filter_map(|node| match &node.kind {
+            NodeKind::Leaf(LeafNode::Text(text)) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/** Checks if the nodes are the same kind of leaf or env with the same name. */
+fn same_shape(a : &Node, b : &Node) -> bool {
+    match (&a.kind, &b.kind) {
+        (NodeKind::Leaf(a), NodeKind::Leaf(b)) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        (NodeKind::Env(a), NodeKind::Env(b)) => a.header.kind == b.header.kind,
+        _ => false,
+    }
+}
+
+fn child_path(path : &[usize], i : usize) -> NodePath {
+    path.iter().copied().chain([i]).collect()
+}
+
+///
+/// Pairs of indices of a longest common subsequence of equal nodes.
+///
+fn common_subsequence(old : &VecDeque<Node>, new : &VecDeque<Node>) -> Vec<(usize, usize)> {
+
+    // lengths[i][j] is the length of the LCS of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    pairs
+}
+
+fn diff_nodes(old : &Node, new : &Node, old_path : &[usize], new_path : &[usize], changes : &mut Vec<TreeChange>) {
+
+    match (&old.kind, &new.kind) {
+        _ if old == new => {},
+        (NodeKind::Leaf(LeafNode::Text(_)), NodeKind::Leaf(LeafNode::Text(_))) => changes.push(
+            TreeChange::ContentChanged { old: old_path.to_vec(), new: new_path.to_vec() }
+        ),
+        (NodeKind::Env(a), NodeKind::Env(b)) if a.header.attrs == b.header.attrs => match (children(old), children(new)) {
+            (Some(old_children), Some(new_children)) => diff_children(
+                old_children,
+                new_children,
+                old_path,
+                new_path,
+                changes
+            ),
+            _ => changes.push(TreeChange::StructureChanged { old: old_path.to_vec(), new: new_path.to_vec() }),
+        },
+        _ => changes.push(TreeChange::StructureChanged { old: old_path.to_vec(), new: new_path.to_vec() }),
+    }
+}
+
+///
+/// Matches the children of two nodes in three steps:
+/// equal nodes in the same order, nodes with the same text anywhere (moved),
+/// and remaining nodes of the same shape in order (changed).
+///
+fn diff_children(
+    old : &VecDeque<Node>,
+    new : &VecDeque<Node>,
+    old_path : &[usize],
+    new_path : &[usize],
+    changes : &mut Vec<TreeChange>
+) {
+
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+
+    for (i, j) in common_subsequence(old, new) {
+        old_matched[i] = true;
+        new_matched[j] = true;
+    }
+
+    // re-identify moved nodes by their text
+    for (i, old_child) in old.iter().enumerate() {
+
+        if old_matched[i] {
+            continue;
+        }
+
+        let old_text = text(old_child);
+
+        if old_text.is_empty() {
+            continue;
+        }
+
+        let Some(j) = (0..new.len()).find(|j| !new_matched[*j] && text(&new[*j]) == old_text) else {
+            continue;
+        };
+
+        old_matched[i] = true;
+        new_matched[j] = true;
+
+        let paths = (child_path(old_path, i), child_path(new_path, j));
+
+        changes.push(match old_child == &new[j] {
+            true => TreeChange::Moved { old: paths.0, new: paths.1 },
+            false => TreeChange::StructureChanged { old: paths.0, new: paths.1 },
+        });
+    }
+
+    // pair the remaining nodes in order
+    let mut next = 0;
+
+    for i in 0..old.len() {
+
+        if old_matched[i] {
+            continue;
+        }
+
+        let Some(j) = (next..new.len()).find(|j| !new_matched[*j] && same_shape(&old[i], &new[*j])) else {
+            continue;
+        };
+
+        old_matched[i] = true;
+        new_matched[j] = true;
+        next = j + 1;
+
+        diff_nodes(&old[i], &new[j], &child_path(old_path, i), &child_path(new_path, j), changes);
+    }
+
+    changes.extend(
+        (0..old.len()).filter(|i| !old_matched[*i]).map(|i| TreeChange::Removed(child_path(old_path, i)))
+    );
+
+    changes.extend(
+        (0..new.len()).filter(|j| !new_matched[*j]).map(|j| TreeChange::Added(child_path(new_path, j)))
+    );
+}
+
+///
+/// Lists the changes from the old to the new tree.
+/// Unchanged nodes are not listed.
+///
+pub fn diff(old : &Node, new : &Node) -> Vec<TreeChange> {
+
+    let mut changes = Vec::new();
+
+    diff_nodes(old, new, &[], &[], &mut changes);
+
+    changes
+}
+
+impl Node {
+
+    /** Finds the node at the path below this node. */
+    pub fn at_path(&self, path : &[usize]) -> Option<&Node> {
+        match path.split_first() {
+            None => Some(self),
+            Some((i, rest)) => children(self)?.get(*i)?.at_path(rest),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::parse;
+
+    fn changes(old : &str, new : &str) -> Vec<TreeChange> {
+        diff(&parse(old).0, &parse(new).0)
+    }
+
+    #[test]
+    fn added_paragraph() {
+
+        assert_eq!(changes("<p>One</p><p>Two</p>", "<p>One</p><p>Two</p>"), []);
+
+        assert_eq!(
+            changes("<p>One</p><p>Two</p>", "<p>One</p><p>New</p><p>Two</p>"),
+            [TreeChange::Added(vec![1])]
+        );
+
+        assert_eq!(
+            changes("<p>One</p><p>Two</p><p>Three</p>", "<p>One</p><p>Three</p>"),
+            [TreeChange::Removed(vec![1])]
+        );
+    }
+
+    #[test]
+    fn reworded_sentence() {
+
+        let old = "<Section><p>Hello <em>world</em>.</p><p>Unchanged.</p></Section>";
+        let new = "<Section><p>Hello <em>there</em>.</p><p>Unchanged.</p></Section>";
+
+        let changes = changes(old, new);
+
+        assert_eq!(changes, [TreeChange::ContentChanged { old: vec![0, 0, 1, 0], new: vec![0, 0, 1, 0] }]);
+
+        let TreeChange::ContentChanged { new: path, .. } = &changes[0] else { unreachable!() };
+
+        assert_eq!(parse(new).0.at_path(path).map(text), Some("there".to_string()));
+    }
+
+    #[test]
+    fn moved_and_restructured() {
+
+        assert_eq!(
+            changes("<p>A</p><p>B</p>", "<p>B</p><p>A</p>"),
+            [TreeChange::Moved { old: vec![0], new: vec![1] }]
+        );
+
+        // same text, different markup
+        assert_eq!(
+            changes("<p>Some text</p>", "<p class=\"note\">Some text</p>"),
+            [TreeChange::StructureChanged { old: vec![0], new: vec![0] }]
+        );
+
+        assert_eq!(
+            changes("<p>Some text</p>", "<p>Some <em>text</em></p>"),
+            [TreeChange::StructureChanged { old: vec![0], new: vec![0] }]
+        );
+    }
+
+}
@@ -0,0 +1,164 @@
+//!
+//! A parsed document with the most common operations on it.
+//!
+
+use crate::document::visit::{transform_one, transform_to_fixpoint, VisitError, Visitor};
+use crate::document::{EnvNode, EnvNodeAttrs, EnvNodeHeader, EnvNodeHeaderKind, Node, NodeKind};
+use crate::error::Diagnostic;
+use crate::parse::parse;
+use crate::visitors::html_emit::HTMLEmitter;
+use crate::visitors::toc::text_content;
+
+///
+/// The root node of a document, which is always a module.
+///
+/// Document::try_from wraps an existing node and fails unless it is a module.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    root: Node,
+}
+
+impl Document {
+
+    ///
+    /// Parses the source, recovering from syntax errors.
+    /// The errors and warnings of the parser are returned as diagnostics.
+    ///
+    pub fn parse(src : &str) -> (Self, Vec<Diagnostic>) {
+
+        let (root, tokens) = parse(src);
+
+        (Self { root }, Diagnostic::from_tokens(&tokens))
+    }
+
+    /** The module node, e.g. to run visitors on it directly. */
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /** Takes the module node out of the document. Use Document::try_from to wrap it again. */
+    pub fn into_root(self) -> Node {
+        self.root
+    }
+
+    ///
+    /// Runs the transformers until the document does not change anymore, see [transform_to_fixpoint].
+    /// Fails if a transformer replaces the module with another kind of node.
+    ///
+    pub fn transform(self, transformers : &mut [Box<dyn Visitor + '_>]) -> Result<Self, VisitError> {
+
+        transform_to_fixpoint(self.root, transformers)?.try_into()
+    }
+
+    ///
+    /// Emits the document as HTML without transforming it.
+    /// Run the [crate::render::default_transformers] first in order to expand components and variables.
+    ///
+    pub fn to_html(&self) -> Result<String, VisitError> {
+
+        let mut html = String::new();
+
+        transform_one(self.root.clone(), &mut HTMLEmitter::new(|s : &str| html.push_str(s)), 1)?;
+
+        Ok(html)
+    }
+
+    /** Attributes of the module, e.g. the entries of the frontmatter. */
+    pub fn metadata(&self) -> &EnvNodeAttrs {
+        match &self.root.kind {
+            NodeKind::Env(EnvNode { header, .. }) => &header.attrs,
+            // checked when the document is created
+            NodeKind::Leaf(_) => unreachable!(),
+        }
+    }
+
+    ///
+    /// The title from the frontmatter or else the text of the first level 1 heading.
+    ///
+    pub fn title(&self) -> Option<String> {
+
+        if let Some(Some(title)) = self.metadata().get("title") {
+            return title.literal_text();
+        }
+
+        self.root
+            .descendants()
+            .find(|node| matches!(
+                &node.kind,
+                NodeKind::Env(EnvNode { header, .. }) if header.kind.heading_level() == Some(1)
+            ))
+            .map(|heading| text_content(heading).trim().to_string())
+    }
+
+}
+
+impl TryFrom<Node> for Document {
+
+    type Error = VisitError;
+
+    fn try_from(root : Node) -> Result<Self, Self::Error> {
+        match &root.kind {
+            NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Module, .. }, .. }) => Ok(Self { root }),
+            _ => Err(VisitError::Unknown("The root of a document must be a module.".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::document::NodePosition;
+    use crate::render::default_transformers;
+
+    #[test]
+    fn parse_and_render() {
+
+        let (document, diagnostics) = Document::parse(
+            "---\ntitle: Notes\nlang: en\n---\n<Component Greeting who><p>Hello ${who}</p></Component><Greeting who=\"World\"/>"
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        assert_eq!(document.title().as_deref(), Some("Notes"));
+        assert_eq!(document.metadata().keys().collect::<Vec<_>>(), ["title", "lang"]);
+
+        let document = document.transform(&mut default_transformers()).unwrap();
+
+        assert_eq!(document.to_html().unwrap(), "<p>Hello World</p>");
+
+        // emitting does not consume the document
+        assert_eq!(document.title().as_deref(), Some("Notes"));
+
+        let (_, diagnostics) = Document::parse("<div>text");
+
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn title() {
+
+        assert_eq!(Document::parse("text\n## Part\n# The *Title*\n# Other").0.title().as_deref(), Some("The Title"));
+        assert_eq!(Document::parse("<h1> Title </h1>").0.title().as_deref(), Some("Title"));
+        assert_eq!(Document::parse("## Part").0.title(), None);
+    }
+
+    #[test]
+    fn root_must_be_module() {
+
+        let fragment = Node::new(NodeKind::new_fragment(VecDeque::new()), NodePosition::Inserted);
+
+        let error = Document::try_from(fragment).unwrap_err();
+
+        assert_eq!(error.to_string(), "The root of a document must be a module.");
+
+        let (document, _) = Document::parse("text");
+
+        let root = document.clone().into_root();
+
+        assert_eq!(Document::try_from(root).unwrap(), document);
+    }
+}
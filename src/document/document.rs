@@ -1,12 +1,12 @@
 
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     sync::atomic::{AtomicUsize, Ordering}
 };
 
 use indexmap::IndexMap;
 
-use crate::parse::{ParserPosition, Token};
+use crate::parse::{Span, Token};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum EquationKind {
@@ -21,28 +21,54 @@ pub enum EnvNodeHeaderKind {
     Code,
     Module,
     // TODO: remove heading
+    // 0-based level, Heading(0) is <h1>
     Heading(usize),
     Other(String),
     // container for a list of child nodes
+    // fragments are transparent: emitters and transformers treat their children
+    // as part of the children of the parent, see Node::flatten_fragments
     Fragment,
     ComponentDefinition
 }
 
+/// Attributes in insertion (source) order.
 pub type EnvNodeAttrs = IndexMap<String, Option<Node>>;
 
+/// Quotes around an attribute value in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuoteStyle {
+    #[default]
+    Double,
+    Single,
+}
+
 #[derive(Debug, Clone)]
 pub struct EnvNodeHeader {
     pub kind: EnvNodeHeaderKind,
     pub attrs: EnvNodeAttrs,
+    ///
+    /// Quotes of the attribute values as written in the source, so they can be written the same way.
+    /// Attributes without an entry (e.g. inserted by transformers) use double quotes.
+    ///
+    pub quotes: HashMap<String, QuoteStyle>,
 }
 
-#[derive(Debug, Clone)]
+/** The quotes of the attribute values are a matter of style and not compared. */
+impl PartialEq for EnvNodeHeader {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.attrs == other.attrs
+    }
+
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum EnvNodeKind {
     Open(VecDeque<Node>),
     SelfClosing,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EnvNode {
     pub kind: EnvNodeKind,
     pub header: EnvNodeHeader,
@@ -58,7 +84,7 @@ pub enum LeafNode {
     Error(String)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum NodeKind{
     Leaf(LeafNode),
     Env(EnvNode),
@@ -68,46 +94,30 @@ pub type NodeId = usize;
 
 #[derive(Debug, Clone)]
 pub enum NodePosition {
-    Source(ParserPosition),
+    Source(Span),
     Inserted
 }
 
-#[derive(Debug)]
+///
+/// Cloning keeps the ids of all nodes. 
+/// Use Node::instantiate to insert a copy of a node into the same document.
+///
+#[derive(Debug, Clone)]
 pub struct Node {
     pub id : NodeId,
     pub kind: NodeKind,
     pub position: NodePosition,
 }
 
-// TODO: this isn't really great: the user would expect a cloned node to have the same id? 
-// but it is required for TransformerOnce to work...
-// --> change the way TransformerOnce works...
-impl Clone for Node {
-    fn clone(&self) -> Self {
-        Self { 
-            id: Node::generate_id(), 
-            kind: self.kind.clone(), 
-            position: self.position.clone()
-        }
-    }
-}
-
-// TODO: This is only required in order to compare attrs in testing. Remove
+///
+/// Structural equality: compares the kind of the nodes including all attributes and children.
+/// Ids and positions are ignored, so independently parsed documents with the same content are equal.
+/// Attributes are compared regardless of their order.
+///
 impl PartialEq for Node {
 
     fn eq(&self, other: &Self) -> bool {
-        // TODO
-        match &self.kind {
-            NodeKind::Leaf(LeafNode::Text(text)) => {
-                match &other.kind {
-                    NodeKind::Leaf(LeafNode::Text(other_text)) => {
-                        other_text == text
-                    },
-                    _ => false
-                }
-            },
-            _ => false,
-        }
+        self.kind == other.kind
     }
 
 }
@@ -118,10 +128,7 @@ impl NodeKind {
         NodeKind::Env(
             EnvNode {
                 kind: EnvNodeKind::Open(children),
-                header: EnvNodeHeader {
-                    kind: EnvNodeHeaderKind::Fragment,
-                    attrs: EnvNodeAttrs::new(),
-                }
+                header: EnvNodeHeader::from_kind(EnvNodeHeaderKind::Fragment, EnvNodeAttrs::new())
             }
         )
     }
@@ -156,10 +163,7 @@ impl EnvNode {
     pub fn new_module(children: VecDeque<Node>) -> Self {
         Self { 
             kind: EnvNodeKind::Open(children), 
-            header: EnvNodeHeader {
-                kind: EnvNodeHeaderKind::Module,
-                attrs: EnvNodeAttrs::new(),
-            }
+            header: EnvNodeHeader::from_kind(EnvNodeHeaderKind::Module, EnvNodeAttrs::new())
         }
     }
 }
@@ -180,12 +184,21 @@ impl EnvNodeHeaderKind {
             EnvNodeHeaderKind::Eq(_) => "Eq",
             EnvNodeHeaderKind::Code => "Code",
             EnvNodeHeaderKind::Module => "",
-            EnvNodeHeaderKind::Heading(0) => "h1",
-            EnvNodeHeaderKind::Heading(1) => "h2",
-            EnvNodeHeaderKind::Heading(_) => "h3",
+            // levels beyond h6 do not exist in HTML
+            EnvNodeHeaderKind::Heading(level) => ["h1", "h2", "h3", "h4", "h5", "h6"][(*level).min(5)],
             EnvNodeHeaderKind::Fragment => "",
             EnvNodeHeaderKind::ComponentDefinition => "Component",
-            EnvNodeHeaderKind::Other(name) => &name
+            EnvNodeHeaderKind::Other(name) => name
+        }
+    }
+
+    ///
+    /// Level (1-6) of headings, including <h1>..<h6> elements.
+    /// 
+    pub fn heading_level(&self) -> Option<usize> {
+        match self.get_name().as_bytes() {
+            [b'h', level @ b'1'..=b'6'] => Some((level - b'0') as usize),
+            _ => None,
         }
     }
 
@@ -204,14 +217,36 @@ impl EnvNodeHeader {
     /** Create new empty header with the specified name */
     pub fn new(parsed_name : &str, attrs : EnvNodeAttrs) -> Self {
 
-        let kind = EnvNodeHeaderKind::new(parsed_name);
+        Self::from_kind(EnvNodeHeaderKind::new(parsed_name), attrs)
+    }
 
+    /** Create new header of the specified kind, whose attributes use the default quotes. */
+    pub fn from_kind(kind : EnvNodeHeaderKind, attrs : EnvNodeAttrs) -> Self {
         Self { 
             kind, 
             attrs,
+            quotes: HashMap::new(),
         }
     }
 
+    /** Quotes of an attribute value, double quotes unless the source used single quotes. */
+    pub fn quote_style(&self, key : &str) -> QuoteStyle {
+        self.quotes.get(key).copied().unwrap_or_default()
+    }
+
+    /** Change the name of the env, keeping all attributes. */
+    pub fn rename(&mut self, new_name : &str) {
+        self.kind = EnvNodeHeaderKind::new(new_name);
+    }
+
+    ///
+    /// Text of an attribute value. 
+    /// None if the attribute does not exist, has no value, or contains unresolved variable expressions.
+    /// 
+    pub fn attr_text(&self, key : &str) -> Option<String> {
+        self.attrs.get(key)?.as_ref()?.literal_text()
+    }
+
     pub fn new_default(parsed_name : &str) -> Self {
 
         Self::new(parsed_name, Self::default_attrs(parsed_name))
@@ -239,7 +274,7 @@ impl EnvNodeHeader {
             );
         }
 
-        return attrs;
+        attrs
 
     }
 }
@@ -259,7 +294,7 @@ impl Node {
     pub fn new_text(token: &Token) -> Self {
         Self::new(
             NodeKind::Leaf(LeafNode::Text(String::from(token.value))),
-            NodePosition::Source(token.position.clone())
+            NodePosition::Source(token.span())
         )
     }
 
@@ -276,6 +311,83 @@ impl Node {
             position: NodePosition::Inserted
         }
     }
+
+    ///
+    /// Copy of the node in which this node and all nodes below it (including attribute values) have new ids.
+    /// Transformers skip nodes with ids they have visited before (see TransformerOnce), 
+    /// so every copy inserted into a document, e.g. the body of a component, must be an instance.
+    ///
+    pub fn instantiate(&self) -> Node {
+
+        let mut node = self.clone();
+
+        node.renew_ids();
+
+        node
+    }
+
+    fn renew_ids(&mut self) {
+
+        self.id = Node::generate_id();
+
+        if let NodeKind::Env(EnvNode { header, kind }) = &mut self.kind {
+
+            for value in header.attrs.values_mut().flatten() {
+                value.renew_ids();
+            }
+
+            if let EnvNodeKind::Open(children) = kind {
+                for child in children {
+                    child.renew_ids();
+                }
+            }
+        }
+    }
+
+    ///
+    /// Text of a text node or a fragment containing only text nodes, 
+    /// e.g. an attribute value without variable expressions.
+    /// 
+    pub fn literal_text(&self) -> Option<String> {
+        match &self.kind {
+            NodeKind::Leaf(LeafNode::Text(text)) => Some(text.clone()),
+            NodeKind::Env(EnvNode { 
+                header: EnvNodeHeader { kind: EnvNodeHeaderKind::Fragment, .. }, 
+                kind: EnvNodeKind::Open(children) 
+            }) => children.iter().map(Node::literal_text).collect(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Replaces all fragments below this node with their children, 
+    /// e.g. the nested fragments left behind by component expansion.
+    /// Fragments in attribute values and the node itself are kept.
+    /// 
+    pub fn flatten_fragments(self) -> Node {
+        match self.kind {
+            NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(children) }) => {
+
+                let mut flattened = VecDeque::with_capacity(children.len());
+
+                for child in children {
+
+                    let child = child.flatten_fragments();
+
+                    match child.kind {
+                        NodeKind::Env(EnvNode { 
+                            header: EnvNodeHeader { kind: EnvNodeHeaderKind::Fragment, .. }, 
+                            kind: EnvNodeKind::Open(grandchildren) 
+                        }) => flattened.extend(grandchildren),
+                        kind => flattened.push_back(Node { kind, ..child }),
+                    }
+                }
+
+                Node { kind: NodeKind::Env(EnvNode::new_open(header, flattened)), ..self }
+            },
+            kind => Node { kind, ..self },
+        }
+    }
 }
 
 impl NodeKind {
@@ -284,12 +396,146 @@ impl NodeKind {
         NodeKind::Env(
             EnvNode {
                 kind: EnvNodeKind::Open(children),
-                header: EnvNodeHeader {
-                    kind: EnvNodeHeaderKind::Heading(level),
-                    attrs: EnvNodeAttrs::new(),
-                }
+                header: EnvNodeHeader::from_kind(EnvNodeHeaderKind::Heading(level), EnvNodeAttrs::new())
             }
         )
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+
+    use crate::document::*;
+    use crate::parse::{parse, parse_with_config, ParseConfig};
+
+    #[test]
+    fn structural_eq() {
+
+        let src = "# Title\n<Section label=\"a\" hidden>Text with $x^2$ and ${var}.<br/></Section>\n/** comment */";
+
+        let (a, _) = parse(src);
+        let (b, _) = parse_with_config(src, &ParseConfig { source: 1, ..Default::default() });
+
+        assert_eq!(a, b);
+        assert_eq!(a, a.clone());
+
+        // attribute order does not matter
+        assert_eq!(parse("<p a=\"1\" b/>").0, parse("<p b a=\"1\"/>").0);
+
+        assert_ne!(parse("<p a=\"1\"/>").0, parse("<p a=\"2\"/>").0);
+        assert_ne!(parse("<p a/>").0, parse("<p a=\"\"/>").0);
+        assert_ne!(parse("<p>a</p>").0, parse("<div>a</div>").0);
+        assert_ne!(parse("<p>a</p><p>b</p>").0, parse("<p>b</p><p>a</p>").0);
+        assert_ne!(parse("<p/>").0, parse("<p></p>").0);
+    }
+
+    #[test]
+    fn mixed_attr_value() {
+
+        let (document, _) = parse("<h1 title=\"Chapter ${n}: ${title}\" id=\"intro\" hidden>Intro</h1>");
+
+        let Some(NodeKind::Env(EnvNode { header, .. })) = document.descendants().next().map(|node| &node.kind) else {
+            panic!("Expected env.");
+        };
+
+        let Some(Some(Node { kind: NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(parts), .. }), .. })) = header.attrs.get("title") else {
+            panic!("Expected a fragment.");
+        };
+
+        let parts = parts
+            .iter()
+            .map(|part| match &part.kind {
+                NodeKind::Leaf(LeafNode::Text(text)) => text.clone(),
+                NodeKind::Leaf(LeafNode::VariableExpression(expr)) => format!("${{{}}}", expr),
+                _ => panic!("Unexpected node."),
+            })
+            .collect::<Vec<String>>();
+
+        assert_eq!(parts, ["Chapter ", "${n}", ": ", "${title}"]);
+
+        assert_eq!(header.attr_text("title"), None);
+        assert_eq!(header.attr_text("id").as_deref(), Some("intro"));
+        assert_eq!(header.attr_text("hidden"), None);
+        assert_eq!(header.attr_text("missing"), None);
+    }
+
+    #[test]
+    fn instantiate() {
+
+        let (document, _) = parse("<Section label=\"${a} b\">Text <b>bold</b></Section>");
+
+        let ids = |node : &Node| {
+
+            let mut ids = Vec::new();
+
+            let mut stack = vec![node];
+
+            while let Some(node) = stack.pop() {
+
+                ids.push(node.id);
+
+                if let NodeKind::Env(EnvNode { header, kind }) = &node.kind {
+
+                    stack.extend(header.attrs.values().flatten());
+
+                    if let EnvNodeKind::Open(children) = kind {
+                        stack.extend(children);
+                    }
+                }
+            }
+
+            ids
+        };
+
+        // clones are the same nodes
+        assert_eq!(ids(&document.clone()), ids(&document));
+
+        let instance = document.instantiate();
+
+        assert_eq!(instance, document);
+        assert_eq!(ids(&instance).len(), ids(&document).len());
+        assert!(ids(&instance).iter().all(|id| !ids(&document).contains(id)));
+    }
+
+    #[test]
+    fn flatten_fragments() {
+
+        use crate::document::visit::{transform, TransformerOnce};
+        use crate::visitors::components::components;
+        use crate::visitors::testing::to_html;
+        use crate::visitors::variables::Variables;
+
+        let (document, _) = parse(
+            "<Component Inner>[${children}]</Component><Component Outer label><p><Inner><b>${label}</b></Inner></p></Component><Outer label=\"x\"/>"
+        );
+
+        let (register, insert) = components();
+
+        let document = transform(
+            document,
+            &mut vec![
+                Box::new(TransformerOnce::new(register)),
+                Box::new(TransformerOnce::new(insert)),
+                Box::new(TransformerOnce::new(Variables::new())),
+            ],
+            1
+        ).unwrap();
+
+        let is_fragment = |node : &Node| matches!(
+            &node.kind, 
+            NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Fragment, .. }, .. })
+        );
+
+        assert!(document.descendants().filter(|node| is_fragment(node)).count() > 1);
+
+        let emit = |document : Node| to_html(document).unwrap();
+
+        let flattened = document.clone().flatten_fragments();
+
+        assert_eq!(flattened.descendants().filter(|node| is_fragment(node)).count(), 0);
+
+        assert_eq!(emit(flattened), "<p>[<b>x</b>]</p>");
+        assert_eq!(emit(document), "<p>[<b>x</b>]</p>");
+    }
+}
@@ -0,0 +1,201 @@
+//!
+//! Serializes document trees to JSON for debugging and external tools, e.g. editor integrations.
+//!
+//! Every node is an object with its id, position, and type:
+//!
+//! {"id":1,"position":{"source":0,"line":1,"col":1,"byte":0,"end":{...}},"type":"env",
+//!  "kind":"Eq","name":"Eq","attrs":{"label":{...}},"children":[...]}
+//!
+//! - position is null for inserted nodes. line and col are 1-based, byte is the 0-based offset.
+//!   end is the position after the node.
+//! - type is one of "env", "text", "variable", "comment", "raw", "error".
+//! - envs have a kind ("Module", "Fragment", "Eq", "Code", "Heading", "Component", "Other"), 
+//!   the name of the element, attributes (null for attributes without a value), 
+//!   and children (null for self-closing envs). 
+//!   Equations have "display" ("inline" or "block"), headings have a "level" (1-6).
+//! - leaves have a value, which is a string for all leaves except "raw", where it is an array of bytes.
+//!
+
+use std::fmt::Write;
+
+use crate::document::*;
+use crate::json::write_string;
+use crate::parse::Span;
+
+fn write_position(out : &mut String, position : &NodePosition) {
+    match position {
+        NodePosition::Source(Span { start, end }) => { 
+            let _ = write!(
+                out,
+                "{{\"source\":{},\"line\":{},\"col\":{},\"byte\":{},\"end\":{{\"line\":{},\"col\":{},\"byte\":{}}}}}",
+                start.source(),
+                start.line() + 1,
+                start.col() + 1,
+                start.byte_idx(),
+                end.line() + 1,
+                end.col() + 1,
+                end.byte_idx()
+            ); 
+        },
+        NodePosition::Inserted => out.push_str("null"),
+    }
+}
+
+fn write_env(out : &mut String, env : &EnvNode) {
+
+    let kind = match &env.header.kind {
+        EnvNodeHeaderKind::Module => "Module",
+        EnvNodeHeaderKind::Fragment => "Fragment",
+        EnvNodeHeaderKind::Eq(_) => "Eq",
+        EnvNodeHeaderKind::Code => "Code",
+        EnvNodeHeaderKind::Heading(_) => "Heading",
+        EnvNodeHeaderKind::ComponentDefinition => "Component",
+        EnvNodeHeaderKind::Other(_) => "Other",
+    };
+
+    let _ = write!(out, "\"type\":\"env\",\"kind\":\"{}\",\"name\":", kind);
+
+    write_string(out, env.header.kind.get_name());
+
+    match &env.header.kind {
+        EnvNodeHeaderKind::Eq(EquationKind::Inline) => out.push_str(",\"display\":\"inline\""),
+        EnvNodeHeaderKind::Eq(EquationKind::Block) => out.push_str(",\"display\":\"block\""),
+        kind => if let Some(level) = kind.heading_level() {
+            let _ = write!(out, ",\"level\":{}", level);
+        },
+    }
+
+    out.push_str(",\"attrs\":{");
+
+    for (i, (key, value)) in env.header.attrs.iter().enumerate() {
+
+        if i > 0 {
+            out.push(',');
+        }
+
+        write_string(out, key);
+
+        out.push(':');
+
+        match value {
+            Some(value) => write_node(out, value),
+            None => out.push_str("null"),
+        }
+    }
+
+    out.push_str("},\"children\":");
+
+    match &env.kind {
+        EnvNodeKind::Open(children) => {
+
+            out.push('[');
+
+            for (i, child) in children.iter().enumerate() {
+
+                if i > 0 {
+                    out.push(',');
+                }
+
+                write_node(out, child);
+            }
+
+            out.push(']');
+        },
+        EnvNodeKind::SelfClosing => out.push_str("null"),
+    }
+}
+
+fn write_leaf(out : &mut String, leaf : &LeafNode) {
+
+    let (kind, value) = match leaf {
+        LeafNode::Text(value) => ("text", value),
+        LeafNode::VariableExpression(value) => ("variable", value),
+        LeafNode::Comment(value) => ("comment", value),
+        LeafNode::Error(value) => ("error", value),
+        LeafNode::RawBytes(bytes) => {
+
+            let bytes = bytes.iter().map(|b| b.to_string()).collect::<Vec<String>>().join(",");
+
+            let _ = write!(out, "\"type\":\"raw\",\"value\":[{}]", bytes);
+
+            return;
+        },
+    };
+
+    let _ = write!(out, "\"type\":\"{}\",\"value\":", kind);
+
+    write_string(out, value);
+}
+
+fn write_node(out : &mut String, node : &Node) {
+
+    let _ = write!(out, "{{\"id\":{},\"position\":", node.id);
+
+    write_position(out, &node.position);
+
+    out.push(',');
+
+    match &node.kind {
+        NodeKind::Env(env) => write_env(out, env),
+        NodeKind::Leaf(leaf) => write_leaf(out, leaf),
+    }
+
+    out.push('}');
+}
+
+impl Node {
+
+    /** Serializes the node and all nodes below it, see the module docs for the format. */
+    pub fn to_json(&self) -> String {
+
+        let mut out = String::new();
+
+        write_node(&mut out, self);
+
+        out
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::document::*;
+    use crate::parse::parse;
+
+    #[test]
+    fn equation() {
+
+        let (document, _) = parse("\n<Eq label=\"e\">x</Eq><br/>");
+
+        let ids = document.descendants().map(|node| node.id).collect::<Vec<NodeId>>();
+
+        let NodeKind::Env(EnvNode { header, .. }) = &document.descendants().nth(1).unwrap().kind else {
+            panic!("Expected env.");
+        };
+
+        let label_id = header.attrs["label"].as_ref().unwrap().id;
+
+        assert_eq!(
+            document.to_json(),
+            format!(
+                concat!(
+                    "{{\"id\":{},\"position\":{{\"source\":0,\"line\":1,\"col\":1,\"byte\":0,\"end\":{{\"line\":2,\"col\":26,\"byte\":26}}}},",
+                    "\"type\":\"env\",\"kind\":\"Module\",\"name\":\"\",\"attrs\":{{}},\"children\":[",
+                    "{{\"id\":{},\"position\":{{\"source\":0,\"line\":1,\"col\":1,\"byte\":0,\"end\":{{\"line\":2,\"col\":1,\"byte\":1}}}},\"type\":\"text\",\"value\":\"\\n\"}},",
+                    "{{\"id\":{},\"position\":{{\"source\":0,\"line\":2,\"col\":1,\"byte\":1,\"end\":{{\"line\":2,\"col\":21,\"byte\":21}}}},",
+                    "\"type\":\"env\",\"kind\":\"Eq\",\"name\":\"Eq\",\"display\":\"block\",\"attrs\":{{",
+                    "\"block\":null,\"label\":{{\"id\":{},\"position\":{{\"source\":0,\"line\":2,\"col\":12,\"byte\":12,\"end\":{{\"line\":2,\"col\":13,\"byte\":13}}}},\"type\":\"text\",\"value\":\"e\"}}",
+                    "}},\"children\":[",
+                    "{{\"id\":{},\"position\":{{\"source\":0,\"line\":2,\"col\":15,\"byte\":15,\"end\":{{\"line\":2,\"col\":16,\"byte\":16}}}},\"type\":\"text\",\"value\":\"x\"}}",
+                    "]}},",
+                    "{{\"id\":{},\"position\":{{\"source\":0,\"line\":2,\"col\":21,\"byte\":21,\"end\":{{\"line\":2,\"col\":26,\"byte\":26}}}},",
+                    "\"type\":\"env\",\"kind\":\"Other\",\"name\":\"br\",\"attrs\":{{}},\"children\":null}}",
+                    "]}}",
+                ),
+                document.id, ids[0], ids[1], label_id, ids[2], ids[3]
+            )
+        );
+    }
+
+}
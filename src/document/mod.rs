@@ -1,5 +1,16 @@
 
 pub mod visit;
+#[allow(clippy::module_inception)]
 mod document;
+mod attrs;
+mod pretty;
+mod query;
+pub mod diff;
+mod json;
+mod builder;
+mod doc;
 pub use document::*;
-
+pub use attrs::{AttrConflict, MergeAttrs, MergePolicy};
+pub use query::Descendants;
+pub use builder::NodeBuilder;
+pub use doc::Document;
@@ -0,0 +1,157 @@
+//!
+//! Human-readable tree dumps for tests and debugging.
+//!
+
+use std::fmt::Write;
+
+use crate::document::*;
+
+/// Text longer than this is truncated in dumps.
+const MAX_TEXT_LEN : usize = 40;
+
+fn snippet(text : &str) -> String {
+
+    match text.char_indices().nth(MAX_TEXT_LEN) {
+        Some((end, _)) => format!("{:?}...", &text[..end]),
+        None => format!("{:?}", text),
+    }
+}
+
+fn write_attr_value(value : &Node, out : &mut String) {
+    match &value.kind {
+        NodeKind::Leaf(LeafNode::Text(text)) => out.push_str(&snippet(text)),
+        NodeKind::Leaf(LeafNode::VariableExpression(expr)) => { let _ = write!(out, "${{{}}}", expr); },
+        NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => {
+            out.push('(');
+
+            for (i, child) in children.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+
+                write_attr_value(child, out);
+            }
+
+            out.push(')');
+        },
+        _ => out.push_str("..."),
+    }
+}
+
+fn write_node(node : &Node, depth : usize, ids : bool, out : &mut String) {
+
+    out.push_str(&"  ".repeat(depth));
+
+    match &node.kind {
+        NodeKind::Leaf(leaf) => match leaf {
+            LeafNode::Text(text) => out.push_str(&snippet(text)),
+            LeafNode::VariableExpression(expr) => { let _ = write!(out, "${{{}}}", expr); },
+            LeafNode::Comment(comment) => { let _ = write!(out, "/** {} */", snippet(comment)); },
+            LeafNode::RawBytes(bytes) => { let _ = write!(out, "<{} raw bytes>", bytes.len()); },
+            LeafNode::Error(message) => { let _ = write!(out, "error: {}", message); },
+        },
+        NodeKind::Env(EnvNode { header, .. }) => {
+
+            let name = match header.kind {
+                EnvNodeHeaderKind::Module => "Module",
+                EnvNodeHeaderKind::Fragment => "Fragment",
+                _ => header.kind.get_name(),
+            };
+
+            out.push('<');
+            out.push_str(name);
+
+            for (key, value) in &header.attrs {
+                out.push(' ');
+                out.push_str(key);
+
+                if let Some(value) = value {
+                    out.push('=');
+                    write_attr_value(value, out);
+                }
+            }
+
+            out.push('>');
+        },
+    }
+
+    if ids {
+        let _ = write!(out, " #{}", node.id);
+    }
+
+    out.push('\n');
+
+    if let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = &node.kind {
+        for child in children {
+            write_node(child, depth + 1, ids, out);
+        }
+    }
+}
+
+impl Node {
+
+    ///
+    /// Renders an indented tree with one node per line.
+    /// Env nodes are shown with their attributes, text is quoted and truncated.
+    /// 
+    pub fn to_pretty_string(&self) -> String {
+
+        let mut out = String::new();
+
+        write_node(self, 0, false, &mut out);
+
+        out
+    }
+
+    /** Same as to_pretty_string but appends the id of each node. */
+    pub fn to_pretty_string_with_ids(&self) -> String {
+
+        let mut out = String::new();
+
+        write_node(self, 0, true, &mut out);
+
+        out
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::document::*;
+    use crate::parse::parse;
+
+    #[test]
+    fn pretty_string() {
+
+        let (document, _) = parse(concat!(
+            "<Section label=\"intro\" hidden>\n",
+            "    Hello ${name}! /** greeting */\n",
+            "    <img src=\"a.png\"/>\n",
+            "</Section>",
+        ));
+
+        assert_eq!(
+            document.to_pretty_string(),
+            concat!(
+                "<Module>\n",
+                "  <Section label=\"intro\" hidden>\n",
+                "    \"\\n    Hello \"\n",
+                "    ${name}\n",
+                "    \"! \"\n",
+                "    /** \" greeting \" */\n",
+                "    \"\\n    \"\n",
+                "    <img src=\"a.png\">\n",
+                "    \"\\n\"\n",
+            )
+        );
+
+        let text = Node::new(NodeKind::Leaf(LeafNode::Text("a".repeat(50))), NodePosition::Inserted);
+
+        assert_eq!(
+            text.to_pretty_string_with_ids(), 
+            format!("\"{}\"... #{}\n", "a".repeat(40), text.id)
+        );
+    }
+
+}
@@ -0,0 +1,143 @@
+//!
+//! Read-only queries on document trees.
+//!
+//! All queries search the node itself and its descendants in document order.
+//!
+
+use std::collections::VecDeque;
+
+use crate::document::*;
+
+///
+/// Iterator over all descendants of a node in document order (depth-first, pre-order).
+///
+pub struct Descendants<'a> {
+    /** Nodes left to visit, the next node is on top. */
+    stack: Vec<&'a Node>,
+}
+
+fn children(node : &Node) -> Option<&VecDeque<Node>> {
+    match &node.kind {
+        NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => Some(children),
+        _ => None,
+    }
+}
+
+impl<'a> Iterator for Descendants<'a> {
+
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        let node = self.stack.pop()?;
+
+        if let Some(children) = children(node) {
+            self.stack.extend(children.iter().rev());
+        }
+
+        Some(node)
+    }
+
+}
+
+impl Node {
+
+    /** Iterates over all nodes below this node. */
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants {
+            stack: children(self).map_or_else(Vec::new, |children| children.iter().rev().collect()),
+        }
+    }
+
+    /** Iterates over this node and all nodes below it. */
+    fn self_and_descendants(&self) -> impl Iterator<Item = &Node> {
+        std::iter::once(self).chain(self.descendants())
+    }
+
+    ///
+    /// Finds all env nodes with the given name, e.g. "Figure" or "h2".
+    ///
+    pub fn find_by_name(&self, name : &str) -> Vec<&Node> {
+        self.self_and_descendants()
+            .filter(|node| matches!(
+                &node.kind,
+                NodeKind::Env(EnvNode { header, .. }) if header.kind.get_name() == name
+            ))
+            .collect()
+    }
+
+    ///
+    /// Finds all env nodes with the attribute key.
+    /// If value is provided, the attribute must have that text as its value.
+    ///
+    pub fn find_by_attr(&self, key : &str, value : Option<&str>) -> Vec<&Node> {
+        self.self_and_descendants()
+            .filter(|node| match &node.kind {
+                NodeKind::Env(EnvNode { header, .. }) => match (header.attrs.get(key), value) {
+                    (Some(_), None) => true,
+                    (Some(Some(Node { kind: NodeKind::Leaf(LeafNode::Text(text)), .. })), Some(value)) => text == value,
+                    _ => false,
+                },
+                _ => false,
+            })
+            .collect()
+    }
+
+    /** Finds the node with the given id. */
+    pub fn find_by_id(&self, id : NodeId) -> Option<&Node> {
+        self.self_and_descendants().find(|node| node.id == id)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::document::*;
+    use crate::parse::parse;
+    use crate::visitors::toc::text_content;
+
+    const SRC : &str = concat!(
+        "<Section label=\"a\">",
+        "<Figure label=\"fig\">x</Figure>",
+        "<Section hidden><Figure>y</Figure></Section>",
+        "</Section>",
+    );
+
+    #[test]
+    fn descendants() {
+
+        let (document, _) = parse(SRC);
+
+        let names = document
+            .descendants()
+            .map(|node| match &node.kind {
+                NodeKind::Env(EnvNode { header, .. }) => header.kind.get_name().to_string(),
+                _ => "text".to_string(),
+            })
+            .collect::<Vec<String>>();
+
+        assert_eq!(names, ["Section", "Figure", "text", "Section", "Figure", "text"]);
+    }
+
+    #[test]
+    fn find() {
+
+        let (document, _) = parse(SRC);
+
+        let figures = document.find_by_name("Figure");
+
+        assert_eq!(figures.iter().map(|node| text_content(node)).collect::<Vec<String>>(), ["x", "y"]);
+
+        assert_eq!(document.find_by_attr("label", None).len(), 2);
+        assert_eq!(text_content(document.find_by_attr("label", Some("fig"))[0]), "x");
+        assert_eq!(document.find_by_attr("hidden", Some("true")).len(), 0);
+
+        let id = figures[1].id;
+
+        assert_eq!(document.find_by_id(id).map(|node| node.id), Some(id));
+        assert_eq!(document.find_by_id(document.id).map(|node| node.id), Some(document.id));
+        assert!(figures[0].find_by_id(id).is_none());
+    }
+
+}
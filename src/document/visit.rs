@@ -1,15 +1,141 @@
 
+use core::fmt;
 use std::collections::{HashSet, VecDeque};
 
 use crate::document::*;
+use crate::error::Diagnostic;
 
 #[derive(Debug)]
 pub enum VisitError {
     Unknown(String),
     RootRemoved,
     MaxIterationsReached,
+    NodeNotFound(NodeId),
+    /// An emitter has reached its maximum output size (in bytes).
+    OutputLimitExceeded(usize),
+    /// Error that occurred within the children of an env.
+    InEnv {
+        name: String,
+        position: NodePosition,
+        error: Box<VisitError>,
+    },
+    /// Error returned by a transformer when entering a node.
+    AtNode {
+        node_id: NodeId,
+        position: NodePosition,
+        /** Index of the transformer in the list passed to transform. */
+        transformer: Option<usize>,
+        error: Box<VisitError>,
+    },
 }
 
+impl VisitError {
+
+    ///
+    /// Wraps the error with the env it occurred in.
+    /// Modules and fragments do not add any context.
+    /// 
+    pub fn context(self, header : &EnvNodeHeader, position : &NodePosition) -> Self {
+        match header.kind {
+            EnvNodeHeaderKind::Module | EnvNodeHeaderKind::Fragment => self,
+            _ => VisitError::InEnv {
+                name: header.kind.get_name().to_string(),
+                position: position.clone(),
+                error: Box::new(self),
+            }
+        }
+    }
+
+    ///
+    /// Wraps the error with the node it occurred at.
+    /// 
+    pub fn at(self, node_id : NodeId, position : &NodePosition) -> Self {
+        VisitError::AtNode {
+            node_id,
+            position: position.clone(),
+            transformer: None,
+            error: Box::new(self),
+        }
+    }
+
+    /// Returns the innermost error, skipping any context.
+    pub fn root_cause(&self) -> &VisitError {
+        match self {
+            VisitError::InEnv { error, .. } | VisitError::AtNode { error, .. } => error.root_cause(),
+            _ => self,
+        }
+    }
+
+    /** Innermost node context of the error. */
+    fn innermost_node(&self) -> Option<&VisitError> {
+        match self {
+            VisitError::InEnv { error, .. } => error.innermost_node(),
+            VisitError::AtNode { error, .. } => error.innermost_node().or(Some(self)),
+            _ => None,
+        }
+    }
+
+    /** Id of the node at which the error occurred. */
+    pub fn node_id(&self) -> Option<NodeId> {
+        match self.innermost_node() {
+            Some(VisitError::AtNode { node_id, .. }) => Some(*node_id),
+            _ => None,
+        }
+    }
+
+    /** Index of the transformer that returned the error. */
+    pub fn transformer(&self) -> Option<usize> {
+        match self.innermost_node() {
+            Some(VisitError::AtNode { transformer, .. }) => *transformer,
+            _ => None,
+        }
+    }
+
+    /** Sets the transformer index of all node contexts that do not have one yet. */
+    fn in_transformer(self, index : usize) -> Self {
+        match self {
+            VisitError::InEnv { name, position, error } => VisitError::InEnv { 
+                name, 
+                position, 
+                error: Box::new(error.in_transformer(index)) 
+            },
+            VisitError::AtNode { node_id, position, transformer, error } => VisitError::AtNode {
+                node_id,
+                position,
+                transformer: transformer.or(Some(index)),
+                error: Box::new(error.in_transformer(index)),
+            },
+            error => error,
+        }
+    }
+
+}
+
+impl fmt::Display for VisitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VisitError::Unknown(message) => write!(f, "{}", message),
+            VisitError::RootRemoved => write!(f, "The root node has been removed by a transformer."),
+            VisitError::MaxIterationsReached => write!(f, "Maximum number of transform passes reached."),
+            VisitError::NodeNotFound(id) => write!(f, "Node with id {} not found.", id),
+            VisitError::OutputLimitExceeded(max_bytes) => write!(f, "Maximum output size of {} bytes exceeded.", max_bytes),
+            VisitError::InEnv { name, position, error } => match position {
+                NodePosition::Source(span) => write!(f, "in <{}> at {}: {}", name, span.start, error),
+                NodePosition::Inserted => write!(f, "in <{}>: {}", name, error),
+            },
+            // the position is omitted if the inner error already has one
+            VisitError::AtNode { position, error, .. } => match (position, error.as_ref()) {
+                (_, VisitError::InEnv { .. } | VisitError::AtNode { .. }) | (NodePosition::Inserted, _) => {
+                    write!(f, "{}", error)
+                },
+                (NodePosition::Source(span), _) => write!(f, "at {}: {}", span.start, error),
+            },
+        }
+    }
+}
+
+impl std::error::Error for VisitError {}
+
 
 pub enum ActionKind {
     Remove,
@@ -63,6 +189,32 @@ pub trait Visitor {
     fn leave(&mut self, _node : &Node, _original_id : NodeId, _parent_id : Option<NodeId>) {
         
     }
+
+    //
+    // Returns the warnings and recoverable errors found since the last call.
+    //
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+}
+
+///
+/// Allows passing a borrowed visitor to transform in order to 
+/// inspect its state after the transform.
+/// 
+impl<V : Visitor + ?Sized> Visitor for &mut V {
+
+    fn enter(&mut self, node : Node, parent_id : Option<NodeId>) -> TransformResult {
+        (**self).enter(node, parent_id)
+    }
+
+    fn leave(&mut self, node : &Node, original_id : NodeId, parent_id : Option<NodeId>) {
+        (**self).leave(node, original_id, parent_id)
+    }
+
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        (**self).take_diagnostics()
+    }
 }
 
 pub struct TransformerOnce<T : Visitor> {
@@ -90,6 +242,10 @@ impl<T: Visitor> Visitor for TransformerOnce<T> {
         }
     }
 
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.transformer.take_diagnostics()
+    }
+
 }
 
 impl<T : Visitor> TransformerOnce<T> {
@@ -140,20 +296,25 @@ impl Action {
 
 }
 
-fn transform_node_single_pass(
+fn transform_node_single_pass<V : Visitor + ?Sized>(
     node : Node,
     parent_id : Option<NodeId>,
-    transformer : &mut Box<dyn Visitor>
+    transformer : &mut V
 ) -> TransformResult {
 
     let original_id = node.id;
 
-    let transform_action = transformer.enter(node, parent_id)?;
+    let position = node.position.clone();
 
-    match &transform_action.kind {
-        ActionKind::Remove => return Ok(transform_action),
-        _ => {}
-    };
+    let transform_action = transformer.enter(node, parent_id).map_err(
+        |error| error.at(original_id, &position)
+    )?;
+
+    if let ActionKind::Remove = &transform_action.kind {
+        return Ok(transform_action);
+    }
+
+    let replaced = matches!(transform_action.kind, ActionKind::Replace);
 
     let transform_action = match transform_action.node {
         // TODO: tidy up NodeKind: split into Leaf (no children) and NonLeaf (with children) to avoid this
@@ -163,7 +324,8 @@ fn transform_node_single_pass(
             position
         } => {
             
-            let mut has_changed = false;
+            // a node replaced in enter stays replaced even if its children are kept
+            let mut has_changed = replaced;
 
             let children = children
                 .into_iter()
@@ -174,7 +336,8 @@ fn transform_node_single_pass(
                         transformer
                     )
                 )
-                .collect::<Result<Vec<Action>, VisitError>>()?
+                .collect::<Result<Vec<Action>, VisitError>>()
+                .map_err(|error| error.context(&header, &position))?
                 .into_iter()
                 // remove children whose transform returned ActionKind::remove
                 .filter(
@@ -204,49 +367,200 @@ fn transform_node_single_pass(
 }
 
 ///
-/// Transforms the tree until all transformers return Action::keep
-/// or max_passes is reached.
+/// Safety cap of transform_to_fixpoint. 
+/// Well-behaved pipelines (e.g. transformers wrapped in TransformerOnce) converge after a few passes.
+///
+pub const FIXPOINT_MAX_PASSES : u32 = 64;
+
+///
+/// Transforms the tree in passes, each running all transformers in order, 
+/// until a full pass leaves every node unchanged (all transformers return Action::keep).
+/// 
+/// Errors with VisitError::MaxIterationsReached if the tree still changes
+/// in the pass after max_passes passes have changed it.
 /// 
 pub fn transform(
     node : Node,
-    transformers : &mut Vec<Box<dyn Visitor>>,
+    transformers : &mut Vec<Box<dyn Visitor + '_>>,
+    max_passes : u32
+) -> Result<Node, VisitError> {
+
+    transform_slice(node, transformers.as_mut_slice(), max_passes)
+}
+
+///
+/// Same as transform with a single visitor, which is called without dynamic dispatch.
+/// Useful for running one visitor over a large tree, e.g. an emitter.
+/// 
+pub fn transform_one<V : Visitor>(
+    node : Node,
+    visitor : &mut V,
+    max_passes : u32
+) -> Result<Node, VisitError> {
+
+    let mut node = node;
+
+    let mut iterations : u32 = 0;
+
+    loop {
+        let action = transform_node_single_pass(node, None, visitor)
+            .map_err(|error| error.in_transformer(0))?;
+
+        match action.kind {
+            ActionKind::Keep => return Ok(action.node),
+            ActionKind::Replace => node = action.node,
+            ActionKind::Remove => return Err(VisitError::RootRemoved),
+        }
+
+        iterations += 1;
+
+        if iterations > max_passes {
+            return Err(VisitError::MaxIterationsReached)
+        }
+    }
+}
+
+///
+/// Transforms the tree until it does not change anymore, see transform.
+/// Errors if the tree is still changing after FIXPOINT_MAX_PASSES passes.
+/// 
+pub fn transform_to_fixpoint(
+    node : Node,
+    transformers : &mut [Box<dyn Visitor + '_>],
+) -> Result<Node, VisitError> {
+
+    transform_slice(node, transformers, FIXPOINT_MAX_PASSES)
+}
+
+///
+/// Same as transform but borrows the transformers as a slice.
+/// This allows re-using a stored array of transformers without moving it into a Vec.
+/// 
+pub fn transform_slice(
+    node : Node,
+    transformers : &mut [Box<dyn Visitor + '_>],
+    max_passes : u32
+) -> Result<Node, VisitError> {
+
+    transform_with_parent(node, None, transformers, max_passes)
+}
+
+fn transform_with_parent(
+    node : Node,
+    parent_id : Option<NodeId>,
+    transformers : &mut [Box<dyn Visitor + '_>],
     max_passes : u32
 ) -> Result<Node, VisitError> {
 
-    let mut action = Action::replace(node);
+    if transformers.is_empty() {
+        return Ok(node);
+    }
+
+    let mut node = node;
 
     let mut iterations : u32 = 0;
 
     loop {
-        for transformer in transformers.iter_mut() {
+        // the pass changed the tree if any of the transformers did, not just the last one
+        let mut changed = false;
+
+        for (index, transformer) in transformers.iter_mut().enumerate() {
             
-            action = match &action.kind {
-                ActionKind::Keep | ActionKind::Replace => transform_node_single_pass(
-                    action.node, 
-                    None,
-                    transformer
-                )?,
+            let action = transform_node_single_pass(node, parent_id, transformer.as_mut())
+                .map_err(|error| error.in_transformer(index))?;
+
+            match action.kind {
+                ActionKind::Keep => { },
+                ActionKind::Replace => changed = true,
                 ActionKind::Remove => return Err(VisitError::RootRemoved),
             }
 
+            node = action.node;
         }
 
-        match &action.kind  {
-            ActionKind::Keep => {
-                return Ok(action.node)
-            },
-            _ => {
-                iterations += 1;
+        if !changed {
+            return Ok(node);
+        }
 
-                if iterations > max_passes {
-                    return Err(VisitError::MaxIterationsReached)
-                }
-            }
+        iterations += 1;
+
+        if iterations > max_passes {
+            return Err(VisitError::MaxIterationsReached)
         }
     }
 }
 
-pub struct DefaultTransformer;
+///
+/// Finds the children list containing the node with target_id.
+/// Returns the id of the parent, its children, and the index of the target.
+/// 
+fn find_in_children(node : &mut Node, target_id : NodeId) -> Option<(NodeId, &mut VecDeque<Node>, usize)> {
+
+    let id = node.id;
+
+    match &mut node.kind {
+        NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => {
+
+            if let Some(index) = children.iter().position(|child| child.id == target_id) {
+                return Some((id, children, index));
+            }
+
+            children
+                .iter_mut()
+                .find_map(|child| find_in_children(child, target_id))
+        },
+        _ => None
+    }
+}
+
+///
+/// Transforms only the subtree rooted at the node with target_id and splices the result back into root.
+/// Transformers see the actual parent of the subtree root as its parent_id.
+/// If a transformer removes the subtree root, it is removed from its parent.
+/// 
+/// On error, the tree is left as it was.
+/// 
+pub fn transform_subtree(
+    root : &mut Node,
+    target_id : NodeId,
+    transformers : &mut [Box<dyn Visitor + '_>],
+    max_passes : u32
+) -> Result<(), VisitError> {
+
+    // the transform consumes the subtree, so it runs on a copy that only replaces the original on success
+    if root.id == target_id {
+
+        *root = transform_with_parent(root.clone(), None, transformers, max_passes)?;
+
+        return Ok(());
+    }
+
+    let (parent_id, children, index) = find_in_children(root, target_id).ok_or(
+        VisitError::NodeNotFound(target_id)
+    )?;
+
+    match transform_with_parent(children[index].clone(), Some(parent_id), transformers, max_passes) {
+        Ok(node) => children[index] = node,
+        Err(VisitError::RootRemoved) => { children.remove(index); },
+        Err(error) => return Err(error),
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct DefaultTransformer {
+    /** Keeps comments instead of removing them. */
+    pub keep_comments: bool,
+}
+
+impl DefaultTransformer {
+
+    pub fn new(keep_comments : bool) -> Self {
+        Self { keep_comments }
+    }
+
+}
 
 // default transformer that is always active
 impl Visitor for DefaultTransformer {
@@ -254,7 +568,7 @@ impl Visitor for DefaultTransformer {
     fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
 
         match &node.kind {
-            NodeKind::Leaf(LeafNode::Comment(_)) => Ok(Action::remove(node)),
+            NodeKind::Leaf(LeafNode::Comment(_)) if !self.keep_comments => Ok(Action::remove(node)),
             _ => Ok(Action::keep(node))
         }
 
@@ -283,25 +597,27 @@ mod test {
                         kind: EnvNodeKind::Open(children) 
                     }
                 ) => {
-                    // TODO: unwrap
-                    // TODO: check that only one child exists
-                    let child = children.get(0).unwrap();
-
-                    if let NodeKind::Leaf(LeafNode::Text(text)) = &child.kind {
-                        let raw_node = Node {
-                            kind: NodeKind::Leaf(LeafNode::Text(
-                                match equation_kind {
-                                    EquationKind::Block => format!("<p><pre>{}</pre></p>", text),
-                                    EquationKind::Inline => format!("<pre>{}</pre>", text),
-                                },
-                            )),
-                            ..node
-                        };
-
-                        Ok(Action::replace(raw_node))
-                    } else {
-                        Ok(Action::remove(node))
+                    // the contents are raw, but may be split into multiple text nodes
+                    let text = children.iter().filter_map(|child| match &child.kind {
+                        NodeKind::Leaf(LeafNode::Text(text)) => Some(text.as_str()),
+                        _ => None,
+                    }).collect::<String>();
+
+                    if text.is_empty() {
+                        return Ok(Action::remove(node));
                     }
+
+                    let raw_node = Node {
+                        kind: NodeKind::Leaf(LeafNode::Text(
+                            match equation_kind {
+                                EquationKind::Block => format!("<p><pre>{}</pre></p>", text),
+                                EquationKind::Inline => format!("<pre>{}</pre>", text),
+                            },
+                        )),
+                        ..node
+                    };
+
+                    Ok(Action::replace(raw_node))
                 },
                 _ => Ok(Action::keep(node))
             }
@@ -332,9 +648,9 @@ mod test {
             "#
         );
 
-        let document = transform(
+        let _document = transform(
             document, 
-            &mut vec![Box::new(DefaultTransformer), Box::new(EquationTransformer)],
+            &mut vec![Box::new(DefaultTransformer::default()), Box::new(EquationTransformer)],
             3
         ).unwrap();
 
@@ -345,7 +661,7 @@ mod test {
         let mut collected_bytes = Vec::new();
 
         // Create a closure that appends bytes to the Vec<u8>
-        let mut collect_closure = |bytes: &[u8]| {
+        let _collect_closure = |bytes: &[u8]| {
             collected_bytes.extend_from_slice(bytes);
         };
 
@@ -355,4 +671,338 @@ mod test {
 
     }
 
+    struct FailingTransformer;
+
+    // fails on any text containing "boom"
+    impl Visitor for FailingTransformer {
+
+        fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+            match &node.kind {
+                NodeKind::Leaf(LeafNode::Text(text)) if text.contains("boom") => Err(
+                    VisitError::Unknown("Encountered boom.".to_string())
+                ),
+                _ => Ok(Action::keep(node))
+            }
+        }
+    }
+
+    #[test]
+    fn error_context() {
+
+        let (document, _) = parse::parse(
+            "Intro\n<Chapter>\n  <>\n    <Section>boom</Section>\n  </>\n</Chapter>\n"
+        );
+
+        let text_id = document.descendants().find(
+            |node| matches!(&node.kind, NodeKind::Leaf(LeafNode::Text(text)) if text == "boom")
+        ).unwrap().id;
+
+        let error = transform(
+            document, 
+            &mut vec![Box::new(DefaultTransformer::default()), Box::new(FailingTransformer)], 
+            1
+        ).unwrap_err();
+
+        // modules and fragments do not show up in the chain
+        assert_eq!(
+            error.to_string(),
+            "in <Chapter> at 2:1: in <Section> at 4:5: at 4:14: Encountered boom."
+        );
+
+        assert!(matches!(error.root_cause(), VisitError::Unknown(_)));
+
+        assert_eq!(error.node_id(), Some(text_id));
+        assert_eq!(error.transformer(), Some(1));
+
+        assert_eq!(VisitError::MaxIterationsReached.node_id(), None);
+    }
+
+    /// Upper-cases all text and records the parent of every entered node.
+    struct UpperCase {
+        parents: Vec<Option<NodeId>>,
+    }
+
+    impl Visitor for UpperCase {
+
+        fn enter(&mut self, node : Node, parent_id : Option<NodeId>) -> TransformResult {
+
+            self.parents.push(parent_id);
+
+            match &node.kind {
+                NodeKind::Leaf(LeafNode::Text(text)) if text.chars().any(char::is_lowercase) => Ok(Action::replace(Node {
+                    kind: NodeKind::Leaf(LeafNode::Text(text.to_uppercase())),
+                    ..node
+                })),
+                _ => Ok(Action::keep(node))
+            }
+        }
+    }
+
+    #[test]
+    fn transform_only_subtree() {
+
+        let (mut document, _) = parse::parse("<Section>first</Section><Section>second</Section>");
+
+        let sections = match &document.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children
+                .iter()
+                .map(|child| child.id)
+                .collect::<Vec<NodeId>>(),
+            _ => panic!("Expected module."),
+        };
+
+        let mut upper_case = UpperCase { parents: Vec::new() };
+
+        transform_subtree(
+            &mut document,
+            sections[1],
+            &mut [Box::new(&mut upper_case) as Box<dyn Visitor>],
+            1
+        ).unwrap();
+
+        // the subtree root is entered with its actual parent
+        assert_eq!(upper_case.parents[0], Some(document.id));
+        assert_eq!(upper_case.parents[1], Some(sections[1]));
+
+        let texts = match &document.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children
+                .iter()
+                .map(|child| match &child.kind {
+                    NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => match &children[0].kind {
+                        NodeKind::Leaf(LeafNode::Text(text)) => text.clone(),
+                        _ => panic!("Expected text."),
+                    },
+                    _ => panic!("Expected section."),
+                })
+                .collect::<Vec<String>>(),
+            _ => panic!("Expected module."),
+        };
+
+        // siblings are untouched
+        assert_eq!(texts, ["first", "SECOND"]);
+
+        assert!(matches!(
+            transform_subtree(&mut document, usize::MAX, &mut [Box::new(&mut upper_case) as Box<dyn Visitor>], 1),
+            Err(VisitError::NodeNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn transform_subtree_errors() {
+
+        /// Fails on every text node.
+        struct Fail;
+
+        impl Visitor for Fail {
+            fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+                match &node.kind {
+                    NodeKind::Leaf(LeafNode::Text(_)) => Err(VisitError::Unknown("boom".to_string())),
+                    _ => Ok(Action::keep(node)),
+                }
+            }
+        }
+
+        /// Removes every node.
+        struct Remove;
+
+        impl Visitor for Remove {
+            fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+                Ok(Action::remove(node))
+            }
+        }
+
+        let (mut document, _) = parse::parse("<Section>first</Section><Section>second</Section>");
+
+        let original = document.clone();
+
+        let section = match &document.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children[1].id,
+            _ => panic!("Expected module."),
+        };
+
+        // the root is restored on errors
+        assert!(transform_subtree(&mut document, original.id, &mut [Box::new(Fail)], 1).is_err());
+        assert_eq!(document, original);
+
+        assert!(matches!(
+            transform_subtree(&mut document, original.id, &mut [Box::new(Remove)], 1),
+            Err(VisitError::RootRemoved)
+        ));
+        assert_eq!(document, original);
+
+        // so is a subtree below the root
+        assert!(transform_subtree(&mut document, section, &mut [Box::new(Fail)], 1).is_err());
+        assert_eq!(document, original);
+
+        // removing the subtree root is not an error
+        transform_subtree(&mut document, section, &mut [Box::new(Remove)], 1).unwrap();
+
+        match &document.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => assert_eq!(children.len(), 1),
+            _ => panic!("Expected module."),
+        }
+    }
+
+    #[test]
+    fn transform_single_visitor() {
+
+        let src = "<Eq>\n    e = mc^2\n</Eq>\nInline $x^2$ equation.\n";
+
+        let generic = transform_one(parse::parse(src).0, &mut EquationTransformer, 3).unwrap();
+        let dynamic = transform(parse::parse(src).0, &mut vec![Box::new(EquationTransformer)], 3).unwrap();
+
+        assert_eq!(generic, dynamic);
+        assert_ne!(generic, parse::parse(src).0);
+
+        // errors are reported the same way
+        let error = transform_one(parse::parse("<Chapter>boom</Chapter>").0, &mut FailingTransformer, 1).unwrap_err();
+
+        assert_eq!(error.transformer(), Some(0));
+    }
+
+    #[test]
+    fn transform_with_slice() {
+
+        let mut transformers : [Box<dyn Visitor>; 2] = [
+            Box::new(DefaultTransformer::default()), 
+            Box::new(EquationTransformer)
+        ];
+
+        // the same transformers can be used on multiple documents
+        for src in ["<Eq>\n    e = mc^2\n</Eq>\n", "Inline $x^2$ equation.\n"] {
+
+            let (document, _) = parse::parse(src);
+
+            let document = transform_slice(document, &mut transformers, 3).unwrap();
+
+            let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = &document.kind else {
+                panic!("Expected module node.");
+            };
+
+            assert!(
+                children.iter().all(|child| matches!(&child.kind, NodeKind::Leaf(LeafNode::Text(_))))
+            );
+        }
+    }
+
+    #[test]
+    fn equation_contents() {
+
+        let texts = |src : &str| {
+
+            let document = transform_one(parse::parse(src).0, &mut EquationTransformer, 3).unwrap();
+
+            let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = document.kind else {
+                panic!("Expected module node.");
+            };
+
+            children.into_iter().map(|child| match child.kind {
+                NodeKind::Leaf(LeafNode::Text(text)) => text,
+                kind => panic!("Expected text, found {:?}.", kind),
+            }).collect::<Vec<String>>()
+        };
+
+        // empty equations are removed
+        assert_eq!(texts("<Eq></Eq>"), Vec::<String>::new());
+        assert_eq!(texts("a $$$$ b"), ["a ", " b"]);
+
+        assert_eq!(texts("$$a ${x} b$$"), ["<p><pre>a ${x} b</pre></p>"]);
+
+        // inserted children are joined
+        let (mut document, _) = parse::parse("$x$");
+
+        if let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = &mut document.kind {
+            if let Some(NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(math), .. })) = children.front_mut().map(|eq| &mut eq.kind) {
+                math.push_back(Node::new(NodeKind::Leaf(LeafNode::Text(" + y".to_string())), NodePosition::Inserted));
+            }
+        }
+
+        let document = transform_one(document, &mut EquationTransformer, 3).unwrap();
+
+        assert_eq!(
+            document,
+            Node::new(NodeKind::Env(EnvNode::new_module(VecDeque::from([
+                Node::new(NodeKind::Leaf(LeafNode::Text("<pre>x + y</pre>".to_string())), NodePosition::Inserted)
+            ]))), NodePosition::Inserted)
+        );
+    }
+
+    #[test]
+    fn fixpoint() {
+
+        use crate::visitors::components::components;
+        use crate::visitors::testing::to_html;
+        use crate::visitors::variables::Variables;
+
+        /// Never changes anything, so the last transformer alone does not tell whether a pass changed the tree.
+        struct Keep;
+
+        impl Visitor for Keep {
+            fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+                Ok(Action::keep(node))
+            }
+        }
+
+        /// Changes every node in every pass.
+        struct Replace;
+
+        impl Visitor for Replace {
+            fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+                Ok(Action::replace(node))
+            }
+        }
+
+        let (document, _) = parse::parse(
+            r#"<Component Greeting name><p>Hello ${name}</p></Component><Greeting name="you"/>"#
+        );
+
+        let (register, insert) = components();
+
+        // Variables runs before the component is inserted, so the variables it introduces are resolved in the second pass
+        let document = transform_to_fixpoint(
+            document, 
+            &mut [Box::new(register), Box::new(Variables::new()), Box::new(insert), Box::new(Keep)]
+        ).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, "<p>Hello you</p>");
+
+        /// Renames envs in enter, which leaves their children unchanged.
+        struct Rename(&'static str, &'static str);
+
+        impl Visitor for Rename {
+            fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+                match node.kind {
+                    NodeKind::Env(mut env) if env.header.kind.get_name() == self.0 => {
+
+                        env.header.rename(self.1);
+
+                        Ok(Action::replace(Node { kind: NodeKind::Env(env), ..node }))
+                    },
+                    kind => Ok(Action::keep(Node { kind, ..node })),
+                }
+            }
+        }
+
+        // the rename to y is only picked up by the first transformer in the next pass
+        let (document, _) = parse::parse("<x>t</x>");
+
+        let document = transform_to_fixpoint(
+            document, 
+            &mut [Box::new(Rename("y", "z")), Box::new(Rename("x", "y"))]
+        ).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, "<z>t</z>");
+
+        let (document, _) = parse::parse("text");
+
+        assert!(matches!(
+            transform_to_fixpoint(document, &mut [Box::new(Replace)]),
+            Err(VisitError::MaxIterationsReached)
+        ));
+    }
+
 }
@@ -0,0 +1,227 @@
+
+use core::fmt;
+
+use crate::document::NodePosition;
+use crate::document::visit::VisitError;
+use crate::parse::{ParserPosition, Token, TokenKind, TokenStorage};
+use crate::parse::error::ParseError;
+
+///
+/// Errors that may occur on the way from source to document.
+/// Parse errors borrow the source in order to display the offending line.
+///
+#[derive(Debug)]
+pub enum Error<'a> {
+    Parse {
+        src: &'a str,
+        error: ParseError,
+        position: ParserPosition,
+    },
+    Visit(VisitError),
+}
+
+impl<'a> Error<'a> {
+
+    ///
+    /// Creates a parse error from an error token emitted by the parser.
+    /// Returns None if the token is not an error token.
+    ///
+    pub fn from_token(src : &'a str, token : &Token<'a>) -> Option<Self> {
+        match &token.kind {
+            TokenKind::Error(error) => Some(Error::Parse {
+                src,
+                error: error.clone(),
+                position: token.position,
+            }),
+            _ => None
+        }
+    }
+
+}
+
+impl From<VisitError> for Error<'_> {
+    fn from(error: VisitError) -> Self {
+        Error::Visit(error)
+    }
+}
+
+impl fmt::Display for Error<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse { src, error, position } => {
+
+                let line = position.line();
+                let col = position.col();
+
+                write!(f, "{}: {}", position, error)?;
+
+                // show the offending line with a marker below the failing column
+                if let Some(snippet) = src.lines().nth(line) {
+                    write!(f, "\n{}\n{}^", snippet, " ".repeat(col))?;
+                }
+
+                Ok(())
+            },
+            Error::Visit(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+///
+/// A problem found while rendering a document.
+/// Unlike Error, diagnostics own their data so they can be collected and reported later.
+///
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub position: NodePosition,
+}
+
+impl Diagnostic {
+
+    pub fn error(message : impl Into<String>, position : NodePosition) -> Self {
+        Self { severity: Severity::Error, message: message.into(), position }
+    }
+
+    pub fn warning(message : impl Into<String>, position : NodePosition) -> Self {
+        Self { severity: Severity::Warning, message: message.into(), position }
+    }
+
+    ///
+    /// Creates a diagnostic from an error token emitted by the parser.
+    /// Returns None if the token is not an error token.
+    ///
+    pub fn from_token(token : &Token) -> Option<Self> {
+        match &token.kind {
+            TokenKind::Error(error) => Some(
+                Diagnostic::error(error.to_string(), NodePosition::Source(token.span()))
+            ),
+            _ => None
+        }
+    }
+
+    /** Diagnostics for all errors and warnings emitted by the parser, errors first. */
+    pub fn from_tokens(tokens : &TokenStorage) -> Vec<Self> {
+
+        let mut diagnostics : Vec<Diagnostic> = tokens.errors().iter().filter_map(Diagnostic::from_token).collect();
+
+        diagnostics.extend(
+            tokens.warnings()
+                .iter()
+                .filter_map(Diagnostic::from_token)
+                .map(|diagnostic| Diagnostic { severity: Severity::Warning, ..diagnostic })
+        );
+
+        diagnostics
+    }
+
+}
+
+///
+/// Uses the innermost error as the message and the position of the innermost env or node it occurred at.
+///
+impl From<VisitError> for Diagnostic {
+    fn from(error: VisitError) -> Self {
+
+        let mut position = NodePosition::Inserted;
+
+        let mut error = &error;
+
+        while let VisitError::InEnv { position: env_position, error: inner, .. } 
+            | VisitError::AtNode { position: env_position, error: inner, .. } = error {
+
+            if let NodePosition::Source(_) = env_position {
+                position = env_position.clone();
+            }
+
+            error = inner;
+        }
+
+        Diagnostic::error(error.to_string(), position)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        match &self.position {
+            NodePosition::Source(span) => write!(f, "{} at {}: {}", severity, span.start, self.message),
+            NodePosition::Inserted => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error<'_> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse { error, .. } => Some(error),
+            Error::Visit(error) => Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::MatchableToken;
+
+    #[test]
+    fn display_parse_error() {
+
+        let src = "line one\n<Section>\n    unclosed";
+
+        let token = Token {
+            value: "",
+            kind: TokenKind::Error(ParseError::unexpected_eof(&[MatchableToken::EnvClose("</Section>".to_string())])),
+            position: ParserPosition::new(1, 9, 18),
+        };
+
+        let error = Error::from_token(src, &token).unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "2:10: Unexpected end of module. Expected '</Section>'.\n<Section>\n         ^"
+        );
+    }
+
+    #[test]
+    fn display_visit_error() {
+
+        let error : Error = VisitError::Unknown("Cannot resolve variable \"x\".".to_string()).into();
+
+        assert_eq!(error.to_string(), "Cannot resolve variable \"x\".");
+
+        assert_eq!(
+            VisitError::MaxIterationsReached.to_string(),
+            "Maximum number of transform passes reached."
+        );
+    }
+
+    #[test]
+    fn error_trait_objects() {
+
+        let error : Box<dyn std::error::Error> = Box::new(ParseError::quote_not_closed(&MatchableToken::Quote));
+
+        assert_eq!(error.to_string(), "Quote '\"' not closed.");
+
+        let error : Box<dyn std::error::Error> = Box::new(
+            Error::Visit(VisitError::RootRemoved)
+        );
+
+        assert!(error.source().is_some());
+    }
+
+}
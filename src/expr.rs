@@ -0,0 +1,355 @@
+//!
+//! Evaluation of arithmetic and comparison expressions in ${...}.
+//!
+//! Supported are numbers, strings in single or double quotes, true/false, variables,
+//! the operators + - * / % == != < <= > >= ! and parentheses.
+//!
+
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    Syntax(String),
+    UndefinedVariable(String),
+    TypeMismatch(String),
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprError::Syntax(message) => write!(f, "Syntax error: {}", message),
+            ExprError::UndefinedVariable(name) => write!(f, "Cannot resolve variable \"{}\".", name),
+            ExprError::TypeMismatch(message) => write!(f, "Type mismatch: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl Value {
+
+    ///
+    /// Interprets text (e.g. the value of a variable) as a number or boolean if possible.
+    ///
+    pub fn from_text(text : &str) -> Self {
+
+        let trimmed = text.trim();
+
+        match trimmed {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => match trimmed.parse::<f64>() {
+                Ok(number) => Value::Number(number),
+                Err(_) => Value::Str(text.to_string()),
+            }
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Bool(_) => "boolean",
+            Value::Str(_) => "string",
+        }
+    }
+
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // integers are shown without decimal places
+            Value::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => write!(f, "{}", *number as i64),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+///
+/// Checks if the expression is a plain variable name.
+/// Names containing other characters like "-" or "." are not, "a-b" is a subtraction.
+///
+pub fn is_identifier(expr : &str) -> bool {
+    expr.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && expr.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/** Maximum nesting of parentheses and unary operators, deeper expressions would overflow the stack. */
+const MAX_DEPTH : usize = 64;
+
+struct ExprParser<'a, R : Fn(&str) -> Result<Value, ExprError>> {
+    remaining: &'a str,
+    resolve: R,
+    /** Current nesting of parentheses and unary operators. */
+    depth: usize,
+}
+
+impl<'a, R : Fn(&str) -> Result<Value, ExprError>> ExprParser<'a, R> {
+
+    fn skip_whitespace(&mut self) {
+        self.remaining = self.remaining.trim_start();
+    }
+
+    ///
+    /// Consumes one of the operators if the remaining expression starts with it.
+    /// Longer operators must be listed first.
+    ///
+    fn operator(&mut self, operators : &[&'static str]) -> Option<&'static str> {
+
+        self.skip_whitespace();
+
+        let operator = operators.iter().find(|op| self.remaining.starts_with(**op))?;
+
+        self.remaining = &self.remaining[operator.len()..];
+
+        Some(operator)
+    }
+
+    ///
+    /// Evaluates a nested expression, failing if the nesting exceeds MAX_DEPTH.
+    ///
+    fn nested(&mut self, evaluate : fn(&mut Self) -> Result<Value, ExprError>) -> Result<Value, ExprError> {
+
+        if self.depth == MAX_DEPTH {
+            return Err(ExprError::Syntax("expression is nested too deeply".to_string()));
+        }
+
+        self.depth += 1;
+
+        let value = evaluate(self);
+
+        self.depth -= 1;
+
+        value
+    }
+
+    fn comparison(&mut self) -> Result<Value, ExprError> {
+
+        let left = self.additive()?;
+
+        let Some(operator) = self.operator(&["==", "!=", "<=", ">=", "<", ">"]) else {
+            return Ok(left);
+        };
+
+        let right = self.additive()?;
+
+        let ordering = match (&left, &right) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) if operator == "==" || operator == "!=" => Some(a.cmp(b)),
+            _ => return Err(ExprError::TypeMismatch(format!(
+                "cannot compare {} and {}", left.type_name(), right.type_name()
+            ))),
+        };
+
+        let result = match operator {
+            "==" => ordering.is_some_and(|o| o.is_eq()),
+            "!=" => !ordering.is_some_and(|o| o.is_eq()),
+            "<" => ordering.is_some_and(|o| o.is_lt()),
+            "<=" => ordering.is_some_and(|o| o.is_le()),
+            ">" => ordering.is_some_and(|o| o.is_gt()),
+            _ => ordering.is_some_and(|o| o.is_ge()),
+        };
+
+        Ok(Value::Bool(result))
+    }
+
+    fn additive(&mut self) -> Result<Value, ExprError> {
+
+        let mut left = self.multiplicative()?;
+
+        while let Some(operator) = self.operator(&["+", "-"]) {
+
+            let right = self.multiplicative()?;
+
+            left = match (operator, left, right) {
+                ("+", Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+                ("+", Value::Str(a), Value::Str(b)) => Value::Str(a + &b),
+                ("-", Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+                (operator, a, b) => return Err(ExprError::TypeMismatch(format!(
+                    "cannot apply {} to {} and {}", operator, a.type_name(), b.type_name()
+                ))),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn multiplicative(&mut self) -> Result<Value, ExprError> {
+
+        let mut left = self.unary()?;
+
+        while let Some(operator) = self.operator(&["*", "/", "%"]) {
+
+            let right = self.unary()?;
+
+            left = match (left, right) {
+                (Value::Number(a), Value::Number(b)) => Value::Number(match operator {
+                    "*" => a * b,
+                    "/" => a / b,
+                    _ => a % b,
+                }),
+                (a, b) => return Err(ExprError::TypeMismatch(format!(
+                    "cannot apply {} to {} and {}", operator, a.type_name(), b.type_name()
+                ))),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Value, ExprError> {
+
+        match self.operator(&["-", "!"]) {
+            Some("-") => match self.nested(Self::unary)? {
+                Value::Number(number) => Ok(Value::Number(-number)),
+                value => Err(ExprError::TypeMismatch(format!("cannot negate {}", value.type_name()))),
+            },
+            Some(_) => match self.nested(Self::unary)? {
+                Value::Bool(value) => Ok(Value::Bool(!value)),
+                value => Err(ExprError::TypeMismatch(format!("cannot invert {}", value.type_name()))),
+            },
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value, ExprError> {
+
+        self.skip_whitespace();
+
+        let c = self.remaining.chars().next().ok_or(
+            ExprError::Syntax("unexpected end of expression".to_string())
+        )?;
+
+        match c {
+            '(' => {
+                self.remaining = &self.remaining[1..];
+
+                let value = self.nested(Self::comparison)?;
+
+                self.operator(&[")"]).ok_or(ExprError::Syntax("expected ')'".to_string()))?;
+
+                Ok(value)
+            },
+            '"' | '\'' => {
+                let end = self.remaining[1..].find(c).ok_or(
+                    ExprError::Syntax("string not closed".to_string())
+                )?;
+
+                let value = self.remaining[1..end + 1].to_string();
+
+                self.remaining = &self.remaining[end + 2..];
+
+                Ok(Value::Str(value))
+            },
+            c if c.is_ascii_digit() || c == '.' => {
+                let len = self.remaining
+                    .find(|c : char| !(c.is_ascii_digit() || c == '.'))
+                    .unwrap_or(self.remaining.len());
+
+                let number = self.remaining[..len].parse::<f64>().map_err(
+                    |_| ExprError::Syntax(format!("invalid number \"{}\"", &self.remaining[..len]))
+                )?;
+
+                self.remaining = &self.remaining[len..];
+
+                Ok(Value::Number(number))
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let len = self.remaining
+                    .find(|c : char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(self.remaining.len());
+
+                let name = &self.remaining[..len];
+
+                self.remaining = &self.remaining[len..];
+
+                match name {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => (self.resolve)(name),
+                }
+            },
+            c => Err(ExprError::Syntax(format!("unexpected '{}'", c))),
+        }
+    }
+
+}
+
+///
+/// Evaluates the expression. Variables are looked up using resolve.
+/// Unknown variables should be reported as ExprError::UndefinedVariable.
+///
+pub fn evaluate(expr : &str, resolve : impl Fn(&str) -> Result<Value, ExprError>) -> Result<Value, ExprError> {
+
+    let mut parser = ExprParser { remaining: expr, resolve, depth: 0 };
+
+    let value = parser.comparison()?;
+
+    parser.skip_whitespace();
+
+    match parser.remaining.chars().next() {
+        Some(c) => Err(ExprError::Syntax(format!("unexpected '{}'", c))),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn eval(expr : &str) -> Result<Value, ExprError> {
+        evaluate(expr, |name| match name {
+            "a" => Ok(Value::Number(2.0)),
+            "name" => Ok(Value::Str("vtx".to_string())),
+            _ => Err(ExprError::UndefinedVariable(name.to_string())),
+        })
+    }
+
+    #[test]
+    fn arithmetic() {
+
+        assert_eq!(eval("a + 1").unwrap(), Value::Number(3.0));
+        assert_eq!(eval("a * 2 + 1").unwrap(), Value::Number(5.0));
+        assert_eq!(eval("a * (2 + 1)").unwrap(), Value::Number(6.0));
+        assert_eq!(eval("-a / 4").unwrap().to_string(), "-0.5");
+        assert_eq!(eval("name + '!'").unwrap().to_string(), "vtx!");
+    }
+
+    #[test]
+    fn comparison() {
+
+        assert_eq!(eval("a > 3").unwrap(), Value::Bool(false));
+        assert_eq!(eval("a * 2 >= 4").unwrap(), Value::Bool(true));
+        assert_eq!(eval("name == \"vtx\"").unwrap(), Value::Bool(true));
+        assert_eq!(eval("!(a == 2)").unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn errors() {
+
+        assert_eq!(eval("b + 1"), Err(ExprError::UndefinedVariable("b".to_string())));
+        assert!(matches!(eval("name + 1"), Err(ExprError::TypeMismatch(_))));
+        assert!(matches!(eval("a +"), Err(ExprError::Syntax(_))));
+        assert!(matches!(eval("a 1"), Err(ExprError::Syntax(_))));
+
+        // deep nesting fails instead of overflowing the stack
+        assert_eq!(eval(&format!("{}a{}", "(".repeat(64), ")".repeat(64))).unwrap(), Value::Number(2.0));
+        assert_eq!(
+            eval(&format!("{}a{}", "(".repeat(100_000), ")".repeat(100_000))),
+            Err(ExprError::Syntax("expression is nested too deeply".to_string()))
+        );
+        assert!(matches!(eval(&"-".repeat(100_000)), Err(ExprError::Syntax(_))));
+    }
+
+}
@@ -0,0 +1,44 @@
+//!
+//! Minimal helpers for writing JSON without pulling in a serialization library.
+//!
+
+use std::fmt::Write;
+
+///
+/// Appends s as a quoted and escaped JSON string to out.
+///
+pub(crate) fn write_string(out : &mut String, s : &str) {
+
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn escape_strings() {
+
+        let mut out = String::new();
+
+        write_string(&mut out, "say \"hi\"\\\n\u{1}");
+
+        assert_eq!(out, r#""say \"hi\"\\\n\u0001""#);
+    }
+
+}
@@ -1,4 +1,15 @@
 
 pub mod document;
+pub mod error;
+pub mod expr;
 pub mod parse;
-pub mod visitors;
\ No newline at end of file
+pub mod render;
+pub mod visitors;
+
+mod json;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use document::Document;
+pub use render::{render_html, render_to_string, transpile_path, transpile_to_html, TranspileError};
\ No newline at end of file
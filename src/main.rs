@@ -1,12 +1,10 @@
 
 use vtx::parse::*;
-use vtx::visitors::components::ComponentInsert;
-use vtx::visitors::components::ComponentRegister;
+use vtx::visitors::components::inspect_components;
 use vtx::visitors::html_emit::HTMLEmitter;
-use vtx::visitors::cleanup::Cleanup;
-use vtx::visitors::variables::Variables;
-use vtx::document::visit::transform;
+use vtx::document::visit::{transform_one, transform_to_fixpoint};
 use vtx::document::visit::TransformerOnce;
+use vtx::render::default_transformers;
 
 use std::io::Read;
 
@@ -22,19 +20,29 @@ fn main() {
 
     let (document, _) = parse(&src);
 
-    let document = transform(
-        document,
-        &mut vec![
-            Box::new(TransformerOnce::new(Cleanup)),
-            Box::new(TransformerOnce::new(ComponentRegister)),
-            Box::new(TransformerOnce::new(ComponentInsert)),
-            Box::new(TransformerOnce::new(Variables::new()))
-        ],
-        1
-    ).unwrap();
+    if std::env::args().any(|arg| arg == "--list-components") {
+        
+        match inspect_components(document) {
+            Ok(report) => print!("{}", report),
+            Err(error) => eprintln!("{}", error),
+        }
+
+        return;
+    }
+
+    if std::env::args().any(|arg| arg == "--json") {
+
+        println!("{}", document.to_json());
+
+        return;
+    }
+
+    let document = transform_to_fixpoint(document, &mut default_transformers()).unwrap();
     
-    let _ = transform(document, &mut vec![
-        Box::new(TransformerOnce::new(HTMLEmitter{ collector: stdout_collector, debug: true })),
-    ], 1);
+    let mut emitter = HTMLEmitter::new(stdout_collector);
+
+    emitter.debug = true;
+
+    let _ = transform_one(document, &mut TransformerOnce::new(emitter), 1);
 
 }
@@ -45,31 +45,34 @@ impl ContentParseMode {
 
     pub fn from_attrs(attrs : &EnvNodeAttrs) -> Result<Self, DynamicParsingError> {
         match attrs.get("content") {
-            Some(value) => match value {
-                Some(node) => match &node.kind {
-                    NodeKind::Leaf(LeafNode::Text(mode)) => {
-                        match mode.as_str() {
-                            "vtx" => Ok(ContentParseMode::Vtx),
-                            "raw" => Ok(ContentParseMode::Raw),
-                            "raw-strict" => Ok(ContentParseMode::RawStrict),
-                            _ => Err(DynamicParsingError::InvalidContentParseMode)
-                        }
-                    },
-                    _ => Err(DynamicParsingError::InvalidContentParseMode)
+            Some(Some(node)) => match &node.kind {
+                NodeKind::Leaf(LeafNode::Text(mode)) => {
+                    match mode.as_str() {
+                        "vtx" => Ok(ContentParseMode::Vtx),
+                        "raw" => Ok(ContentParseMode::Raw),
+                        "raw-strict" => Ok(ContentParseMode::RawStrict),
+                        _ => Err(DynamicParsingError::InvalidContentParseMode)
+                    }
                 },
-                None => Ok(Self::Vtx),
+                _ => Err(DynamicParsingError::InvalidContentParseMode)
             },
-            None => Ok(Self::Vtx),
+            Some(None) | None => Ok(Self::Vtx),
         }
     }
 
 }
 
+impl Default for DynamicParserState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DynamicParserState {
 
     pub fn new() -> Self {
         Self {
-            // TODO: define Eq/Code as components
+            // built-in raw envs, components with content="raw" are added through set_env_parse_attrs
             env_parse_attrs: HashMap::from([
                 (EnvNodeHeaderKind::Eq(EquationKind::Block), EnvParseAttrs {
                     content: ContentParseMode::Raw
@@ -79,7 +82,10 @@ impl DynamicParserState {
                 }),
                 (EnvNodeHeaderKind::Code, EnvParseAttrs {
                     content: ContentParseMode::Raw
-                })
+                }),
+                (EnvNodeHeaderKind::Other(RAW_HTML_ENV.to_string()), EnvParseAttrs {
+                    content: ContentParseMode::Raw
+                }),
             ])
         }
     }
@@ -101,7 +107,7 @@ impl EnvParseAttrs {
     }
 
     pub fn content(&self) -> &ContentParseMode {
-        return &self.content;
+        &self.content
     }
 
 }
@@ -117,4 +123,21 @@ impl DynamicParserState {
 
 pub fn component_name_definition_attrs(attrs : &EnvNodeAttrs) -> Option<&String> {
     Some(attrs.first()?.0)
-}
\ No newline at end of file
+}
+///
+/// Name of the env whose contents are passed through to the HTML output as they are, 
+/// e.g. <Html><svg>...</svg></Html>.
+///
+pub const RAW_HTML_ENV : &str = "Html";
+
+/// HTML elements which never have children
+const VOID_ELEMENTS : [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+///
+/// Checks if the env is a void element, which is self-closing even if written as <br>.
+///
+pub fn is_void_element(header_kind : &EnvNodeHeaderKind) -> bool {
+    matches!(header_kind, EnvNodeHeaderKind::Other(name) if VOID_ELEMENTS.contains(&name.as_str()))
+}
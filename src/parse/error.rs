@@ -1,15 +1,19 @@
 
 use core::fmt;
 
-use crate::parse::TokenKind;
+use crate::parse::MatchableToken;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParseErrorKind {
     EnvHeaderNotClosed,
     EnvNotClosed,
+    MismatchedClosingTag,
+    DuplicateAttr,
     MissingAttrName,
     MissingAttrValue,
     QuoteNotClosed,
+    FrontmatterNotClosed,
+    InvalidFrontmatter,
     Unknown,
     ToDo
 }
@@ -28,18 +32,47 @@ pub struct ParseError {
 
 impl ParseError {
     
-    pub fn unexpected_eof(_end_kinds : &[TokenKind],) -> Self {
-        
+    pub fn unexpected_eof(end_kinds : &[MatchableToken]) -> Self {
+
+        let end_kinds = end_kinds.iter().map(|kind| kind.to_string()).collect::<Vec<String>>();
+
+        let expected = match end_kinds.split_last() {
+            None => "more input".to_string(),
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        };
+
+        ParseError {
+            kind: ParseErrorKind::EnvNotClosed,
+            message: format!("Unexpected end of module. Expected {}.", expected),
+        }
+    }
+
+    pub fn env_not_closed(name : &str) -> Self {
         ParseError {
             kind: ParseErrorKind::EnvNotClosed,
-            message: format!("Environment never closed. Expected TODO: print end_kinds."),
+            message: format!("<{}> is never closed.", name),
+        }
+    }
+
+    pub fn mismatched_closing_tag(expected : &str, found : &str) -> Self {
+        ParseError {
+            kind: ParseErrorKind::MismatchedClosingTag,
+            message: format!("Expected {} but found {}.", expected, found),
+        }
+    }
+
+    pub fn duplicate_attr(name : &str) -> Self {
+        ParseError {
+            kind: ParseErrorKind::DuplicateAttr,
+            message: format!("Duplicate attribute \"{}\", the last value is used.", name),
         }
     }
 
     pub fn env_header_not_closed() -> Self {     
         ParseError {
             kind: ParseErrorKind::EnvHeaderNotClosed,
-            message: format!("Expected '>', '/>', or attribute list."),
+            message: "Expected '>', '/>', or attribute list.".to_string(),
         }
     }
 
@@ -71,15 +104,37 @@ impl ParseError {
         }
     }
 
-    pub fn quote_not_closed() -> Self{
+    pub fn quote_not_closed(quote : &MatchableToken) -> Self{
         ParseError{
             kind: ParseErrorKind::QuoteNotClosed,
-            message: String::from("Quote '\"' not closed."),
+            message: format!("Quote {} not closed.", quote),
+        }
+    }
+
+    pub fn frontmatter_not_closed() -> Self{
+        ParseError{
+            kind: ParseErrorKind::FrontmatterNotClosed,
+            message: String::from("Frontmatter not closed. Expected '---'."),
+        }
+    }
+
+    pub fn invalid_frontmatter_line() -> Self{
+        ParseError{
+            kind: ParseErrorKind::InvalidFrontmatter,
+            message: String::from("Expected \"key: value\" in frontmatter."),
         }
     }
 
     pub fn kind(&self) -> &ParseErrorKind {
-        return &self.kind
+        &self.kind
     }
 
 }
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,5 +1,8 @@
 
+#[allow(clippy::module_inception)]
 mod parse;
 pub mod error;
 pub mod dynamic_parse;
+mod reparse;
 pub use parse::*;
+pub use reparse::reparse;
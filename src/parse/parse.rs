@@ -1,5 +1,6 @@
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::str::Chars;
 
 use crate::document::*;
@@ -8,10 +9,11 @@ use crate::parse::dynamic_parse::ContentParseMode;
 use crate::parse::dynamic_parse::DynamicParserState;
 use crate::parse::dynamic_parse::DynamicParsingError;
 use crate::parse::dynamic_parse::EnvParseAttrs;
+use crate::parse::dynamic_parse::is_void_element;
 
 use crate::parse::error::*;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ParserPosition {
     // index of the current line in the module 
     line: usize,
@@ -19,26 +21,88 @@ pub struct ParserPosition {
     col: usize,
     // offset of the current char (from the source start) in bytes
     byte_idx: usize,
+    // index of the source when parsing multiple sources
+    source: usize,
 }
 
-impl Clone for ParserPosition {
-    fn clone(&self) -> ParserPosition {
-        Self{ line: self.line, col: self.col, byte_idx: self.byte_idx }
+///
+/// Range of a source from start (inclusive) to end (exclusive).
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: ParserPosition,
+    pub end: ParserPosition,
+}
+
+impl Span {
+
+    pub fn new(start : ParserPosition, end : ParserPosition) -> Self {
+        Self { start, end }
+    }
+
+    /** Empty span at the position. */
+    pub fn at(position : ParserPosition) -> Self {
+        Self { start: position, end: position }
+    }
+
+    /** Length of the span in bytes. */
+    pub fn len(&self) -> usize {
+        self.end.byte_idx - self.start.byte_idx
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+}
+
+///
+/// Formats the position as line:col (both 1-based).
+///
+impl std::fmt::Display for ParserPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.col + 1)
+    }
+}
+
+impl PartialOrd for ParserPosition {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for ParserPosition {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.byte_idx.cmp(&other.byte_idx)
+        (self.source, self.byte_idx).cmp(&(other.source, other.byte_idx))
     }
 }
 
 pub struct TokenStorage<'a> {
     tokens: Vec<Token<'a>>,
-    errors: Vec<Token<'a>>
+    errors: Vec<Token<'a>>,
+    warnings: Vec<Token<'a>>,
+}
+
+///
+/// Options for parsing a source.
+///
+#[derive(Debug, Clone, Default)]
+pub struct ParseConfig {
+    /** Index of the source that all positions will refer to. */
+    pub source: usize,
+    ///
+    /// Also inserts a LeafNode::Error node where the parser has recovered from an error, 
+    /// e.g. into an env that is never closed or as the value of an attribute that is missing its value. 
+    /// The node has the message and the position of the error in TokenStorage::errors.
+    ///
+    pub error_nodes: bool,
+    /** Also parses `\(...\)` as inline and `\[...\]` as block equations, like LaTeX. */
+    pub latex_delimiters: bool,
 }
 
 pub struct Parser<'a>{
+    /** Entire source string. */
+    src : &'a str,
     /** Remaining source string. */
     remaining : &'a str,
     /** Char iterator over the source string. */
@@ -49,19 +113,75 @@ pub struct Parser<'a>{
     parsed_tokens: TokenStorage<'a>,
     /** Dynamic part of the parser state. */
     dynamic_state: DynamicParserState,
+    /** Closing tags of the envs whose children are being parsed, innermost last. */
+    closing_tags: Vec<MatchableToken>,
+    /** Closing tag of the innermost parse_children_until. */
+    innermost_closing_tag: MatchableToken,
+    /** Byte offsets of the envs that are known to be unclosed at the end of the module. */
+    unclosed: HashSet<usize>,
+    /** Number of times parse_children_until has left a closing tag to an ancestor. */
+    ancestor_stops: usize,
+    ///
+    /// Set when an env turns out to be unclosed at the end of the module: 
+    /// the position after its header, where the env ends, and its parsed children, 
+    /// which become its siblings.
+    ///
+    unclosed_siblings: Option<(ParserPosition, VecDeque<Node>)>,
+    /** See ParseConfig::error_nodes. */
+    error_nodes: bool,
+    /** See ParseConfig::latex_delimiters. */
+    latex_delimiters: bool,
+}
+
+///
+/// Parser state that can be restored in order to parse a part of the source again.
+///
+struct Checkpoint {
+    position: ParserPosition,
+    tokens: usize,
+    errors: usize,
+    warnings: usize,
+}
+
+/** Reason for parse_children_until to stop. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChildrenEnd {
+    /** The closing tag has been found. */
+    Closed,
+    /** The closing tag of an ancestor has been found, the parser is positioned right before it. */
+    Ancestor,
+    /** The end of the module has been reached. */
+    EndOfModule,
 }
 
 #[derive(Debug, Clone)]
 pub struct TokenHandle(usize);
 
+///
+/// Matched token at which seek_to_and_capture has stopped.
+///
+#[derive(Debug, Clone)]
+struct Stop<'a> {
+    kind: MatchableToken,
+    value: &'a str,
+    position: ParserPosition,
+    /** Handle of the stored token. */
+    handle: TokenHandle,
+}
+
 impl Copy for TokenHandle {}
 
+///
+/// Tokens that are matched at the current position, see Parser::try_parse_token.
+///
 #[derive(Debug, Clone, PartialEq)]
-pub enum TokenKind{
+pub enum MatchableToken {
     EnvOpen,
     FragmentOpen,
     FragmentClose,
     EnvClose(String),
+    /** Closing tag with any name, e.g. "</Chapter>". */
+    AnyEnvClose,
     EnvSelfClose,
     RightAngle,
     CommentOpen,
@@ -71,12 +191,32 @@ pub enum TokenKind{
     EndOfModule,
     DollarBrace,
     RightBrace,
+    DoubleDollar,
     Dollar,
     Equals,
     Quote,
+    SingleQuote,
     HeadingOpen,
-    // TODO: these are non-matchable tokens that are only parsed when capturing
-    //       separate matchable from non-matchable tokens
+    ListItemOpen,
+    DoubleAsterisk,
+    Asterisk,
+    Underscore,
+    LeftBracket,
+    RightBracket,
+    /** `\(`, see ParseConfig::latex_delimiters. */
+    InlineMathOpen,
+    InlineMathClose,
+    /** `\[`, see ParseConfig::latex_delimiters. */
+    BlockMathOpen,
+    BlockMathClose,
+}
+
+///
+/// Tokens for the source between matched tokens, see Parser::seek_to_and_capture.
+/// These would match anything, so they cannot be used for matching.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapturedKind {
     Text,
     CommentText,
     Math,
@@ -84,9 +224,16 @@ pub enum TokenKind{
     AttrName,
     VariableName,
     StringLiteral,
-    Error(ParseError)
+    /** Whitespace skipped before a quoted attribute value. */
+    Whitespace,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Matched(MatchableToken),
+    Captured(CapturedKind),
+    Error(ParseError)
+}
 
 #[derive(Debug, PartialEq)]
 pub struct Token<'a> {
@@ -98,11 +245,16 @@ pub struct Token<'a> {
 impl ParserPosition {
 
     pub fn zero() -> Self {
-        Self { line: 0, col: 0, byte_idx: 0 }
+        Self { line: 0, col: 0, byte_idx: 0, source: 0 }
     }
 
     pub fn new(line : usize, col : usize, abs: usize) -> Self {
-        Self { line, col, byte_idx: abs }
+        Self { line, col, byte_idx: abs, source: 0 }
+    }
+
+    /** Same position within another source. */
+    pub fn with_source(self, source : usize) -> Self {
+        Self { source, ..self }
     }
 
     //
@@ -125,25 +277,172 @@ impl ParserPosition {
         delta_bytes
     }
 
-    pub fn line(&self) -> &usize { &self.line }
-    pub fn col(&self) -> &usize { &self.col }
-    pub fn bytes(&self) -> &usize { &self.line }
+    /** Position after the text starting at this position. */
+    pub fn advanced_over(mut self, text : &str) -> Self {
+
+        self.advance_over(text);
+
+        self
+    }
+
+    ///
+    /// Advances the position by the text without decoding its chars.
+    /// Same as calling advance for every char in the text.
+    ///
+    fn advance_over(&mut self, text : &str) {
+
+        let bytes = text.as_bytes();
+
+        // counts the first byte of every char
+        let count_chars = |bytes : &[u8]| bytes.iter().filter(|b| (**b & 0xC0) != 0x80).count();
+
+        match bytes.iter().rposition(|b| *b == b'\n') {
+            Some(last_line_break) => {
+                self.line += bytes.iter().filter(|b| **b == b'\n').count();
+                self.col = count_chars(&bytes[last_line_break + 1..]);
+            },
+            None => self.col += count_chars(bytes),
+        }
+
+        self.byte_idx += bytes.len();
+    }
+
+    /** Index of the line (0-based). */
+    pub fn line(&self) -> usize { self.line }
+    /** Index of the char within the line (0-based). */
+    pub fn col(&self) -> usize { self.col }
+    /** Offset from the start of the source in bytes. */
+    pub fn byte_idx(&self) -> usize { self.byte_idx }
+    /** Index of the source when parsing multiple sources. */
+    pub fn source(&self) -> usize { self.source }
+
+    #[deprecated(note = "use byte_idx")]
+    pub fn bytes(&self) -> usize { self.byte_idx }
 
 }
 
-impl TokenKind {
+impl MatchableToken {
 
     fn new_env_close(header_kind : &EnvNodeHeaderKind) -> Self {
-        TokenKind::EnvClose(header_kind.get_closing_string())
+        MatchableToken::EnvClose(header_kind.get_closing_string())
+    }
+
+    ///
+    /// Bytes a match of the token can start with.
+    /// None if the token may start with a non-ASCII char.
+    ///
+    fn first_bytes(&self) -> Option<&[u8]> {
+        match self {
+            MatchableToken::EnvOpen |
+            MatchableToken::FragmentOpen |
+            MatchableToken::FragmentClose |
+            MatchableToken::AnyEnvClose => Some(b"<"),
+            MatchableToken::EnvClose(closer) => Some(&closer.as_bytes()[..closer.len().min(1)]),
+            MatchableToken::EnvSelfClose | MatchableToken::CommentOpen => Some(b"/"),
+            MatchableToken::RightAngle => Some(b">"),
+            MatchableToken::CommentClose => Some(b"*"),
+            // unicode whitespace
+            MatchableToken::Whitespace => None,
+            MatchableToken::EndOfLine => Some(b"\n"),
+            // only matches at the end, see seek_to
+            MatchableToken::EndOfModule => Some(b""),
+            MatchableToken::DollarBrace | MatchableToken::DoubleDollar | MatchableToken::Dollar => Some(b"$"),
+            MatchableToken::RightBrace => Some(b"}"),
+            MatchableToken::Equals => Some(b"="),
+            MatchableToken::Quote => Some(b"\""),
+            MatchableToken::SingleQuote => Some(b"'"),
+            MatchableToken::HeadingOpen => Some(b"#"),
+            MatchableToken::ListItemOpen => Some(b"0123456789-*+"),
+            MatchableToken::DoubleAsterisk | MatchableToken::Asterisk => Some(b"*"),
+            MatchableToken::Underscore => Some(b"_"),
+            MatchableToken::LeftBracket => Some(b"["),
+            MatchableToken::RightBracket => Some(b"]"),
+            MatchableToken::InlineMathOpen |
+            MatchableToken::InlineMathClose |
+            MatchableToken::BlockMathOpen |
+            MatchableToken::BlockMathClose => Some(b"\\"),
+        }
+    }
+}
+
+/** How the token is referred to in error messages, e.g. "Expected '</Section>'." */
+impl fmt::Display for MatchableToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchableToken::EnvOpen => write!(f, "an opening tag"),
+            MatchableToken::FragmentOpen => write!(f, "'<>'"),
+            MatchableToken::FragmentClose => write!(f, "'</>'"),
+            MatchableToken::EnvClose(closer) => write!(f, "'{}'", closer),
+            MatchableToken::AnyEnvClose => write!(f, "a closing tag"),
+            MatchableToken::EnvSelfClose => write!(f, "'/>'"),
+            MatchableToken::RightAngle => write!(f, "'>'"),
+            MatchableToken::CommentOpen => write!(f, "'/**'"),
+            MatchableToken::CommentClose => write!(f, "'*/'"),
+            MatchableToken::Whitespace => write!(f, "whitespace"),
+            MatchableToken::EndOfLine => write!(f, "a line break"),
+            MatchableToken::EndOfModule => write!(f, "the end of the module"),
+            MatchableToken::DollarBrace => write!(f, "'${{'"),
+            MatchableToken::RightBrace => write!(f, "'}}'"),
+            MatchableToken::DoubleDollar => write!(f, "'$$'"),
+            MatchableToken::Dollar => write!(f, "'$'"),
+            MatchableToken::Equals => write!(f, "'='"),
+            MatchableToken::Quote => write!(f, "'\"'"),
+            MatchableToken::SingleQuote => write!(f, "\"'\""),
+            MatchableToken::HeadingOpen => write!(f, "a heading"),
+            MatchableToken::ListItemOpen => write!(f, "a list item"),
+            MatchableToken::DoubleAsterisk => write!(f, "'**'"),
+            MatchableToken::Asterisk => write!(f, "'*'"),
+            MatchableToken::Underscore => write!(f, "'_'"),
+            MatchableToken::LeftBracket => write!(f, "'['"),
+            MatchableToken::RightBracket => write!(f, "']'"),
+            MatchableToken::InlineMathOpen => write!(f, "'\\('"),
+            MatchableToken::InlineMathClose => write!(f, "'\\)'"),
+            MatchableToken::BlockMathOpen => write!(f, "'\\['"),
+            MatchableToken::BlockMathClose => write!(f, "'\\]'"),
+        }
     }
 }
 
+///
+/// Table of the bytes at which one of the tokens or an escape may start.
+/// None if one of the tokens may start with a non-ASCII char.
+///
+fn stop_bytes(tokens : &[MatchableToken]) -> Option<[bool; 256]> {
+
+    let mut stops = [false; 256];
+
+    stops[b'\\' as usize] = true;
+
+    for token in tokens {
+        for byte in token.first_bytes()? {
+            stops[*byte as usize] = true;
+        }
+    }
+
+    Some(stops)
+}
+
 impl<'a> Token<'a> {
 
     pub fn len(&self) -> usize {
         self.value.len()
     } 
 
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    /** Range of the source covered by the token. */
+    pub fn span(&self) -> Span {
+        Span::new(self.position, self.position.advanced_over(self.value))
+    }
+
+}
+
+impl<'a> Default for TokenStorage<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> TokenStorage<'a> {
@@ -151,7 +450,8 @@ impl<'a> TokenStorage<'a> {
     pub fn new() -> Self {
         Self { 
             tokens: Vec::new(), 
-            errors: Vec::new()
+            errors: Vec::new(),
+            warnings: Vec::new(),
         }
     }
     
@@ -159,6 +459,24 @@ impl<'a> TokenStorage<'a> {
         self.tokens.get(handle.0).unwrap()
     }
 
+    ///
+    /// All tokens in source order, e.g. for syntax highlighting.
+    /// Matched tokens are markers like "<" or "/>", captured tokens are the source between them.
+    ///
+    pub fn tokens(&self) -> impl Iterator<Item = &Token<'a>> {
+        self.tokens.iter()
+    }
+
+    /** Syntax errors found while parsing. */
+    pub fn errors(&self) -> &[Token<'a>] {
+        &self.errors
+    }
+
+    /** Likely authoring mistakes that do not prevent parsing, e.g. duplicate attributes. */
+    pub fn warnings(&self) -> &[Token<'a>] {
+        &self.warnings
+    }
+
     //
     // Pushes token into the storage and returns a TokenHandle.
     //
@@ -171,15 +489,264 @@ impl<'a> TokenStorage<'a> {
 
 ///
 /// @returns the substring that matches a heading-open token
+/// i.e. one or more "#" followed by spaces or tabs
 /// 
 fn capture_heading_open(s : &str) -> Option<&str> {
 
-    let mut chars_processed : usize = 0;
+    let level = s.bytes().take_while(|c| *c == b'#').count();
+
+    let whitespace = s.bytes().skip(level).take_while(|c| matches!(c, b' ' | b'\t')).count();
+
+    (level > 0 && whitespace > 0).then(|| &s[..level + whitespace])
+}
+
+///
+/// @returns the substring that matches a closing tag with any name, e.g. "</Chapter>"
+/// 
+fn capture_env_close(s : &str) -> Option<&str> {
+
+    let bytes = s.as_bytes();
+
+    if !s.starts_with("</") || !bytes.get(2).is_some_and(u8::is_ascii_alphabetic) {
+        return None;
+    }
+
+    let name_len = bytes[2..]
+        .iter()
+        .take_while(|c| !matches!(c, b'>' | b'<' | b'/') && !c.is_ascii_whitespace())
+        .count();
+
+    (bytes.get(2 + name_len) == Some(&b'>')).then(|| &s[..name_len + 3])
+}
+
+///
+/// @returns the substring that matches a list item marker including the following space
+/// e.g. "- ", "* ", "+ ", "1. "
+/// 
+fn capture_list_item_open(s : &str) -> Option<&str> {
+
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+
+    let marker_len = match s.as_bytes().get(digits) {
+        Some(b'.') if digits > 0 => digits + 1,
+        Some(b'-' | b'*' | b'+') if digits == 0 => 1,
+        _ => return None,
+    };
+
+    match s.as_bytes().get(marker_len) {
+        Some(b' ' | b'\t') => Some(&s[..marker_len + 1]),
+        _ => None,
+    }
+}
+
+fn is_ordered_list_marker(marker : &str) -> bool {
+    marker.starts_with(|c : char| c.is_ascii_digit())
+}
+
+///
+/// Checks if an emphasis opened by marker is closed before the end of the line.
+/// s is the source right after the opening marker.
+/// 
+fn emphasis_closes(s : &str, marker : &str) -> bool {
+
+    // the opening marker must be followed by content
+    if s.chars().next().is_none_or(char::is_whitespace) {
+        return false;
+    }
+
+    let mut chars = s.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' => return false,
+            '\\' => { chars.next(); },
+            // a single '*' does not close "**" and "**" does not close a single '*'
+            '*' if s[i..].starts_with("**") && marker != "**" => { chars.next(); },
+            _ if s[i..].starts_with(marker) => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+///
+/// Captures the destination of a link "[label](url)".
+/// s is the source right after the opening bracket.
+/// The label must end on the same line, parentheses in the url must be balanced or escaped.
+/// 
+/// @returns (label length, url) where the url still contains escape characters
+/// 
+fn capture_link(s : &str) -> Option<(usize, &str)> {
+
+    let mut chars = s.char_indices();
+
+    let label_len = loop {
+        match chars.next()? {
+            (_, '\n') => return None,
+            (_, '\\') => { chars.next(); },
+            (i, ']') => break i,
+            _ => {}
+        }
+    };
+
+    let url_start = label_len + 2;
+
+    if s.as_bytes().get(label_len + 1) != Some(&b'(') {
+        return None;
+    }
+
+    let mut depth : usize = 0;
+
+    let mut chars = s[url_start..].char_indices();
+
+    loop {
+        match chars.next()? {
+            (_, '\n') => return None,
+            (_, '\\') => { chars.next(); },
+            (_, '(') => depth += 1,
+            (i, ')') if depth == 0 => return Some((label_len, &s[url_start..url_start + i])),
+            (_, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+///
+/// Removes the escape characters from a string.
+/// 
+fn unescape(s : &str) -> String {
+
+    let mut result = String::with_capacity(s.len());
+
+    let mut chars = s.chars();
 
-    match s.chars().skip_while(|c| { let skip = *c == '#'; chars_processed +=1; skip }).next() {
-        Some(c) if chars_processed > 1 && c == ' ' => Some(&s[..chars_processed]),
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Characters with a meaning in vtx, which are taken literally when preceded by a backslash.
+pub const ESCAPABLE_CHARS : [char; 12] = ['<', '>', '$', '{', '}', '\\', '#', '*', '_', '[', ']', '-'];
+
+///
+/// Replaces escape sequences of characters with a meaning in vtx (e.g. "\$") with the literal character.
+/// Other backslashes are kept as they are.
+/// 
+pub fn decode_escapes(s : &str) -> String {
+
+    let mut result = String::with_capacity(s.len());
+
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next)) if ESCAPABLE_CHARS.contains(&next) => {
+                result.push(next);
+                chars.next();
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+///
+/// Creates a text node from a token with escape sequences decoded.
+/// 
+fn decoded_text(token : &Token) -> Node {
+    Node::new(
+        NodeKind::Leaf(LeafNode::Text(decode_escapes(token.value))),
+        NodePosition::Source(token.span())
+    )
+}
+
+///
+/// Parses the attribute block at the end of a heading, e.g. "## Heading {#intro .big}".
+/// The block is removed from the text, "#intro" becomes the id and ".big" is added to the classes.
+/// 
+/// Text that does not end with a valid block is kept as it is and no attributes are returned.
+/// Other envs (including fragments, which have no element to put the attributes on) take no blocks.
+///
+fn take_heading_attrs(children : &mut VecDeque<Node>) -> EnvNodeAttrs {
+
+    let mut attrs = EnvNodeAttrs::new();
+
+    let text_of = |node : &Node| match &node.kind {
+        NodeKind::Leaf(LeafNode::Text(text)) => Some(text.clone()),
         _ => None,
+    };
+
+    // the block may span multiple text nodes, e.g. ".snake_case"
+    let run = children.iter().rev().take_while(|node| text_of(node).is_some()).count();
+
+    let first = children.len() - run;
+
+    let text = children.range(first..).filter_map(text_of).collect::<String>();
+
+    let trimmed = text.trim_end();
+
+    let Some(block_start) = trimmed.strip_suffix('}').and_then(|text| text.rfind('{')) else {
+        return attrs;
+    };
+
+    let mut id = None;
+    let mut classes = Vec::new();
+
+    for part in trimmed[block_start + 1..trimmed.len() - 1].split_whitespace() {
+        match (part.strip_prefix('#'), part.strip_prefix('.')) {
+            (Some(value), _) if !value.is_empty() => id = Some(value),
+            (_, Some(value)) if !value.is_empty() => classes.push(value),
+            _ => return attrs,
+        }
+    }
+
+    if id.is_none() && classes.is_empty() {
+        return attrs;
+    }
+
+    let start = match &children[first].position {
+        NodePosition::Source(span) => Some(span.start),
+        NodePosition::Inserted => None,
+    };
+
+    let position_of = |from : usize, to : usize| match start {
+        Some(start) => NodePosition::Source(Span::new(
+            start.advanced_over(&text[..from]), 
+            start.advanced_over(&text[..to])
+        )),
+        None => NodePosition::Inserted,
+    };
+
+    let block_position = position_of(block_start, trimmed.len());
+
+    let prefix = text[..block_start].trim_end();
+
+    let prefix_position = position_of(0, prefix.len());
+
+    children.truncate(first);
+
+    if !prefix.is_empty() {
+        children.push_back(Node::new(NodeKind::Leaf(LeafNode::Text(prefix.to_string())), prefix_position));
+    }
+
+    let value = |text : String| Some(Node::new(NodeKind::Leaf(LeafNode::Text(text)), block_position.clone()));
+
+    if let Some(id) = id {
+        attrs.insert("id".to_string(), value(id.to_string()));
+    }
+
+    if !classes.is_empty() {
+        attrs.insert("class".to_string(), value(classes.join(" ")));
     }
+
+    attrs
 }
 
 impl<'a> Parser<'a> {
@@ -188,12 +755,37 @@ impl<'a> Parser<'a> {
     /// Create a new parser from a source slice. 
     /// 
     pub fn new(src : & 'a str) -> Self {
+        Self::with_source(src, 0)
+    }
+
+    ///
+    /// Create a new parser for one of multiple sources.
+    /// All positions will refer to the source index.
+    /// 
+    pub fn with_source(src : & 'a str, source : usize) -> Self {
         Parser {
+            src,
             iter: src.chars(), 
             remaining: src, 
-            position: ParserPosition::zero(),
+            position: ParserPosition::zero().with_source(source),
             parsed_tokens: TokenStorage::new(),
             dynamic_state: DynamicParserState::new(),
+            closing_tags: Vec::new(),
+            innermost_closing_tag: MatchableToken::EndOfModule,
+            unclosed: HashSet::new(),
+            ancestor_stops: 0,
+            unclosed_siblings: None,
+            error_nodes: false,
+            latex_delimiters: false,
+        }
+    }
+
+    /** Create a new parser using the config. */
+    pub fn with_config(src : & 'a str, config : &ParseConfig) -> Self {
+        Self {
+            error_nodes: config.error_nodes,
+            latex_delimiters: config.latex_delimiters,
+            ..Self::with_source(src, config.source)
         }
     }
 
@@ -212,6 +804,19 @@ impl<'a> Parser<'a> {
         Some(c)   
     }
 
+    ///
+    /// Moves the current position by n bytes, which must end at a char boundary.
+    /// 
+    fn skip_bytes(&mut self, n : usize) {
+
+        let (skipped, remaining) = self.remaining.split_at(n);
+
+        self.position.advance_over(skipped);
+
+        self.remaining = remaining;
+        self.iter = remaining.chars();
+    }
+
     ///
     /// Moves the current position by n chars
     /// 
@@ -222,6 +827,29 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            position: self.position,
+            tokens: self.parsed_tokens.tokens.len(),
+            errors: self.parsed_tokens.errors.len(),
+            warnings: self.parsed_tokens.warnings.len(),
+        }
+    }
+
+    ///
+    /// Moves back to the checkpoint and discards all tokens and errors found since.
+    ///
+    fn restore(&mut self, checkpoint : Checkpoint) {
+
+        self.position = checkpoint.position;
+        self.remaining = &self.src[checkpoint.position.byte_idx..];
+        self.iter = self.remaining.chars();
+
+        self.parsed_tokens.tokens.truncate(checkpoint.tokens);
+        self.parsed_tokens.errors.truncate(checkpoint.errors);
+        self.parsed_tokens.warnings.truncate(checkpoint.warnings);
+    }
+
     ///
     ///  Moves the iterator to the next unescaped char.
     /// 
@@ -241,95 +869,119 @@ impl<'a> Parser<'a> {
     // Returns the part of the string that matched the token kind.
     // Advances the position by the length of the matched string.
     //
-    fn try_parse_token(&mut self, token: &TokenKind) -> Option<&'a str> {
+    fn try_parse_token(&mut self, token: &MatchableToken) -> Option<&'a str> {
         
         let bytes = self.remaining.as_bytes();
 
+        // the first byte, remaining may be empty
+        let first = bytes.first().copied();
+
         let value = match token {
 
-            TokenKind::EnvOpen => (
-                bytes[0] == b'<' && 
-                bytes.len() > 1 && (
-                    (bytes[1] >= b'a' && bytes[1] <= b'z') ||
-                    (bytes[1] >= b'A' && bytes[1] <= b'Z')
-                )
+            MatchableToken::EnvOpen => (
+                first == Some(b'<') && 
+                bytes.get(1).is_some_and(u8::is_ascii_alphabetic)
             ).then(||&self.remaining[..1]),
 
-            TokenKind::FragmentOpen => self.remaining.starts_with("<>")
-                .then(||"<>"),
+            MatchableToken::FragmentOpen => self.remaining.starts_with("<>")
+                .then_some("<>"),
 
-            TokenKind::FragmentClose => self.remaining.starts_with("</>")
-                .then(||"</>"),
+            MatchableToken::FragmentClose => self.remaining.starts_with("</>")
+                .then_some("</>"),
 
-            TokenKind::Whitespace => {
+            MatchableToken::Whitespace => {
                 let whitespace_len = self.remaining
-                    .chars()
-                    .take_while(|c| c.is_whitespace())
-                    .count();
+                    .char_indices()
+                    .find(|(_, c)| !c.is_whitespace())
+                    .map_or(self.remaining.len(), |(i, _)| i);
 
                 (whitespace_len > 0).then(|| &self.remaining[..whitespace_len])
             },
 
-            TokenKind::EndOfModule => (self.remaining.len() == 1)
-                .then(|| ""),
+            MatchableToken::EndOfModule => self.remaining.is_empty()
+                .then_some(""),
 
 
-            TokenKind::DollarBrace => self.remaining.starts_with("${")
+            MatchableToken::DollarBrace => self.remaining.starts_with("${")
                 .then(|| &self.remaining[..2]),
 
-            TokenKind::RightBrace => (bytes[0] == b'}')
+            MatchableToken::RightBrace => (first == Some(b'}'))
+                .then(|| &self.remaining[..1]),
+
+            MatchableToken::DoubleDollar => self.remaining.starts_with("$$")
+                .then_some("$$"),
+
+            MatchableToken::Dollar => (first == Some(b'$'))
                 .then(|| &self.remaining[..1]),
 
-            TokenKind::Dollar => (bytes[0] == b'$' )
+            MatchableToken::Equals => (first == Some(b'='))
                 .then(|| &self.remaining[..1]),
 
-            TokenKind::Equals => (bytes[0] == b'=' )
+            MatchableToken::Quote => (first == Some(b'"'))
                 .then(|| &self.remaining[..1]),
 
-            TokenKind::Quote => (bytes[0] == b'"' )
+            MatchableToken::SingleQuote => (first == Some(b'\''))
                 .then(|| &self.remaining[..1]),
 
-            TokenKind::EndOfLine => (bytes[0] == b'\n')
+            MatchableToken::EndOfLine => (first == Some(b'\n'))
                 .then(|| &self.remaining[..1]),
 
-            TokenKind::CommentOpen => self.remaining.starts_with("/**")
-                .then(|| "/**"),
+            MatchableToken::CommentOpen => self.remaining.starts_with("/**")
+                .then_some("/**"),
 
-            TokenKind::CommentClose => self.remaining.starts_with("*/")
-                .then(|| "*/"),
+            MatchableToken::CommentClose => self.remaining.starts_with("*/")
+                .then_some("*/"),
 
-            TokenKind::EnvSelfClose => self.remaining.starts_with("/>")
-                .then(|| "/>"),
+            MatchableToken::EnvSelfClose => self.remaining.starts_with("/>")
+                .then_some("/>"),
 
-            TokenKind::RightAngle => self.remaining.starts_with(">")
-                .then(|| ">"),
+            MatchableToken::RightAngle => self.remaining.starts_with(">")
+                .then_some(">"),
 
-            TokenKind::EnvClose(closer) => self.remaining.starts_with(closer).then( 
+            MatchableToken::EnvClose(closer) => self.remaining.starts_with(closer).then( 
                 || &self.remaining[..closer.len()]
             ),
 
-            TokenKind::HeadingOpen => capture_heading_open(self.remaining),
-
-            // These can never be used for matching 
-            // as they would match anything
-            // TODO: split TokenKind into matchable and non-matchable
-            // not using a default match is intentional to ensure that all other cases are handled
-            TokenKind::Text | 
-            TokenKind::Math | 
-            TokenKind::EnvName | 
-            TokenKind::AttrName | 
-            TokenKind::CommentText |
-            TokenKind::StringLiteral |
-            TokenKind::VariableName |
-            TokenKind::Error(_) => unreachable!(
-                "Cannot use non-matchable token for matching."
-            ),
+            MatchableToken::AnyEnvClose => capture_env_close(self.remaining),
+
+            MatchableToken::HeadingOpen => capture_heading_open(self.remaining),
+
+            MatchableToken::ListItemOpen => capture_list_item_open(self.remaining)
+                .filter(|_| self.at_line_start()),
+
+            MatchableToken::DoubleAsterisk => self.remaining.starts_with("**")
+                .then_some("**"),
+
+            // a single '*' must not be the start of "**"
+            MatchableToken::Asterisk => (first == Some(b'*') && bytes.get(1) != Some(&b'*'))
+                .then(|| &self.remaining[..1]),
+
+            MatchableToken::Underscore => (first == Some(b'_'))
+                .then(|| &self.remaining[..1]),
+
+            MatchableToken::LeftBracket => (first == Some(b'['))
+                .then(|| &self.remaining[..1]),
+
+            MatchableToken::RightBracket => (first == Some(b']'))
+                .then(|| &self.remaining[..1]),
+
+            // matched before the backslash is treated as an escape
+            MatchableToken::InlineMathOpen => self.remaining.starts_with("\\(")
+                .then_some("\\("),
+
+            MatchableToken::InlineMathClose => self.remaining.starts_with("\\)")
+                .then_some("\\)"),
+
+            MatchableToken::BlockMathOpen => self.remaining.starts_with("\\[")
+                .then_some("\\["),
+
+            MatchableToken::BlockMathClose => self.remaining.starts_with("\\]")
+                .then_some("\\]"),
         };
 
         match value {
             Some(s) => {
-                // TODO: this will skip n bytes, not n chars as str.len() is in bytes
-                self.skip(s.len());
+                self.skip(s.chars().count());
 
                 Some(s)
             },
@@ -362,40 +1014,48 @@ impl<'a> Parser<'a> {
     /// Same as seek_to but also captures all skipped chars 
     /// in token with captured_kind.
     /// 
-    /// Returns (captured_token_handle, end_token_handle)
+    /// Returns (captured_token_handle, stop)
     /// 
-    /// end_token_handle always points to one of the end_kinds provided or EndOfModule.
+    /// stop is always one of the end_kinds provided or EndOfModule.
     /// 
     /// captured_token_handle may be None if zero chars were captured
     /// 
     fn seek_to_and_capture(
         &mut self, 
-        captured_kind : TokenKind,
-        end_kinds : &[TokenKind],
-    ) -> (Option<TokenHandle>, TokenHandle) {
+        captured_kind : CapturedKind,
+        end_kinds : &[MatchableToken],
+    ) -> (Option<TokenHandle>, Stop<'a>) {
 
-        let prev_position = self.position.clone();
+        let prev_position = self.position;
 
         let prev_remaining = self.remaining;
 
         let end_token = self.seek_to(end_kinds);
         
         let end_position = end_token.as_ref().map(
-            |token| token.position.byte_idx
+            |(_, token)| token.position.byte_idx
         ).unwrap_or(self.position.byte_idx);
 
-        let captured_length = end_position - prev_position.byte_idx;
+        // seek_to never moves backwards and stops at char boundaries, 
+        // should it ever fail to, an empty capture is better than a panic in release builds
+        let captured = end_position
+            .checked_sub(prev_position.byte_idx)
+            .and_then(|captured_length| prev_remaining.get(..captured_length));
+
+        debug_assert!(captured.is_some(), "seek_to stopped before the start or inside of a char");
+
+        let captured = captured.unwrap_or("");
 
-        let captured_handle = (captured_length > 0).then(
+        let captured_handle = (!captured.is_empty()).then(
             || self.push_token(Token { 
-                value: &prev_remaining[..captured_length], 
-                position: prev_position.clone(), 
-                kind: captured_kind
+                value: captured, 
+                position: prev_position, 
+                kind: TokenKind::Captured(captured_kind)
             })
         );
 
-        let end_handle = match end_token {
-            Some(token) => self.push_token(token),
+        let (kind, token) = match end_token {
+            Some(end_token) => end_token,
             None => {
                 self.push_error(
                     ParseError::unexpected_eof(end_kinds),
@@ -405,53 +1065,78 @@ impl<'a> Parser<'a> {
                     ""
                 );
 
-                self.push_token(Token { 
-                    kind: TokenKind::EndOfModule, 
-                    value: "", 
-                    position: self.position.clone()
-                })
+                (
+                    MatchableToken::EndOfModule,
+                    Token { 
+                        kind: TokenKind::Matched(MatchableToken::EndOfModule), 
+                        value: "", 
+                        position: self.position
+                    }
+                )
             },
         };
 
-        (captured_handle, end_handle)
+        let stop = Stop {
+            kind,
+            value: token.value,
+            position: token.position,
+            handle: self.push_token(token),
+        };
+
+        (captured_handle, stop)
     }   
 
     ///
     /// Moves the iterator right behind the first matching token.
-    /// Returns the first matching token.
+    /// Returns the first matching token along with its kind.
     /// 
     /// Returns None if end of input is reached and EndOfModule is not in tokens.
     /// 
-    fn seek_to(&mut self, tokens : &[TokenKind])  -> Option<Token<'a>> {
+    fn seek_to(&mut self, tokens : &[MatchableToken])  -> Option<(MatchableToken, Token<'a>)> {
+
+        let stops = stop_bytes(tokens);
         
-        while self.remaining.len() > 0 {
+        while !self.remaining.is_empty() {
 
             for kind in tokens {
                 
-                let position = self.position.clone();
+                let position = self.position;
 
                 if let Some(value) = self.try_parse_token(kind) {
-                    return Some(
+                    return Some((
+                        kind.clone(),
                         Token {
                             value,
-                            kind: kind.clone(),
+                            kind: TokenKind::Matched(kind.clone()),
                             position
                         }
-                    )
+                    ))
                 }
             }
             
             self.next_unescaped_char();
+
+            // no token can start before the next stop byte, which is ASCII and thus a char boundary
+            if let Some(stops) = &stops {
+
+                let skipped = self.remaining
+                    .bytes()
+                    .position(|b| stops[b as usize])
+                    .unwrap_or(self.remaining.len());
+
+                self.skip_bytes(skipped);
+            }
         }
 
         // return EndOfModule if EndOfModule is if one of tokens
-        tokens.contains(&TokenKind::EndOfModule).then(
-            || Token {
+        tokens.contains(&MatchableToken::EndOfModule).then_some((
+            MatchableToken::EndOfModule,
+            Token {
                 value: "",
-                kind: TokenKind::EndOfModule,
-                position: self.position.clone()
+                kind: TokenKind::Matched(MatchableToken::EndOfModule),
+                position: self.position
             }
-        )
+        ))
 
     }
 
@@ -459,11 +1144,35 @@ impl<'a> Parser<'a> {
 
         self.parsed_tokens.errors.push(Token {
             kind: TokenKind::Error(error),
-            position: position.clone(),
+            position: *position,
+            value
+        });
+    }
+
+    pub fn push_warning(&mut self, warning : ParseError, position : &ParserPosition, value : &'a str) {
+
+        self.parsed_tokens.warnings.push(Token {
+            kind: TokenKind::Error(warning),
+            position: *position,
             value
         });
     }
 
+    ///
+    /// Pushes an error and returns the node representing it in the document if error nodes are enabled.
+    /// 
+    fn push_error_node(&mut self, error : ParseError, position : &ParserPosition, value : &'a str) -> Option<Node> {
+
+        let node = self.error_nodes.then(|| Node::new(
+            NodeKind::Leaf(LeafNode::Error(error.to_string())),
+            NodePosition::Source(Span::new(*position, position.advanced_over(value)))
+        ));
+
+        self.push_error(error, position, value);
+
+        node
+    }
+
     pub fn add_component_definition(&mut self, name : &str, attrs : &EnvNodeAttrs, header_position : &ParserPosition) {
 
         let env_parser_attrs = EnvParseAttrs::from_attrs(attrs);
@@ -489,8 +1198,8 @@ impl<'a> Parser<'a> {
         // TODO: allow nested comments
 
         let (text, _) = self.seek_to_and_capture(
-            TokenKind::CommentText,
-            &[TokenKind::CommentClose]
+            CapturedKind::CommentText,
+            &[MatchableToken::CommentClose]
         );
 
         self.get_captured_value(text)
@@ -502,8 +1211,8 @@ impl<'a> Parser<'a> {
     pub fn parse_variable_expression(&mut self) -> String {
 
         let (token, _) = self.seek_to_and_capture(
-            TokenKind::VariableName,
-            &[TokenKind::RightBrace]
+            CapturedKind::VariableName,
+            &[MatchableToken::RightBrace]
         );
 
         self.get_captured_value(token).to_string()
@@ -514,84 +1223,222 @@ impl<'a> Parser<'a> {
     /// 
     pub fn parse_children(
         &mut self,
-        closing_tag : TokenKind
+        closing_tag : MatchableToken
     ) -> VecDeque<Node> {
+        self.parse_children_until(closing_tag).0
+    }
+
+    ///
+    /// Parse children terminated by closing_tag and returns why parsing has stopped.
+    /// 
+    /// Reaching the closing tag of an enclosing env also stops parsing, leaving 
+    /// the closing tag to the enclosing env. An error is pushed if closing_tag 
+    /// closes an env or fragment. 
+    /// 
+    fn parse_children_until(
+        &mut self,
+        closing_tag : MatchableToken
+    ) -> (VecDeque<Node>, ChildrenEnd) {
 
         let mut children = VecDeque::new();
-        
-        loop {
 
-            let (text, stop_token) = self.seek_to_and_capture(
-                TokenKind::Text,
-                &[
-                    closing_tag.clone(),
-                    TokenKind::FragmentOpen, 
-                    TokenKind::EnvOpen, 
-                    TokenKind::DollarBrace,
-                    TokenKind::Dollar,
-                    TokenKind::CommentOpen,
-                    TokenKind::HeadingOpen
-                ],
-            );
+        let closes_env = matches!(closing_tag, MatchableToken::EnvClose(_) | MatchableToken::FragmentClose);
+
+        let ancestor_tags = self.closing_tags
+            .iter()
+            .filter(|tag| **tag != closing_tag)
+            .cloned()
+            .collect::<Vec<MatchableToken>>();
+
+        if closes_env {
+            self.closing_tags.push(closing_tag.clone());
+        }
+
+        let enclosing_tag = std::mem::replace(&mut self.innermost_closing_tag, closing_tag.clone());
+
+        let end_kinds = [
+            closing_tag.clone(),
+            MatchableToken::FragmentOpen, 
+            MatchableToken::EnvOpen, 
+            MatchableToken::DollarBrace,
+            // must be matched before two inline equations
+            MatchableToken::DoubleDollar,
+            MatchableToken::Dollar,
+            MatchableToken::CommentOpen,
+            MatchableToken::HeadingOpen,
+            MatchableToken::ListItemOpen,
+            MatchableToken::DoubleAsterisk,
+            MatchableToken::Asterisk,
+            MatchableToken::Underscore,
+            MatchableToken::LeftBracket,
+        ]
+            .into_iter()
+            .chain(ancestor_tags.iter().cloned())
+            .chain(
+                self.latex_delimiters
+                    .then_some([MatchableToken::InlineMathOpen, MatchableToken::BlockMathOpen])
+                    .into_iter()
+                    .flatten()
+            )
+            // closing tags that close neither this nor an enclosing env are mismatched
+            .chain((!self.closing_tags.is_empty()).then_some(MatchableToken::AnyEnvClose))
+            // a line may also be ended by the end of input
+            .chain((closing_tag == MatchableToken::EndOfLine).then_some(MatchableToken::EndOfModule))
+            .collect::<Vec<MatchableToken>>();
+        
+        let end = loop {
 
-            let stop_token = self.get_token(stop_token);
+            let (text, stop) = self.seek_to_and_capture(CapturedKind::Text, &end_kinds);
 
             if let Some(text) = text {
-                children.push_back(Node::new_text(self.get_token(text)))
+                children.push_back(decoded_text(self.get_token(text)))
             }
 
-            let stop_kind = stop_token.kind.clone();
-            let stop_position = stop_token.position.clone();
+            let Stop { kind: stop_kind, position: stop_position, value: stop_value, handle: stop_handle } = stop;
 
             let kind = match stop_kind {
 
-                _ if stop_kind == closing_tag => break,
+                _ if stop_kind == closing_tag => break ChildrenEnd::Closed,
+
+                // leave the closing tag to the ancestor
+                _ if ancestor_tags.contains(&stop_kind) => {
+
+                    self.restore(Checkpoint { 
+                        position: stop_position, 
+                        tokens: stop_handle.0, 
+                        errors: self.parsed_tokens.errors.len(),
+                        warnings: self.parsed_tokens.warnings.len(),
+                    });
+
+                    if closes_env {
+
+                        let name = match &closing_tag {
+                            MatchableToken::EnvClose(closer) => closer.trim_start_matches("</").trim_end_matches('>'),
+                            _ => "",
+                        };
+
+                        children.extend(self.push_error_node(ParseError::env_not_closed(name), &stop_position, ""));
+                    }
+
+                    break ChildrenEnd::Ancestor;
+                },
+
+                // the mismatched tag closes the env, anything else is left to the env it belongs to
+                MatchableToken::AnyEnvClose => {
+
+                    if closes_env {
+
+                        let expected = match &closing_tag {
+                            MatchableToken::EnvClose(closer) => closer.as_str(),
+                            _ => "</>",
+                        };
+
+                        children.extend(self.push_error_node(
+                            ParseError::mismatched_closing_tag(expected, stop_value), 
+                            &stop_position, 
+                            stop_value
+                        ));
+
+                        break ChildrenEnd::Closed;
+                    }
+
+                    self.restore(Checkpoint { 
+                        position: stop_position, 
+                        tokens: stop_handle.0, 
+                        errors: self.parsed_tokens.errors.len(),
+                        warnings: self.parsed_tokens.warnings.len(),
+                    });
+
+                    break ChildrenEnd::Ancestor;
+                },
                 
-                TokenKind::HeadingOpen => NodeKind::heading(stop_token.value.len(), self.parse_children(TokenKind::EndOfLine)),
+                // the marker is "#" repeated level + 1 times followed by whitespace
+                MatchableToken::HeadingOpen => {
+
+                    let mut children = self.parse_children(MatchableToken::EndOfLine);
+
+                    let attrs = take_heading_attrs(&mut children);
+
+                    NodeKind::Env(EnvNode::new_open(
+                        EnvNodeHeader::from_kind(
+                            EnvNodeHeaderKind::Heading(stop_value.trim_end_matches([' ', '\t']).len() - 1), 
+                            attrs,
+                        ),
+                        children
+                    ))
+                },
 
-                TokenKind::FragmentOpen => {
-                    NodeKind::new_fragment(self.parse_children(TokenKind::FragmentClose))
+                MatchableToken::FragmentOpen => {
+                    NodeKind::new_fragment(self.parse_children(MatchableToken::FragmentClose))
                 },
 
-                TokenKind::EnvOpen => NodeKind::Env(self.parse_env_from_name()),
+                MatchableToken::ListItemOpen => self.parse_list(stop_value, &stop_position),
+
+                MatchableToken::EnvOpen => NodeKind::Env(self.parse_env_from_name()),
 
-                TokenKind::DollarBrace => NodeKind::Leaf(LeafNode::VariableExpression(
+                MatchableToken::DollarBrace => NodeKind::Leaf(LeafNode::VariableExpression(
                     self.parse_variable_expression()
                 )),
 
-                TokenKind::Dollar => {
+                MatchableToken::DoubleDollar => self.parse_math(EquationKind::Block, MatchableToken::DoubleDollar),
 
-                    let (math, _) = self.
-                        seek_to_and_capture(
-                            TokenKind::Math,
-                            &[TokenKind::Dollar]
-                        );
-                   
-                    let header_kind = EnvNodeHeaderKind::Eq(EquationKind::Inline);
-
-                    NodeKind::Env(
-                        EnvNode{
-                            header: EnvNodeHeader{
-                                kind: header_kind, 
-                                attrs: EnvNodeAttrs::new(), 
-                            }, 
-                            kind: EnvNodeKind::Open(
-                                match math {
-                                    Some(token_handle) => VecDeque::from([Node::new_text(self.get_token(token_handle))]),
-                                    None => VecDeque::new()
-                                }
-                            ) 
-                        }
-                    )
-                },
+                MatchableToken::Dollar => self.parse_math(EquationKind::Inline, MatchableToken::Dollar),
+
+                MatchableToken::InlineMathOpen => self.parse_math(EquationKind::Inline, MatchableToken::InlineMathClose),
 
-                TokenKind::CommentOpen => NodeKind::Leaf(
+                MatchableToken::BlockMathOpen => self.parse_math(EquationKind::Block, MatchableToken::BlockMathClose),
+
+                MatchableToken::CommentOpen => NodeKind::Leaf(
                     LeafNode::Comment(self.parse_comment().to_string())
                 ),
 
-                // should be fine to do nothing as a parser error should have been pushed
-                TokenKind::EndOfModule => {
-                    return children;
+                MatchableToken::DoubleAsterisk | 
+                MatchableToken::Asterisk | 
+                MatchableToken::Underscore => {
+
+                    // '_' within words (snake_case) is not an emphasis
+                    let intraword = stop_kind == MatchableToken::Underscore && text.is_some_and(
+                        |text| self.get_token(text).value.ends_with(char::is_alphanumeric)
+                    );
+
+                    if intraword || !emphasis_closes(self.remaining, stop_value) {
+                        // unmatched markers are treated as literal text
+                        NodeKind::Leaf(LeafNode::Text(stop_value.to_string()))
+                    } else {
+                        let name = match stop_kind {
+                            MatchableToken::DoubleAsterisk => "strong",
+                            _ => "em",
+                        };
+
+                        NodeKind::Env(EnvNode::new_open(
+                            EnvNodeHeader::new(name, EnvNodeAttrs::new()),
+                            self.parse_children(stop_kind)
+                        ))
+                    }
+                },
+
+                MatchableToken::LeftBracket => match capture_link(self.remaining) {
+                    Some((_, url)) => self.parse_link(url),
+                    // brackets without a destination are treated as literal text
+                    None => NodeKind::Leaf(LeafNode::Text(stop_value.to_string())),
+                },
+
+                MatchableToken::EndOfModule => {
+
+                    // the error pushed by seek_to_and_capture lists every token the children stop at,
+                    // but only the closing tags are expected
+                    if !end_kinds.contains(&MatchableToken::EndOfModule) {
+                        if let Some(eof) = self.parsed_tokens.errors.pop() {
+
+                            let expected = std::iter::once(closing_tag.clone())
+                                .chain(ancestor_tags.iter().cloned())
+                                .collect::<Vec<MatchableToken>>();
+
+                            self.push_error(ParseError::unexpected_eof(&expected), &eof.position, eof.value);
+                        }
+                    }
+
+                    break ChildrenEnd::EndOfModule
                 },
 
                 // token can only be one of the kinds passed to 
@@ -599,41 +1446,331 @@ impl<'a> Parser<'a> {
                 // should not happen
                 _ => unreachable!()
             };
-            
-            children.push_back(
-                Node::new(kind, NodePosition::Source(stop_position))
-            );
+
+            self.push_child(&mut children, kind, stop_position);
+        };
+
+        if closes_env {
+            self.closing_tags.pop();
+        }
+
+        self.innermost_closing_tag = enclosing_tag;
+
+        if end == ChildrenEnd::Ancestor {
+            self.ancestor_stops += 1;
         }
         
-        children
+        (children, end)
+    }
+
+    ///
+    /// Appends a node parsed by parse_children_until, followed by the siblings 
+    /// handed over by an unclosed env.
+    /// 
+    fn push_child(&mut self, children : &mut VecDeque<Node>, kind : NodeKind, start : ParserPosition) {
+
+        let (end, siblings) = self.unclosed_siblings
+            .take()
+            .unwrap_or((self.position, VecDeque::new()));
+
+        let node = Node::new(kind, NodePosition::Source(Span::new(start, end)));
+
+        // nested unclosed envs hand over all following siblings at every level, 
+        // so the shorter list is moved into the longer one
+        if siblings.len() > children.len() {
+
+            let mut siblings = siblings;
+
+            siblings.push_front(node);
+
+            while let Some(child) = children.pop_back() {
+                siblings.push_front(child);
+            }
+
+            *children = siblings;
+        } else {
+            children.push_back(node);
+            children.extend(siblings);
+        }
+    }
+
+    ///
+    /// Parse an equation starting right after the opening delimiter.
+    /// The contents up to closing_tag are kept as they are.
+    /// 
+    fn parse_math(&mut self, kind : EquationKind, closing_tag : MatchableToken) -> NodeKind {
+
+        let (math, _) = self.seek_to_and_capture(CapturedKind::Math, &[closing_tag]);
+
+        NodeKind::Env(
+            EnvNode{
+                header: EnvNodeHeader::from_kind(EnvNodeHeaderKind::Eq(kind), EnvNodeAttrs::new()), 
+                kind: EnvNodeKind::Open(
+                    match math {
+                        Some(token_handle) => VecDeque::from([Node::new_text(self.get_token(token_handle))]),
+                        None => VecDeque::new()
+                    }
+                ) 
+            }
+        )
+    }
+
+    ///
+    /// Parse a link "[label](url)" starting right after the opening bracket.
+    /// url is the destination found by capture_link.
+    /// 
+    pub fn parse_link(&mut self, url : &'a str) -> NodeKind {
+
+        let label = self.parse_children(MatchableToken::RightBracket);
+
+        // skip the opening parenthesis
+        self.skip(1);
+
+        let url_position = self.position;
+
+        self.push_token(Token {
+            value: url,
+            kind: TokenKind::Captured(CapturedKind::StringLiteral),
+            position: url_position,
+        });
+
+        // skip the url and the closing parenthesis
+        self.skip(url.chars().count() + 1);
+
+        let href = Node::new(
+            NodeKind::Leaf(LeafNode::Text(unescape(url))),
+            NodePosition::Source(Span::new(url_position, url_position.advanced_over(url)))
+        );
+
+        NodeKind::Env(EnvNode::new_open(
+            EnvNodeHeader::new("a", EnvNodeAttrs::from([("href".to_string(), Some(href))])),
+            label
+        ))
+    }
+
+    ///
+    /// Checks if only whitespace precedes the current position in the current line.
+    /// 
+    fn at_line_start(&self) -> bool {
+        self.src[..self.position.byte_idx]
+            .rsplit('\n')
+            .next()
+            .is_none_or(|line| line.chars().all(char::is_whitespace))
+    }
+
+    ///
+    /// Checks if the next line is a list item without advancing the position.
+    /// The parser must be positioned at the start of the line.
+    /// Returns the indentation (in chars) and the marker of the item.
+    /// 
+    fn peek_list_item(&self) -> Option<(usize, &'a str)> {
+
+        let indent = self.remaining
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .count();
+
+        // indentation only contains single byte chars
+        capture_list_item_open(&self.remaining[indent..]).map(|marker| (indent, marker))
+    }
+
+    ///
+    /// Skips the indentation of a list item found by peek_list_item and parses its marker.
+    /// 
+    fn next_list_item(&mut self, indent : usize) -> (&'a str, ParserPosition) {
+
+        self.skip(indent);
+
+        let position = self.position;
+
+        // peek_list_item has found the marker, so this matches
+        let marker = self.try_parse_token(&MatchableToken::ListItemOpen).unwrap_or("");
+
+        self.push_token(Token {
+            value: marker,
+            kind: TokenKind::Matched(MatchableToken::ListItemOpen),
+            position,
+        });
+
+        (marker, position)
+    }
+
+    ///
+    /// Parse a list starting right after the marker of its first item. 
+    /// Consecutive items with the same indentation and list kind are grouped into one list.
+    /// Items with a deeper indentation are parsed as nested lists.
+    /// A blank line or a line that isn't a list item ends the list.
+    /// 
+    pub fn parse_list(&mut self, marker : &str, position : &ParserPosition) -> NodeKind {
+
+        let ordered = is_ordered_list_marker(marker);
+
+        let indent = position.col();
+
+        let mut items = VecDeque::new();
+
+        let mut item_position = *position;
+
+        loop {
+            // items end with the line
+            let mut item_children = self.parse_children(MatchableToken::EndOfLine);
+
+            // nested lists belong to the current item
+            while let Some((nested_indent, _)) = self.peek_list_item().filter(
+                |(nested_indent, _)| *nested_indent > indent
+            ) {
+                let (nested_marker, nested_position) = self.next_list_item(nested_indent);
+
+                let nested_list = self.parse_list(nested_marker, &nested_position);
+
+                item_children.push_back(Node::new(
+                    nested_list,
+                    NodePosition::Source(Span::new(nested_position, self.position))
+                ));
+            }
+
+            items.push_back(Node::new(
+                NodeKind::Env(EnvNode::new_open(
+                    EnvNodeHeader::new("li", EnvNodeAttrs::new()),
+                    item_children
+                )),
+                NodePosition::Source(Span::new(item_position, self.position))
+            ));
+
+            match self.peek_list_item() {
+                Some((next_indent, next_marker)) 
+                    if next_indent == indent && is_ordered_list_marker(next_marker) == ordered => {
+                    let (_, next_position) = self.next_list_item(next_indent);
+                    item_position = next_position;
+                },
+                _ => break
+            }
+        }
+
+        NodeKind::Env(EnvNode::new_open(
+            EnvNodeHeader::new(if ordered { "ol" } else { "ul" }, EnvNodeAttrs::new()),
+            items
+        ))
+    }
+
+    ///
+    /// Parse a quoted attribute value starting after the opening quote.
+    /// Values without variable expressions are parsed into a single text node.
+    /// Values containing ${...} are parsed into a fragment of text and expression nodes.
+    /// If the quote is never closed, the value is an error node (if enabled).
+    /// 
+    fn parse_attr_value(&mut self, quote : &Stop<'a>, fallback_position : &ParserPosition) -> Node {
+
+        let value_position = self.position;
+
+        let mut parts = VecDeque::new();
+
+        loop {
+            let (captured, stop) = self.seek_to_and_capture(
+                CapturedKind::StringLiteral,
+                &[quote.kind.clone(), MatchableToken::DollarBrace]
+            );
+
+            if let Some(captured) = captured {
+                parts.push_back(decoded_text(self.get_token(captured)));
+            }
+
+            match stop.kind {
+                MatchableToken::DollarBrace => {
+
+                    let position = stop.position;
+
+                    let expression = self.parse_variable_expression();
+
+                    parts.push_back(Node::new(
+                        NodeKind::Leaf(LeafNode::VariableExpression(expression)),
+                        NodePosition::Source(Span::new(position, self.position))
+                    ));
+                },
+                MatchableToken::EndOfModule => {
+
+                    // the error for reaching the end of the module is replaced by a more specific one
+                    self.parsed_tokens.errors.pop();
+
+                    if let Some(node) = self.push_error_node(ParseError::quote_not_closed(&quote.kind), &quote.position, quote.value) {
+                        return node;
+                    }
+
+                    break;
+                },
+                // closing quote
+                _ => break
+            }
+        }
+
+        let has_expressions = parts.iter().any(
+            |part| matches!(part.kind, NodeKind::Leaf(LeafNode::VariableExpression(_)))
+        );
+
+        match parts.pop_front() {
+            Some(text) if parts.is_empty() && !has_expressions => text,
+            // empty strings are not captured as tokens
+            None => Node::new(
+                NodeKind::Leaf(LeafNode::Text(String::new())),
+                NodePosition::Source(Span::at(*fallback_position))
+            ),
+            Some(first) => {
+                parts.push_front(first);
+
+                let span = Span::new(value_position, self.position);
+
+                Node::new(NodeKind::new_fragment(parts), NodePosition::Source(span))
+            }
+        }
+    }
+
+    ///
+    /// Inserts an attribute of an env header.
+    /// If the attribute exists already, its value is replaced (last wins, keeping the position 
+    /// of the first) and a warning is pushed at the duplicate name.
+    /// 
+    fn insert_attr(&mut self, attrs : &mut EnvNodeAttrs, key_handle : Option<TokenHandle>, key : String, value : Option<Node>) {
+
+        if let Some(key_handle) = key_handle.filter(|_| attrs.contains_key(&key)) {
+
+            let Token { position, value, .. } = *self.get_token(key_handle);
+
+            self.push_warning(ParseError::duplicate_attr(&key), &position, value);
+        }
+
+        attrs.insert(key, value);
     }
 
     ///
-    /// Parse env header attributes after the env name
+    /// Parse env header attributes after the env name.
+    /// Returns the attributes, the quotes of their values, and the token that ended the header.
     /// 
-    pub fn parse_env_header_attrs(&mut self) -> (EnvNodeAttrs, TokenKind) {
+    pub fn parse_env_header_attrs(&mut self) -> (EnvNodeAttrs, HashMap<String, QuoteStyle>, MatchableToken) {
 
         let mut attrs = EnvNodeAttrs::new();
 
+        let mut quotes = HashMap::new();
+
         loop {
 
-            let (key, end_token) = self.seek_to_and_capture(
-                TokenKind::AttrName,
+            let (key, end) = self.seek_to_and_capture(
+                CapturedKind::AttrName,
                 &[
-                    TokenKind::Equals,
-                    TokenKind::Whitespace,
-                    TokenKind::EnvSelfClose,
-                    TokenKind::RightAngle,
+                    MatchableToken::Equals,
+                    MatchableToken::Whitespace,
+                    MatchableToken::EnvSelfClose,
+                    MatchableToken::RightAngle,
                 ]
             );
 
-            let end_token = self.get_token(end_token);
+            let end_position = end.position;
 
-            let end_position = end_token.position.clone();
+            match end.kind {
 
-            match end_token.kind {
+                MatchableToken::Equals => {
+
+                    let key_handle = key;
 
-                TokenKind::Equals => {
                     let key = match key {
                         Some(key) => self.get_token(key).value.to_string(),
                         None => {
@@ -649,47 +1786,66 @@ impl<'a> Parser<'a> {
                     };
 
                     // skip whitespace until the opening quote
-                    self.seek_to_and_capture(
-                        TokenKind::Whitespace,
-                        &[TokenKind::Quote]
+                    let (_, quote) = self.seek_to_and_capture(
+                        CapturedKind::Whitespace,
+                        &[
+                            MatchableToken::Quote, 
+                            MatchableToken::SingleQuote,
+                            MatchableToken::EnvSelfClose,
+                            MatchableToken::RightAngle,
+                        ]
                     );
 
-                    let (captured, _) = self.seek_to_and_capture(
-                        TokenKind::StringLiteral,
-                        &[TokenKind::Quote]
-                    );
+                    if !matches!(quote.kind, MatchableToken::Quote | MatchableToken::SingleQuote) {
 
-                    // this is kind of ugly but required since seek_to_and_capture will not register empty strings as Text tokens...
-                    let fallback = Token {
-                        position: end_position.clone(),
-                        kind: TokenKind::Text,
-                        value: ""
+                        // keep the errors in source order, the header is never closed if the module ended
+                        let eof = (quote.kind == MatchableToken::EndOfModule)
+                            .then(|| self.parsed_tokens.errors.pop())
+                            .flatten();
+
+                        let value = self.push_error_node(ParseError::missing_attr_value(), &end_position, end.value);
+
+                        self.parsed_tokens.errors.extend(eof);
+
+                        self.insert_attr(&mut attrs, key_handle, key, value);
+
+                        // the header is closed by the token that was expected to be the quote
+                        return (attrs, quotes, quote.kind);
+                    }
+
+                    // the value is closed by the same kind of quote
+                    let value = self.parse_attr_value(&quote, &end_position);
+
+                    let style = match quote.kind {
+                        MatchableToken::SingleQuote => QuoteStyle::Single,
+                        _ => QuoteStyle::Double,
                     };
 
-                    let value = captured.map(|c| self.get_token(c)).unwrap_or(&fallback);
+                    quotes.insert(key.clone(), style);
 
-                    attrs.insert(key, Some(Node::new_text(value)));
+                    self.insert_attr(&mut attrs, key_handle, key, Some(value));
 
                     // skip any whitespace after the value
-                    self.try_parse_token(&TokenKind::Whitespace);
+                    self.try_parse_token(&MatchableToken::Whitespace);
 
                 },
 
-                TokenKind::EnvSelfClose | 
-                TokenKind::RightAngle | 
-                TokenKind::Whitespace | 
-                TokenKind::EndOfModule => {
+                MatchableToken::EnvSelfClose | 
+                MatchableToken::RightAngle | 
+                MatchableToken::Whitespace | 
+                MatchableToken::EndOfModule => {
 
-                    if let Some(key) = key {
-                        let key = self.get_token(key).value.to_string();
+                    if let Some(key_handle) = key {
+                        let key = self.get_token(key_handle).value.to_string();
 
-                        attrs.insert(key, None);
+                        self.insert_attr(&mut attrs, Some(key_handle), key, None);
                     } 
                     
-                    match end_token.kind {
+                    match end.kind {
 
-                        TokenKind::EnvSelfClose | TokenKind::RightAngle => {
-                            return (attrs, end_token.kind.clone());
+                        // an error has been pushed if the header is never closed
+                        MatchableToken::EnvSelfClose | MatchableToken::RightAngle | MatchableToken::EndOfModule => {
+                            return (attrs, quotes, end.kind);
                         },
 
                         _ => { }
@@ -707,15 +1863,15 @@ impl<'a> Parser<'a> {
     /// Parse an env node header starting from the name. 
     /// Example input: "Eq>", "Eq label='eq:my_equation'>"
     /// 
-    pub fn parse_env_header_from_name(&mut self) -> (EnvNodeHeader, TokenKind) {
+    pub fn parse_env_header_from_name(&mut self) -> (EnvNodeHeader, MatchableToken) {
 
-        let (name, stop_token) = self
+        let (name, stop) = self
             .seek_to_and_capture(
-                TokenKind::EnvName,
+                CapturedKind::EnvName,
                 &[
-                    TokenKind::Whitespace,
-                    TokenKind::EnvSelfClose, 
-                    TokenKind::RightAngle, 
+                    MatchableToken::Whitespace,
+                    MatchableToken::EnvSelfClose, 
+                    MatchableToken::RightAngle, 
                 ]
             );
 
@@ -723,42 +1879,39 @@ impl<'a> Parser<'a> {
         // EnvOpen only matches if followed by a letter
         let name = self.get_token(name.unwrap()).value;
 
-        let attrs_position = self.position.clone();
+        let attrs_position = self.position;
         
         let mut header = EnvNodeHeader::new_default(name);
         
-        let stop_kind = self.get_token(stop_token).kind.clone();
-
-        let stop_kind = if stop_kind == TokenKind::Whitespace {
+        let stop_kind = if stop.kind == MatchableToken::Whitespace {
                 
-            let (attrs, stop_kind_after_attrs) = self.parse_env_header_attrs();
+            let (attrs, quotes, stop_kind_after_attrs) = self.parse_env_header_attrs();
 
             for (key, value) in attrs {
                 header.attrs.insert(key, value);
             }
 
+            header.quotes = quotes;
+
             stop_kind_after_attrs
         } else {
 
-            stop_kind
+            stop.kind
         };
 
         // a component is has been defined, insert the components parsing attributes
-        match &header.kind {
-            EnvNodeHeaderKind::ComponentDefinition => {
-                let name = component_name_definition_attrs(&header.attrs);
-
-                match name {
-                    Some(name) 
-                        => self.add_component_definition(name, &header.attrs, &attrs_position),
-                    None => self.push_error(
-                        ParseError::missing_attr_name(),
-                        &attrs_position,
-                        "TODO"
-                    )
-                };
-            },
-            _ => {} 
+        if header.kind == EnvNodeHeaderKind::ComponentDefinition {
+            let name = component_name_definition_attrs(&header.attrs);
+
+            match name {
+                Some(name) 
+                    => self.add_component_definition(name, &header.attrs, &attrs_position),
+                None => self.push_error(
+                    ParseError::missing_attr_name(),
+                    &attrs_position,
+                    "TODO"
+                )
+            };
         };
 
         (header, stop_kind)
@@ -769,29 +1922,53 @@ impl<'a> Parser<'a> {
     /// Begins parsing an environment node right after the '<'
     /// Example input: "Document></Document>"
     /// 
+    /// An env that is not closed before the end of the module is closed right after 
+    /// its header and the source after the header is parsed as its siblings.
+    /// 
     pub fn parse_env_from_name(&mut self) -> EnvNode {
 
+        // position of the '<'
+        let start = ParserPosition { 
+            col: self.position.col.saturating_sub(1), 
+            byte_idx: self.position.byte_idx.saturating_sub(1), 
+            ..self.position 
+        };
+
         let (header, stop_token) = self.parse_env_header_from_name();
 
         let parse_options = self.dynamic_state.get_env_parse_attrs(&header.kind);
 
+        let strict = matches!(parse_options.content(), ContentParseMode::RawStrict);
+
         match stop_token {
 
-            TokenKind::EnvSelfClose => EnvNode::new_self_closing(header),
+            MatchableToken::EnvSelfClose => EnvNode::new_self_closing(header),
+
+            // <br> does not need to be closed
+            MatchableToken::RightAngle if is_void_element(&header.kind) => EnvNode::new_self_closing(header),
 
-            TokenKind::RightAngle =>  {
+            MatchableToken::RightAngle =>  {
                 let children = match parse_options.content() {
                     // parse children as nodes
-                    ContentParseMode::Vtx => self.parse_children(
-                        TokenKind::new_env_close(&header.kind)
-                    ),
+                    ContentParseMode::Vtx => self.parse_children_or_recover(&header.kind, start),
                     // parse children as one big string of text
-                    ContentParseMode::Raw => {
-                        let closing_tag = TokenKind::new_env_close(&header.kind);
+                    ContentParseMode::Raw | ContentParseMode::RawStrict => {
+
+                        if strict {
+                            let position = self.position;
+
+                            self.push_error(
+                                ParseError::todo("content=\"raw-strict\" is not implemented yet, parsing as raw."),
+                                &position,
+                                ""
+                            );
+                        }
+
+                        let closing_tag = MatchableToken::new_env_close(&header.kind);
                     
                         let (text, _) = self.seek_to_and_capture(
-                            TokenKind::Text,
-                            &[closing_tag.clone()],
+                            CapturedKind::Text,
+                            std::slice::from_ref(&closing_tag),
                         );
                         if let Some(text) = text {
                             VecDeque::from([Node::new_text(self.get_token(text))])
@@ -799,59 +1976,389 @@ impl<'a> Parser<'a> {
                             VecDeque::new()
                         }
                     },
-                    ContentParseMode::RawStrict => todo!("Not implemented yet")
                 };
 
                 EnvNode::new_open(header, children)
             },
 
-            // kind can only be one of the variants passed to seek_to_and_capture
-            _ => unreachable!()
+            // the header was never closed, an error has been pushed
+            _ => EnvNode::new_self_closing(header)
         }
 
     }
 
     ///
-    /// Returns document node.
-    /// Parses entire document.
+    /// Parses the children of an env that starts at start.
+    /// If the env is not closed, it ends after its header and no children are returned.
     /// 
-    fn parse_document(&mut self) -> Node {
+    fn parse_children_or_recover(&mut self, kind : &EnvNodeHeaderKind, start : ParserPosition) -> VecDeque<Node> {
 
-        let children = self.parse_children(
-            TokenKind::EndOfModule
-        );
+        // the env is known to be unclosed from parsing it before
+        if self.unclosed.contains(&start.byte_idx) {
+            return self.push_error_node(ParseError::env_not_closed(kind.get_name()), &start, "")
+                .into_iter()
+                .collect();
+        }
 
-        Node::new(
-            NodeKind::Env(EnvNode::new_module(children)),
-            NodePosition::Source(ParserPosition::zero())
-        )
-    }
-    
-}
+        let checkpoint = self.checkpoint();
 
-pub fn parse(src : &str) -> (Node, TokenStorage) {
-    
-    let mut parser = Parser::new(src);
+        // the closing tag of the parent, before parsing the children replaces it
+        let parent_closing_tag = self.innermost_closing_tag.clone();
 
-    let document = parser.parse_document();
+        let ancestor_stops = self.ancestor_stops;
 
-    (document, parser.parsed_tokens)
-}
+        match self.parse_children_until(MatchableToken::new_env_close(kind)) {
+            (children, ChildrenEnd::EndOfModule) => {
 
-#[cfg(test)]
-mod tests {
+                // see recover_unclosed
+                let reuse_children = self.ancestor_stops == ancestor_stops && matches!(
+                    parent_closing_tag, 
+                    MatchableToken::EnvClose(_) | MatchableToken::FragmentClose | MatchableToken::EndOfModule
+                );
 
-    use super::*;
+                self.recover_unclosed(kind, start, checkpoint, reuse_children.then_some(children))
+            },
+            (children, _) => children,
+        }
+    }
+
+    ///
+    /// Closes an env that has reached the end of the module right after its header.
+    /// Returns its children, which are an error node at most.
+    /// 
+    /// The source after the header has to be parsed as the siblings of the env.
+    /// If the parent is an env and no closing tag has been left to an ancestor, 
+    /// the children that have been parsed already are the same as these siblings. 
+    /// They are handed to the parent (see unclosed_siblings) instead of being parsed again, 
+    /// which would take quadratic time for nested unclosed envs.
+    /// Otherwise (e.g. in a heading, which ends at tokens the env does not stop at),
+    /// the source is parsed again.
+    /// 
+    fn recover_unclosed(
+        &mut self, 
+        kind : &EnvNodeHeaderKind, 
+        start : ParserPosition, 
+        checkpoint : Checkpoint, 
+        children : Option<VecDeque<Node>>,
+    ) -> VecDeque<Node> {
+
+        self.unclosed.insert(start.byte_idx);
+
+        let Some(children) = children else {
+
+            self.restore(checkpoint);
+
+            return self.push_error_node(ParseError::env_not_closed(kind.get_name()), &start, "")
+                .into_iter()
+                .collect();
+        };
+
+        self.unclosed_siblings = Some((checkpoint.position, children));
+
+        // the error for reaching the end of the module is replaced by a more specific one
+        let eof = self.parsed_tokens.errors.pop();
+
+        debug_assert!(eof.is_some_and(|token| matches!(
+            token.kind, 
+            TokenKind::Error(ParseError { kind: ParseErrorKind::EnvNotClosed, .. })
+        )));
+
+        let node = self.push_error_node(ParseError::env_not_closed(kind.get_name()), &start, "");
+
+        // keep the errors in source order, the errors of the children have been pushed already
+        if let Some(error) = self.parsed_tokens.errors.pop() {
+            self.parsed_tokens.errors.insert(checkpoint.errors, error);
+        }
+
+        node.into_iter().collect()
+    }
+
+    ///
+    /// Returns document node.
+    /// Parses entire document.
+    /// 
+    fn parse_document(&mut self) -> Node {
+
+        let attrs = self.parse_frontmatter();
+
+        let children = self.parse_children(
+            MatchableToken::EndOfModule
+        );
+
+        let mut module = EnvNode::new_module(children);
+
+        module.header.attrs = attrs;
+
+        Node::new(
+            NodeKind::Env(module),
+            NodePosition::Source(Span::new(ParserPosition::zero().with_source(self.position.source), self.position))
+        )
+    }
+
+    ///
+    /// Parses the metadata block at the start of a source into attributes of the module:
+    ///
+    /// ---
+    /// title: My Document
+    /// lang: "en"
+    /// ---
+    ///
+    /// Empty lines and lines starting with # are ignored.
+    /// Nothing is consumed if the source does not start with a block.
+    /// An unterminated block is reported as an error and parsed as regular content.
+    ///
+    pub fn parse_frontmatter(&mut self) -> EnvNodeAttrs {
+
+        let mut attrs = EnvNodeAttrs::new();
+
+        if self.position.byte_idx != 0 
+            || !(self.remaining.starts_with("---\n") || self.remaining.starts_with("---\r\n")) {
+            return attrs;
+        }
+
+        let mut lines = self.remaining.split_inclusive('\n');
+
+        let opening = lines.next().unwrap_or_default();
+
+        let Some(body_len) = lines.clone().position(|line| line.trim_end() == "---") else {
+
+            let position = self.position;
+
+            self.push_error(ParseError::frontmatter_not_closed(), &position, &opening[..3]);
+
+            return attrs;
+        };
+
+        self.skip(opening.chars().count());
+
+        for line in lines.clone().take(body_len) {
+
+            let content = line.trim();
+
+            let Some((key, value)) = content.split_once(':').filter(|_| !content.starts_with('#')) else {
+
+                if !content.is_empty() && !content.starts_with('#') {
+                    let position = self.position;
+                    self.push_error(ParseError::invalid_frontmatter_line(), &position, line.trim_end());
+                }
+
+                self.skip(line.chars().count());
+
+                continue;
+            };
+
+            // move to the start of the value in order to record its position
+            let value_offset = line.len() - line.trim_start().len() + key.len() + 1;
+            let value_offset = value_offset + value.len() - value.trim_start().len();
+
+            self.skip(line[..value_offset].chars().count());
+
+            let value = value.trim();
+
+            let value = value
+                .strip_prefix('"').and_then(|value| value.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')))
+                .unwrap_or(value);
+
+            attrs.insert(
+                key.trim().to_string(),
+                Some(Node::new(
+                    NodeKind::Leaf(LeafNode::Text(value.to_string())),
+                    NodePosition::Source(Span::new(self.position, self.position.advanced_over(value)))
+                ))
+            );
+
+            self.skip(line[value_offset..].chars().count());
+        }
+
+        // closing delimiter
+        if let Some(closing) = lines.nth(body_len) {
+            self.skip(closing.chars().count());
+        }
+
+        attrs
+    }
+    
+}
+
+pub fn parse(src : &str) -> (Node, TokenStorage<'_>) {
+    parse_with_config(src, &ParseConfig::default())
+}
+
+pub fn parse_with_config<'a>(src : &'a str, config : &ParseConfig) -> (Node, TokenStorage<'a>) {
+    
+    let mut parser = Parser::with_config(src, config);
+
+    let document = parser.parse_document();
+
+    (document, parser.parsed_tokens)
+}
+
+///
+/// Parses multiple sources into one module containing the children of all sources in order.
+/// Positions refer to the index of their source in sources. 
+/// Returns the tokens of each source.
+/// 
+pub fn parse_sources<'a>(sources : &[&'a str]) -> (Node, Vec<TokenStorage<'a>>) {
+
+    let mut children = VecDeque::new();
+
+    let mut tokens = Vec::with_capacity(sources.len());
+
+    for (i, src) in sources.iter().enumerate() {
+
+        let mut parser = Parser::with_source(src, i);
+
+        let document = parser.parse_document();
+
+        if let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(mut document_children), .. }) = document.kind {
+            children.append(&mut document_children);
+        }
+
+        tokens.push(parser.parsed_tokens);
+    }
+
+    let document = Node::new(
+        NodeKind::Env(EnvNode::new_module(children)),
+        NodePosition::Source(Span::at(ParserPosition::zero()))
+    );
+
+    (document, tokens)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    ///
+    /// Compact representation of a node for comparing parsed trees.
+    /// 
+    fn describe(node : &Node) -> String {
+        match &node.kind {
+            NodeKind::Leaf(LeafNode::Text(text)) => format!("{:?}", text),
+            NodeKind::Leaf(leaf) => format!("{:?}", leaf),
+            NodeKind::Env(EnvNode { header, kind }) => {
+                let children = match kind {
+                    EnvNodeKind::Open(children) => children
+                        .iter()
+                        .map(describe)
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    EnvNodeKind::SelfClosing => String::new(),
+                };
+
+                format!("{}({})", header.kind.get_name(), children)
+            }
+        }
+    }
+
+    #[test]
+    fn position_accessors() {
+
+        let position = ParserPosition::new(1, 2, 3).with_source(4);
+
+        assert_eq!(position.line(), 1);
+        assert_eq!(position.col(), 2);
+        assert_eq!(position.byte_idx(), 3);
+        assert_eq!(position.source(), 4);
+
+        #[allow(deprecated)]
+        let bytes = position.bytes();
+
+        assert_eq!(bytes, 3);
+
+        let copy = position;
+
+        assert_eq!(copy, position);
+        assert_eq!(position.to_string(), "2:3");
+    }
+
+    #[test]
+    fn env_span() {
+
+        let src = "Text <em>a <b>b</b></em> more";
+
+        let (document, _) = parse(src);
+
+        let Some(Node { position: NodePosition::Source(span), .. }) = document.descendants().nth(1) else {
+            panic!("Expected a node from the source.");
+        };
+
+        assert_eq!(&src[span.start.byte_idx()..span.end.byte_idx()], "<em>a <b>b</b></em>");
+        assert_eq!(span.len(), 19);
+        assert_eq!((span.start.to_string(), span.end.to_string()), ("1:6".to_string(), "1:25".to_string()));
+
+        let Some(Node { position: NodePosition::Source(span), .. }) = document.descendants().nth(2) else {
+            panic!("Expected a node from the source.");
+        };
+
+        assert_eq!(&src[span.start.byte_idx()..span.end.byte_idx()], "a ");
+    }
+
+    #[test]
+    fn frontmatter() {
+
+        fn metadata(document : &Node) -> Vec<(&str, &str, usize, usize)> {
+            match &document.kind {
+                NodeKind::Env(EnvNode { header, .. }) => header.attrs
+                    .iter()
+                    .map(|(key, value)| match value {
+                        Some(Node { 
+                            kind: NodeKind::Leaf(LeafNode::Text(text)), 
+                            position: NodePosition::Source(position), 
+                            .. 
+                        }) => (key.as_str(), text.as_str(), position.start.line, position.start.col),
+                        _ => panic!("Expected text."),
+                    })
+                    .collect(),
+                _ => panic!("Expected module."),
+            }
+        }
+
+        let (document, tokens) = parse("---\ntitle: My Document\n# comment\n\nlang:  \"en\"\n---\n# Hello\n");
+
+        assert_eq!(tokens.errors(), []);
+        assert_eq!(metadata(&document), [("title", "My Document", 1, 7), ("lang", "en", 4, 7)]);
+        assert!(matches!(
+            document.descendants().next(),
+            Some(Node { position: NodePosition::Source(position), .. }) if position.start == ParserPosition::new(6, 0, 50)
+        ));
+
+        // no frontmatter
+        for src in ["Hello\n---\na: b\n---", "--- a: b\n---", "\n---\na: b\n---"] {
+
+            let (document, tokens) = parse(src);
+
+            assert_eq!(tokens.errors(), []);
+            assert_eq!(metadata(&document), []);
+        }
+
+        let (document, tokens) = parse("---\ntitle: x\n# Hello\n");
+
+        assert_eq!(metadata(&document), []);
+        assert_eq!(tokens.errors()[0].kind, TokenKind::Error(ParseError::frontmatter_not_closed()));
+        assert_eq!(tokens.errors()[0].position, ParserPosition::zero());
+
+        let (document, tokens) = parse("---\ntitle: x\nno value\n---\n");
+
+        assert_eq!(metadata(&document), [("title", "x", 1, 7)]);
+        assert!(matches!(
+            &tokens.errors()[0].kind,
+            TokenKind::Error(error) if error.kind() == &ParseErrorKind::InvalidFrontmatter
+        ));
+        assert_eq!(tokens.errors()[0].position, ParserPosition::new(2, 0, 13));
+    }
 
     #[test]
     fn seek_to_and_capture() {
         
-        let end_document = TokenKind::new_env_close(&EnvNodeHeaderKind::Other("Document".to_string()));
+        let end_document = MatchableToken::new_env_close(&EnvNodeHeaderKind::Other("Document".to_string()));
 
         let cases = vec![
             (
                 "</Document>",
-                TokenKind::Text,
+                CapturedKind::Text,
                 [end_document.clone()],
                 // expected tokens
                 (
@@ -859,13 +2366,13 @@ mod tests {
                     Token {
                         position: ParserPosition::zero(),
                         value: "</Document>",
-                        kind: end_document.clone()
+                        kind: TokenKind::Matched(end_document.clone())
                     }
                 )
             ),
             (
                 "some text abc! <1 </Document>",
-                TokenKind::Text,
+                CapturedKind::Text,
                 [end_document.clone()],
                 // expected tokens
                 (
@@ -873,19 +2380,19 @@ mod tests {
                         Token {
                             position: ParserPosition::zero(),
                             value: "some text abc! <1 ",
-                            kind: TokenKind::Text
+                            kind: TokenKind::Captured(CapturedKind::Text)
                         }
                     ),
                     Token {
                         position: ParserPosition::new(0, 18, 18),
                         value: "</Document>",
-                        kind: end_document.clone()
+                        kind: TokenKind::Matched(end_document.clone())
                     }
                 )
             ),
             (
                 "some text\n abc! <1 \\</Document>",
-                TokenKind::Text,
+                CapturedKind::Text,
                 [end_document.clone()],
                 // expected tokens
                 (
@@ -893,13 +2400,13 @@ mod tests {
                         Token {
                             position: ParserPosition::zero(),
                             value: "some text\n abc! <1 \\</Document>",
-                            kind: TokenKind::Text
+                            kind: TokenKind::Captured(CapturedKind::Text)
                         }
                     ),
                     Token {
                         position: ParserPosition::new(1, 21, 31),
                         value: "",
-                        kind: TokenKind::EndOfModule
+                        kind: TokenKind::Matched(MatchableToken::EndOfModule)
                     }
                 )
             )
@@ -925,9 +2432,11 @@ mod tests {
             );
 
             assert_eq!(
-                parser.get_token(end),
+                parser.get_token(end.handle),
                 &expected.1
             );
+
+            assert_eq!(TokenKind::Matched(end.kind), expected.1.kind);
         }
     }
 
@@ -940,21 +2449,21 @@ mod tests {
             (
                 "/>",
                 EnvNodeAttrs::new(),
-                TokenKind::EnvSelfClose,
+                MatchableToken::EnvSelfClose,
             ),
             (
                 "label=\"foo\"/>",
                 EnvNodeHeader::generate_attrs(vec![
                     ("label", Some("foo")),
                 ]),
-                TokenKind::EnvSelfClose,
+                MatchableToken::EnvSelfClose,
             ),
             (
                 "label=\"foo\">",
                 EnvNodeHeader::generate_attrs(vec![
                     ("label", Some("foo")),
                 ]),
-                TokenKind::RightAngle,
+                MatchableToken::RightAngle,
             ),
             (
                 "label=\"foo\"  bar=\"1\" >",
@@ -962,7 +2471,7 @@ mod tests {
                     ("label",Some("foo")),
                     ("bar", Some("1")),
                 ]),
-                TokenKind::RightAngle,
+                MatchableToken::RightAngle,
             ),
             (
                 "label=\"foo\" bar=\"1\">",
@@ -970,7 +2479,7 @@ mod tests {
                     ("label",Some("foo")),
                     ("bar", Some("1")),
                 ]),
-                TokenKind::RightAngle,
+                MatchableToken::RightAngle,
             ),
             (
                 "label=\"foo\"\n\tbar=\"1\"\n />",
@@ -978,14 +2487,14 @@ mod tests {
                     ("label", Some("foo")),
                     ("bar", Some("1")),
                 ]),
-                TokenKind::EnvSelfClose,
+                MatchableToken::EnvSelfClose,
             ),
             (
                 "some_attr />",
                 EnvNodeHeader::generate_attrs(vec![
                     ("some_attr", None),
                 ]),
-                TokenKind::EnvSelfClose,
+                MatchableToken::EnvSelfClose,
             ),
             (
                 "label=\"foo\" bar />",
@@ -993,7 +2502,7 @@ mod tests {
                     ("label", Some("foo")),
                     ("bar", None),
                 ]),
-                TokenKind::EnvSelfClose,
+                MatchableToken::EnvSelfClose,
             ),
             (
                 "label=\"foo\" bar/>",
@@ -1001,7 +2510,16 @@ mod tests {
                     ("label", Some("foo")),
                     ("bar", None),
                 ]),
-                TokenKind::EnvSelfClose,
+                MatchableToken::EnvSelfClose,
+            ),
+            (
+                "label='foo' title=\"it's\" alt='say \"hi\"'>",
+                EnvNodeHeader::generate_attrs(vec![
+                    ("label", Some("foo")),
+                    ("title", Some("it's")),
+                    ("alt", Some("say \"hi\"")),
+                ]),
+                MatchableToken::RightAngle,
             ),
         ];
 
@@ -1009,13 +2527,25 @@ mod tests {
 
             let mut parser = Parser::new(src);
 
-            let (attrs, end_token) = parser.parse_env_header_attrs();
+            let (attrs, _, end_token) = parser.parse_env_header_attrs();
 
             assert_eq!(end_token, expected_end);
             
             assert_eq!(attrs, expected_attrs);
         }
-            
+
+        let (_, quotes, _) = Parser::new("label='foo' title=\"it's\" hidden>").parse_env_header_attrs();
+
+        assert_eq!(
+            quotes, 
+            HashMap::from([("label".to_string(), QuoteStyle::Single), ("title".to_string(), QuoteStyle::Double)])
+        );
+
+        let (_, tokens) = parse("<a title='x>");
+
+        assert_eq!(tokens.errors()[0].kind, TokenKind::Error(ParseError::quote_not_closed(&MatchableToken::SingleQuote)));
+        assert_eq!(ParseError::quote_not_closed(&MatchableToken::SingleQuote).to_string(), "Quote \"'\" not closed.");
+
     }
 
     #[test]
@@ -1068,6 +2598,114 @@ mod tests {
 
     } 
 
+    #[test]
+    fn match_tokens_at_end() {
+
+        let matchable = [
+            MatchableToken::EnvOpen, MatchableToken::FragmentOpen, MatchableToken::FragmentClose,
+            MatchableToken::EnvClose("</a>".to_string()), MatchableToken::EnvSelfClose, MatchableToken::RightAngle,
+            MatchableToken::CommentOpen, MatchableToken::CommentClose, MatchableToken::Whitespace, MatchableToken::EndOfLine,
+            MatchableToken::DollarBrace, MatchableToken::RightBrace, MatchableToken::Dollar, MatchableToken::Equals,
+            MatchableToken::Quote, MatchableToken::SingleQuote, MatchableToken::HeadingOpen, MatchableToken::ListItemOpen,
+            MatchableToken::DoubleAsterisk, MatchableToken::Asterisk, MatchableToken::Underscore,
+            MatchableToken::LeftBracket, MatchableToken::RightBracket,
+        ];
+
+        for kind in &matchable {
+            assert_eq!(Parser::new("").try_parse_token(kind), None, "{:?}", kind);
+        }
+
+        assert_eq!(Parser::new("").try_parse_token(&MatchableToken::EndOfModule), Some(""));
+
+        // whitespace is matched by bytes, not chars
+        let mut parser = Parser::new("\u{3000}\u{a0}x");
+
+        assert_eq!(parser.try_parse_token(&MatchableToken::Whitespace), Some("\u{3000}\u{a0}"));
+        assert_eq!(parser.remaining, "x");
+    }
+
+    #[test]
+    fn parse_never_panics() {
+
+        let cases = [
+            "", "<", "<a", "<a ", "<a b", "<a b=", "<a b=\"", "<a b='x", "<a/", "</a>", "<>", "</>",
+            "${", "${x", "$", "$x", "/**", "*/", "#", "# ", "- ", "1.", "1. ", "*", "**", "_", "[", "[x](",
+            "\\", "<a\u{3000}b>c</a>", "ä<ö", "😀${😀}", "<Component X content=\"raw-strict\"><X>x</X>",
+            "<Component X content=\"raw\"><X>", "<Component>", "<Component X content=\"other\">",
+            "$$", "$$x", "$x$$", "</", "</a", "<a b=\"x\" b", "---", "---\n", "{#", "# {.}", "\\(", "\\[x",
+            "<Html>", "<Html><", "<Eq>", "]", "](", "<a>]</a>", "\r\n- \r\n",
+        ];
+
+        let config = ParseConfig { error_nodes: true, latex_delimiters: true, ..Default::default() };
+
+        for src in cases {
+            parse(src);
+            parse_with_config(src, &config);
+        }
+    }
+
+    #[test]
+    fn minimal_inputs() {
+
+        for (src, expected) in [
+            ("", "()"),
+            (" ", r#"(" ")"#),
+            ("x", r#"("x")"#),
+            ("\n", r#"("\n")"#),
+            ("ä", r#"("ä")"#),
+        ] {
+            let (document, tokens) = parse(src);
+
+            assert_eq!(tokens.errors(), [], "{:?}", src);
+            assert_eq!(describe(&document), expected, "{:?}", src);
+
+            let NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Module, .. }, .. }) = &document.kind else {
+                panic!("{:?} is not parsed as a module", src);
+            };
+        }
+    }
+
+    #[test]
+    fn parse_random_inputs_never_panics() {
+
+        // concatenations of fragments that are likely to trip up the parser
+        let fragments = [
+            "<", ">", "</", "/>", "<>", "</>", "<a", "</a>", "<b ", "<Eq>", "</Eq>", "<Html>", "</Html>",
+            "<Component X", " content=\"raw\"", " content=\"raw-strict\"", "<X>", "</X>", "</Component>",
+            "${", "}", "$", "$$", "=", "\"", "'", "/**", "*/", "#", "# ", "{#id .c}", "\n", "\n\n", " ", "\t",
+            "- ", "1. ", "  ", "*", "**", "_", "[", "](", ")", "\\", "\\(", "\\)", "\\[", "\\]", "---\n",
+            "key: value\n", "x", "ä", "😀", "\u{3000}", "\r\n",
+        ];
+
+        let configs = [
+            ParseConfig::default(),
+            ParseConfig { error_nodes: true, latex_delimiters: true, ..Default::default() },
+        ];
+
+        // xorshift, deterministic so that failures can be reproduced
+        let mut state : u64 = 0x2545_f491_4f6c_dd1d;
+
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+
+        for _ in 0..20_000 {
+
+            let len = next() % 24;
+
+            let src = (0..len)
+                .map(|_| fragments[next() % fragments.len()])
+                .collect::<String>();
+
+            for config in &configs {
+                parse_with_config(&src, config);
+            }
+        }
+    }
+
     #[test]
     fn dynamic_parsing_error() {
 
@@ -1080,15 +2718,325 @@ mod tests {
         let (_, tokens) = super::parse(src);
 
         let error = tokens.errors.iter().find(
-            |token| match &token.kind {
-                TokenKind::Error(ParseError{ kind: ParseErrorKind::EnvNotClosed, .. }) => true,
-                _ => false,
-            }
+            |token| matches!(
+                &token.kind,
+                TokenKind::Error(ParseError{ kind: ParseErrorKind::EnvNotClosed, .. })
+            )
         );
 
         assert!(error.is_some());
     }
 
+    #[test]
+    fn recover_unclosed_env() {
+
+        let errors = |tokens : &TokenStorage| tokens.errors()
+            .iter()
+            .map(|token| match &token.kind {
+                TokenKind::Error(error) => format!("{}: {}", token.position, error),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<String>>();
+
+        let (document, tokens) = parse("<Section>\n<p>Still <em>parsed</em></p>\n<Section>closed</Section>");
+
+        assert_eq!(
+            describe(&document),
+            r#"(Section(), "\n", p("Still ", em("parsed")), "\n", Section("closed"))"#
+        );
+
+        assert_eq!(errors(&tokens), ["1:1: <Section> is never closed."]);
+
+        // closed by the closing tag of the parent
+        let (document, tokens) = parse("<div><Section>a <b>b</b></div><p>after</p>");
+
+        assert_eq!(describe(&document), r#"(div(Section("a ", b("b"))), p("after"))"#);
+
+        assert_eq!(errors(&tokens), ["1:25: <Section> is never closed."]);
+
+        // the siblings handed over by nested unclosed envs keep their order
+        let (document, _) = parse("<a>x<b>y<c>z");
+
+        assert_eq!(describe(&document), r#"(a(), "x", b(), "y", c(), "z")"#);
+
+        // nested unclosed envs are only parsed once, parsing them needs a larger stack in debug builds
+        let (nodes, errors) = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(|| {
+                let src = "<a>".repeat(200) + "text";
+
+                let (document, tokens) = parse(&src);
+
+                (document.descendants().count(), tokens.errors().len())
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(nodes, 201);
+        assert_eq!(errors, 200);
+    }
+
+    #[test]
+    fn line_ends_at_end_of_input() {
+
+        for (src, expected) in [
+            ("# Title", r#"(h1("Title"))"#),
+            ("text\n## Sub", r#"("text\n", h2("Sub"))"#),
+            ("- a\n- b", r#"(ul(li("a"), li("b")))"#),
+        ] {
+            let (document, tokens) = parse(src);
+
+            assert_eq!(tokens.errors(), [], "{:?}", src);
+            assert_eq!(describe(&document), expected);
+        }
+    }
+
+    #[test]
+    fn duplicate_attrs() {
+
+        let (document, tokens) = parse(r#"<a href="x" title="t" href="y">link</a><input disabled disabled/>"#);
+
+        let headers = document
+            .descendants()
+            .filter_map(|node| match &node.kind {
+                NodeKind::Env(env) => Some(&env.header),
+                _ => None,
+            })
+            .collect::<Vec<&EnvNodeHeader>>();
+
+        // the later value wins
+        assert_eq!(headers[0].attr_text("href").as_deref(), Some("y"));
+        assert_eq!(headers[0].attrs.keys().collect::<Vec<_>>(), ["href", "title"]);
+        assert_eq!(headers[1].attrs.keys().collect::<Vec<_>>(), ["disabled"]);
+
+        assert_eq!(tokens.errors(), []);
+
+        let warnings = tokens.warnings()
+            .iter()
+            .map(|token| match &token.kind {
+                TokenKind::Error(error) => format!("{}: {:?} {}", token.position, error.kind(), error),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            warnings,
+            [
+                r#"1:23: DuplicateAttr Duplicate attribute "href", the last value is used."#,
+                r#"1:56: DuplicateAttr Duplicate attribute "disabled", the last value is used."#,
+            ]
+        );
+    }
+
+    #[test]
+    fn error_nodes() {
+
+        let config = ParseConfig { error_nodes: true, ..Default::default() };
+
+        let (document, tokens) = parse_with_config("<p>a</p>\n<Section>text", &config);
+
+        assert_eq!(
+            describe(&document), 
+            r#"(p("a"), "\n", Section(Error("<Section> is never closed.")), "text")"#
+        );
+
+        // the node refers to the same error as the token
+        let error = document.descendants().find(|node| matches!(node.kind, NodeKind::Leaf(LeafNode::Error(_)))).unwrap();
+
+        let [token] = tokens.errors() else { panic!("Expected one error.") };
+
+        let (NodeKind::Leaf(LeafNode::Error(message)), TokenKind::Error(token_error)) = (&error.kind, &token.kind) else {
+            unreachable!()
+        };
+
+        assert_eq!(message, &token_error.to_string());
+        assert!(matches!(&error.position, NodePosition::Source(span) if span.start == token.position));
+
+        // errors found within the children are inserted where the env ends
+        let (document, _) = parse_with_config("<div><Section>a</div><em>b</Chapter>", &config);
+
+        assert_eq!(
+            describe(&document), 
+            r#"(div(Section("a", Error("<Section> is never closed."))), em("b", Error("Expected </em> but found </Chapter>.")))"#
+        );
+
+        // attribute values that cannot be parsed
+        let (document, tokens) = parse_with_config("<a href=>link</a><p title=\"x>text</p>", &config);
+
+        assert_eq!(
+            describe(&document), 
+            r#"(a("link"), p())"#
+        );
+
+        let values = document
+            .descendants()
+            .filter_map(|node| match &node.kind {
+                NodeKind::Env(env) => env.header.attrs.values().next().cloned().flatten(),
+                _ => None,
+            })
+            .map(|value| value.kind)
+            .collect::<Vec<NodeKind>>();
+
+        assert_eq!(
+            values, 
+            [
+                NodeKind::Leaf(LeafNode::Error("Expected attribute value after '='.".to_string())),
+                NodeKind::Leaf(LeafNode::Error("Quote '\"' not closed.".to_string())),
+            ]
+        );
+
+        assert_eq!(
+            tokens.errors().iter().map(|token| (token.position.to_string(), token.kind.clone())).collect::<Vec<_>>()[..2],
+            [
+                ("1:8".to_string(), TokenKind::Error(ParseError::missing_attr_value())),
+                ("1:27".to_string(), TokenKind::Error(ParseError::quote_not_closed(&MatchableToken::Quote))),
+            ]
+        );
+
+        // not inserted by default
+        assert_eq!(describe(&parse("<Section>text").0), r#"(Section(), "text")"#);
+    }
+
+    #[test]
+    fn block_math() {
+
+        let (document, tokens) = parse("Display $$\n\\sum_i x_i\n$$ then $x$ $y$, $a$$b$ and \\$\\$");
+
+        assert_eq!(tokens.errors(), []);
+
+        assert_eq!(
+            describe(&document), 
+            r#"("Display ", Eq("\n\\sum_i x_i\n"), " then ", Eq("x"), " ", Eq("y"), ", ", Eq("a"), Eq("b"), " and $$")"#
+        );
+
+        let kinds = document
+            .descendants()
+            .filter_map(|node| match &node.kind {
+                NodeKind::Env(env) => Some(env.header.kind.clone()),
+                _ => None,
+            })
+            .collect::<Vec<EnvNodeHeaderKind>>();
+
+        assert_eq!(kinds[0], EnvNodeHeaderKind::Eq(EquationKind::Block));
+        assert!(kinds[1..].iter().all(|kind| *kind == EnvNodeHeaderKind::Eq(EquationKind::Inline)));
+
+        // never closed
+        let (_, tokens) = parse("$$ x");
+
+        assert_eq!(tokens.errors().len(), 1);
+
+        // the equation swallows the closing tag of the env it is opened in
+        let (document, tokens) = parse("<p>a $$ x</p>");
+
+        assert_eq!(describe(&document), r#"(p(), "a ", Eq(" x</p>"))"#);
+
+        assert_eq!(
+            tokens.errors()
+                .iter()
+                .map(|token| match &token.kind {
+                    TokenKind::Error(error) => error.message.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<String>>(),
+            ["<p> is never closed.", "Unexpected end of module. Expected '$$'."]
+        );
+    }
+
+    #[test]
+    fn latex_delimiters() {
+
+        let config = ParseConfig { latex_delimiters: true, ..Default::default() };
+
+        let src = r"Inline \(x^2\), block \[\frac{a}{b} \cdot (c)\] and \$";
+
+        let (document, tokens) = parse_with_config(src, &config);
+
+        assert_eq!(tokens.errors(), []);
+
+        assert_eq!(describe(&document), r#"("Inline ", Eq("x^2"), ", block ", Eq("\\frac{a}{b} \\cdot (c)"), " and $")"#);
+
+        let kinds = document
+            .descendants()
+            .filter_map(|node| match &node.kind {
+                NodeKind::Env(env) => Some(env.header.kind.clone()),
+                _ => None,
+            })
+            .collect::<Vec<EnvNodeHeaderKind>>();
+
+        assert_eq!(kinds, [EnvNodeHeaderKind::Eq(EquationKind::Inline), EnvNodeHeaderKind::Eq(EquationKind::Block)]);
+
+        // text by default
+        assert_eq!(describe(&parse(r"\(x\)").0), r#"("\\(x\\)")"#);
+    }
+
+    #[test]
+    fn mismatched_closing_tag() {
+
+        let errors = |tokens : &TokenStorage| tokens.errors()
+            .iter()
+            .map(|token| match &token.kind {
+                TokenKind::Error(error) => format!("{}: {:?} {}", token.position, error.kind(), error),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<String>>();
+
+        // the mismatched tag closes the env
+        let (document, tokens) = parse("<Section>\ntext</Chapter><p>after</p>");
+
+        assert_eq!(describe(&document), r#"(Section("\ntext"), p("after"))"#);
+
+        assert_eq!(
+            errors(&tokens), 
+            ["2:5: MismatchedClosingTag Expected </Section> but found </Chapter>."]
+        );
+
+        // found within the children of an env, e.g. an emphasis
+        let (document, tokens) = parse("<div><em>a **b</Chapter> c</div>");
+
+        assert_eq!(describe(&document), r#"(div(em("a ", "**", "b"), " c"))"#);
+
+        assert_eq!(
+            errors(&tokens), 
+            ["1:15: MismatchedClosingTag Expected </em> but found </Chapter>."]
+        );
+
+        // closing tags outside of envs are text
+        let (document, tokens) = parse("a</b>");
+
+        assert_eq!(describe(&document), r#"("a</b>")"#);
+        assert_eq!(tokens.errors(), []);
+    }
+
+    #[test]
+    fn unexpected_eof() {
+
+        let cases = [
+            ("<a", "Unexpected end of module. Expected whitespace, '/>' or '>'."),
+            ("/** x", "Unexpected end of module. Expected '*/'."),
+            ("a $x", "Unexpected end of module. Expected '$'."),
+            ("<Code>x", "Unexpected end of module. Expected '</Code>'."),
+            // only the closing tags of the children are expected
+            ("<>a", "Unexpected end of module. Expected '</>'."),
+            ("<div><>a", "Unexpected end of module. Expected '</>' or '</div>'."),
+        ];
+
+        for (src, expected) in cases {
+
+            let (_, tokens) = parse(src);
+
+            let messages = tokens.errors()
+                .iter()
+                .map(|token| match &token.kind {
+                    TokenKind::Error(error) => error.message.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<String>>();
+
+            assert_eq!(messages.last().map(String::as_str), Some(expected), "{}", src);
+        }
+    }
+
     #[test]
     fn dynamic_parsing_valid() {
 
@@ -1104,4 +3052,410 @@ mod tests {
         assert_eq!(tokens.errors, []);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn raw_component_like_code() {
+
+        let body = "<b>bold</b> <Unclosed> ${x} $y";
+
+        let src = format!(
+            "<Component Listing content=\"raw\">${{children}}</Component><Listing>{0}</Listing><Code>{0}</Code>",
+            body
+        );
+
+        let (document, tokens) = parse(&src);
+
+        assert_eq!(tokens.errors(), []);
+
+        // the body is a single text node for both
+        assert!(describe(&document).ends_with(&format!("Listing({0:?}), Code({0:?}))", body)));
+    }
+
+    #[test]
+    fn parse_emphasis() {
+
+        let cases = [
+            (
+                "**_both_** and *one*",
+                r#"(strong(em("both")), " and ", em("one"))"#
+            ),
+            (
+                "5 * 3 = 15",
+                r#"("5 ", "*", " 3 = 15")"#
+            ),
+            (
+                "a lone *",
+                r#"("a lone ", "*")"#
+            ),
+            (
+                "snake_case_name",
+                r#"("snake", "_", "case", "_", "name")"#
+            ),
+            (
+                "\\*not emphasized\\*",
+                r#"("*not emphasized*")"#
+            ),
+            (
+                "### *a\nb* c",
+                r#"(h3("*", "a"), "b", "*", " c")"#
+            ),
+        ];
+
+        for (src, expected) in cases {
+
+            let (document, tokens) = parse(src);
+
+            assert_eq!(describe(&document), expected, "{}", src);
+
+            assert_eq!(tokens.errors, []);
+        }
+    }
+
+    #[test]
+    fn parse_interpolation() {
+
+        let (document, tokens) = parse("Hello ${name}!");
+
+        assert_eq!(describe(&document), r#"("Hello ", VariableExpression("name"), "!")"#);
+
+        assert_eq!(tokens.errors, []);
+
+        let columns = match &document.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children
+                .iter()
+                .map(|child| match &child.position {
+                    NodePosition::Source(span) => span.start.col(),
+                    NodePosition::Inserted => panic!("Expected source position."),
+                })
+                .collect::<Vec<usize>>(),
+            _ => panic!("Expected module."),
+        };
+
+        assert_eq!(columns, [0, 6, 13]);
+    }
+
+    #[test]
+    fn parse_links() {
+
+        let cases = [
+            (
+                "see [the docs](https://example.com)",
+                r#"("see ", a("the docs"))"#,
+                "https://example.com",
+            ),
+            (
+                "[**bold** label](/a_(b)/c)",
+                r#"(a(strong("bold"), " label"))"#,
+                "/a_(b)/c",
+            ),
+            (
+                "[escaped](/a\\)b)",
+                r#"(a("escaped"))"#,
+                "/a)b",
+            ),
+        ];
+
+        for (src, expected, href) in cases {
+
+            let (document, tokens) = parse(src);
+
+            assert_eq!(describe(&document), expected, "{}", src);
+
+            assert_eq!(tokens.errors, []);
+
+            let link = match &document.kind {
+                NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children.back().unwrap(),
+                _ => panic!("Expected module."),
+            };
+
+            match &link.kind {
+                NodeKind::Env(EnvNode { header, .. }) => assert!(matches!(
+                    header.attrs.get("href"), 
+                    Some(Some(Node { kind: NodeKind::Leaf(LeafNode::Text(value)), .. })) if value == href
+                )),
+                _ => panic!("Expected link."),
+            }
+        }
+
+        // brackets without a destination remain text
+        for (src, expected) in [
+            ("[x]", r#"("[", "x]")"#),
+            ("[x] (y)", r#"("[", "x] (y)")"#),
+            ("[x](y", r#"("[", "x](y")"#),
+        ] {
+            let (document, tokens) = parse(src);
+
+            assert_eq!(describe(&document), expected, "{}", src);
+
+            assert_eq!(tokens.errors, []);
+        }
+    }
+
+    #[test]
+    fn parse_escapes() {
+
+        let (document, tokens) = parse(r#"costs \$5 \<b> \\ \${x} \*a\* \_b\_ \[c\] \- \%"#);
+
+        assert_eq!(describe(&document), r#"("costs $5 <b> \\ ${x} *a* _b_ [c] - \\%")"#);
+
+        assert_eq!(tokens.errors, []);
+
+        // math is not decoded
+        let (document, _) = parse(r#"$a \\ b$"#);
+
+        assert_eq!(describe(&document), r#"(Eq("a \\\\ b"))"#);
+    }
+
+    #[test]
+    fn parse_void_elements() {
+
+        for src in ["a<br>b", "a<br/>b", "a<br />b"] {
+
+            let (document, tokens) = parse(src);
+
+            assert_eq!(describe(&document), r#"("a", br(), "b")"#, "{}", src);
+
+            assert_eq!(tokens.errors, []);
+
+            let children = match &document.kind {
+                NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children,
+                _ => panic!("Expected module."),
+            };
+
+            assert!(matches!(children[1].kind, NodeKind::Env(EnvNode { kind: EnvNodeKind::SelfClosing, .. })), "{}", src);
+        }
+
+        let (document, tokens) = parse(r#"<img src="a.png">text"#);
+
+        assert_eq!(describe(&document), r#"(img(), "text")"#);
+
+        assert_eq!(tokens.errors, []);
+    }
+
+    #[test]
+    fn parse_multiple_sources() {
+
+        let (document, tokens) = parse_sources(&["<p>First</p>\n<Section>a</Section>", "<p>Second</p>"]);
+
+        assert_eq!(describe(&document), r#"(p("First"), "\n", Section("a"), p("Second"))"#);
+
+        assert_eq!(tokens.len(), 2);
+
+        let children = match &document.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children,
+            _ => panic!("Expected module."),
+        };
+
+        let sources = children
+            .iter()
+            .map(|child| match &child.position {
+                NodePosition::Source(span) => (span.start.source(), span.start.line()),
+                NodePosition::Inserted => panic!("Expected source position."),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(sources, [(0, 0), (0, 0), (0, 1), (1, 0)]);
+
+        // positions of different sources do not compare equal
+        assert!(ParserPosition::zero() < ParserPosition::zero().with_source(1));
+
+        let mut ids = children.iter().map(|child| child.id).collect::<Vec<_>>();
+
+        ids.push(document.id);
+        ids.sort();
+        ids.dedup();
+
+        assert_eq!(ids.len(), 5);
+    }
+
+    ///
+    /// seek_to as it was before skipping to stop bytes, testing every token at every char.
+    ///
+    fn seek_to_by_chars<'a>(parser : &mut Parser<'a>, tokens : &[MatchableToken]) -> Option<(MatchableToken, Token<'a>)> {
+
+        while !parser.remaining.is_empty() {
+
+            for kind in tokens {
+
+                let position = parser.position;
+
+                if let Some(value) = parser.try_parse_token(kind) {
+                    return Some((kind.clone(), Token { value, kind: TokenKind::Matched(kind.clone()), position }))
+                }
+            }
+
+            parser.next_unescaped_char();
+        }
+
+        tokens.contains(&MatchableToken::EndOfModule).then_some((
+            MatchableToken::EndOfModule,
+            Token { value: "", kind: TokenKind::Matched(MatchableToken::EndOfModule), position: parser.position }
+        ))
+    }
+
+    #[test]
+    fn seek_to_stop_bytes() {
+
+        let sources = [
+            "",
+            "plain text without any tokens",
+            "Äöü ✓ <b>bold</b>\n# heading\n- item\n12. item\n\\<escaped\\> \\\\<b> *a* **b** _c_ [l](u) ${x} $y$ </Code>",
+            "multi\nline\n  text ✓\n\twith\u{a0}unicode\u{2003}spaces = \"quoted\" 'single' /** comment */ <></> />",
+            "trailing backslash \\",
+        ];
+
+        let token_sets = [
+            vec![
+                MatchableToken::EndOfModule,
+                MatchableToken::FragmentOpen,
+                MatchableToken::EnvOpen,
+                MatchableToken::DollarBrace,
+                MatchableToken::Dollar,
+                MatchableToken::CommentOpen,
+                MatchableToken::HeadingOpen,
+                MatchableToken::ListItemOpen,
+                MatchableToken::DoubleAsterisk,
+                MatchableToken::Asterisk,
+                MatchableToken::Underscore,
+                MatchableToken::LeftBracket,
+                MatchableToken::AnyEnvClose,
+            ],
+            vec![MatchableToken::Whitespace, MatchableToken::EnvSelfClose, MatchableToken::RightAngle],
+            vec![MatchableToken::EnvClose("</Code>".to_string())],
+            vec![MatchableToken::EndOfLine, MatchableToken::EndOfModule, MatchableToken::RightBracket],
+            vec![MatchableToken::Equals, MatchableToken::Quote, MatchableToken::SingleQuote, MatchableToken::CommentClose],
+        ];
+
+        for src in sources {
+            for tokens in &token_sets {
+
+                let mut parser = Parser::new(src);
+                let mut reference = Parser::new(src);
+
+                loop {
+                    let stop = parser.seek_to(tokens);
+
+                    assert_eq!(stop, seek_to_by_chars(&mut reference, tokens), "{:?} in {:?}", tokens, src);
+                    assert_eq!(parser.position, reference.position);
+                    assert_eq!(parser.remaining, reference.remaining);
+
+                    if stop.is_none_or(|(kind, _)| kind == MatchableToken::EndOfModule) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn token_stream() {
+
+        let (_, tokens) = parse("<p class=\"x\">Hi</p>");
+
+        let tokens = tokens
+            .tokens()
+            .map(|token| format!("{} {:?} {:?}", token.position, token.kind, token.value))
+            .collect::<Vec<String>>();
+
+        assert_eq!(tokens, [
+            r#"1:1 Matched(EnvOpen) "<""#,
+            r#"1:2 Captured(EnvName) "p""#,
+            r#"1:3 Matched(Whitespace) " ""#,
+            r#"1:4 Captured(AttrName) "class""#,
+            r#"1:9 Matched(Equals) "=""#,
+            r#"1:10 Matched(Quote) "\"""#,
+            r#"1:11 Captured(StringLiteral) "x""#,
+            r#"1:12 Matched(Quote) "\"""#,
+            r#"1:13 Matched(RightAngle) ">""#,
+            r#"1:14 Captured(Text) "Hi""#,
+            r#"1:16 Matched(EnvClose("</p>")) "</p>""#,
+            r#"1:20 Matched(EndOfModule) """#,
+        ]);
+    }
+
+    #[test]
+    fn parse_headings() {
+
+        let cases = [
+            ("# Heading\n", r#"(h1("Heading"))"#),
+            ("#\tHeading\n", r#"(h1("Heading"))"#),
+            ("##   spaced\n", r#"(h2("spaced"))"#),
+            ("### \t ä\n", r#"(h3("ä"))"#),
+            ("#no heading\n", r##"("#no heading\n")"##),
+            ("## Intro {#intro .big .snake_case}\n", r#"(h2("Intro"))"#),
+            // blocks are only parsed at the end of the line
+            ("# {#a} b\n", r##"(h1("{#a} b"))"##),
+            ("# a {b}\n", r#"(h1("a {b}"))"#),
+            // only headings take attribute blocks
+            ("<>a</> {#a}\n", r##"(("a"), " {#a}\n")"##),
+        ];
+
+        for (src, expected) in cases {
+
+            let (document, tokens) = parse(src);
+
+            assert_eq!(describe(&document), expected, "{}", src);
+
+            assert_eq!(tokens.errors, []);
+        }
+
+        let (document, _) = parse("## Intro {#intro .big .snake_case}\n");
+
+        let heading = document.descendants().next().unwrap();
+
+        let NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(children) }) = &heading.kind else {
+            panic!("Expected heading.");
+        };
+
+        assert_eq!(header.attr_text("id").as_deref(), Some("intro"));
+        assert_eq!(header.attr_text("class").as_deref(), Some("big snake_case"));
+
+        let NodePosition::Source(span) = &children[0].position else {
+            panic!("Expected source position.");
+        };
+
+        assert_eq!(span.end.col(), 8);
+    }
+
+    #[test]
+    fn parse_lists() {
+
+        let cases = [
+            (
+                "- a\n- b\n* c\n",
+                r#"(ul(li("a"), li("b"), li("c")))"#
+            ),
+            (
+                "1. first\n2. second\n",
+                r#"(ol(li("first"), li("second")))"#
+            ),
+            (
+                "- a\n  - a1\n  - a2\n- b\n",
+                r#"(ul(li("a", ul(li("a1"), li("a2"))), li("b")))"#
+            ),
+            (
+                "- a\n  1. a1\n- b\n",
+                r#"(ul(li("a", ol(li("a1"))), li("b")))"#
+            ),
+            // a blank line ends the list
+            (
+                "- a\n\n- b\n",
+                r#"(ul(li("a")), "\n", ul(li("b")))"#
+            ),
+            // markers must be at the start of a line
+            (
+                "Some - text\n- item\n",
+                r#"("Some - text\n", ul(li("item")))"#
+            ),
+        ];
+
+        for (src, expected) in cases {
+
+            let (document, tokens) = parse(src);
+
+            assert_eq!(describe(&document), expected, "{}", src);
+
+            assert_eq!(tokens.errors, []);
+        }
+    }
+
+}
@@ -0,0 +1,224 @@
+//!
+//! Incremental reparsing for editors.
+//!
+//! Only the env containing an edit is parsed again and grafted into the old tree.
+//! The positions of all nodes after the edit are moved accordingly.
+//!
+//! This is conservative: edits that are not strictly within a top-level env, edits 
+//! that produce syntax errors, and documents defining components are parsed again entirely.
+//!
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use crate::document::*;
+use crate::parse::{parse, ParserPosition, Span};
+
+/** Position of the byte offset in src. */
+fn position_at(src : &str, byte_idx : usize, source : usize) -> ParserPosition {
+
+    let before = &src[..byte_idx];
+
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    ParserPosition::new(before.matches('\n').count(), before[line_start..].chars().count(), byte_idx)
+        .with_source(source)
+}
+
+/** Applies f to the positions of the node, its attribute values, and all nodes below. */
+fn map_positions(node : &mut Node, f : &impl Fn(ParserPosition) -> ParserPosition) {
+
+    if let NodePosition::Source(span) = &mut node.position {
+        *span = Span::new(f(span.start), f(span.end));
+    }
+
+    if let NodeKind::Env(EnvNode { header, kind }) = &mut node.kind {
+
+        for value in header.attrs.values_mut().flatten() {
+            map_positions(value, f);
+        }
+
+        if let EnvNodeKind::Open(children) = kind {
+            for child in children {
+                map_positions(child, f);
+            }
+        }
+    }
+}
+
+fn defines_components(node : &Node) -> bool {
+    std::iter::once(node).chain(node.descendants()).any(|node| matches!(
+        &node.kind,
+        NodeKind::Env(EnvNode { header, .. }) if header.kind == EnvNodeHeaderKind::ComponentDefinition
+    ))
+}
+
+///
+/// Parses the slice of the edited source covering the block at span.
+/// None if the slice cannot be parsed on its own.
+///
+fn reparse_block(new_src : &str, span : &Span, delta : isize) -> Option<VecDeque<Node>> {
+
+    let start = span.start;
+    let end = span.end.byte_idx().checked_add_signed(delta)?;
+
+    let (block, tokens) = parse(new_src.get(start.byte_idx()..end)?);
+
+    if !tokens.errors().is_empty() || defines_components(&block) {
+        return None;
+    }
+
+    let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(mut children), .. }) = block.kind else {
+        return None;
+    };
+
+    // positions within the slice are relative to the start of the block
+    let to_source = |position : ParserPosition| ParserPosition::new(
+        start.line() + position.line(),
+        if position.line() == 0 { start.col() + position.col() } else { position.col() },
+        start.byte_idx() + position.byte_idx()
+    ).with_source(start.source());
+
+    for child in children.iter_mut() {
+        map_positions(child, &to_source);
+    }
+
+    Some(children)
+}
+
+///
+/// Applies the edit (replacing the bytes in edit with new_text) to src and returns the 
+/// tree of the edited source. old must be the tree parsed from src.
+/// 
+/// Only the top-level env containing the edit is parsed again if possible.
+/// 
+pub fn reparse(old : &Node, src : &str, edit : Range<usize>, new_text : &str) -> Node {
+
+    let new_src = format!("{}{}{}", &src[..edit.start], new_text, &src[edit.end..]);
+
+    let full = || parse(&new_src).0;
+
+    let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = &old.kind else {
+        return full();
+    };
+
+    if defines_components(old) {
+        return full();
+    }
+
+    // the opening and closing tags must not be touched by the edit
+    let Some((index, span)) = children.iter().enumerate().find_map(|(i, child)| match (&child.kind, &child.position) {
+        (NodeKind::Env(_), NodePosition::Source(span)) 
+            if span.start.byte_idx() < edit.start && edit.end < span.end.byte_idx() => Some((i, *span)),
+        _ => None,
+    }) else {
+        return full();
+    };
+
+    let delta = new_text.len() as isize - edit.len() as isize;
+
+    let Some(block) = reparse_block(&new_src, &span, delta) else {
+        return full();
+    };
+
+    let source = span.start.source();
+    let old_end = position_at(src, edit.end, source);
+    let new_end = position_at(&new_src, edit.start + new_text.len(), source);
+
+    // moves positions after the edit
+    let shift = |position : ParserPosition| match position.byte_idx() >= edit.end {
+        true => ParserPosition::new(
+            position.line() + new_end.line() - old_end.line(),
+            match position.line() == old_end.line() {
+                true => position.col() + new_end.col() - old_end.col(),
+                false => position.col(),
+            },
+            position.byte_idx() + new_end.byte_idx() - old_end.byte_idx()
+        ).with_source(source),
+        false => position,
+    };
+
+    let mut document = old.clone();
+
+    if let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = &mut document.kind {
+
+        let after = children.split_off(index + 1);
+
+        children.pop_back();
+
+        children.extend(block);
+
+        for mut child in after {
+            map_positions(&mut child, &shift);
+            children.push_back(child);
+        }
+    }
+
+    if let NodePosition::Source(span) = &mut document.position {
+        span.end = shift(span.end);
+    }
+
+    document
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn spans(node : &Node) -> Vec<(usize, usize)> {
+        node.descendants()
+            .filter_map(|node| match &node.position {
+                NodePosition::Source(span) => Some((span.start.byte_idx(), span.end.byte_idx())),
+                NodePosition::Inserted => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edit_paragraph() {
+
+        let src = "# Title\n<p>First <em>paragraph</em>.</p>\n<p>Second\nline</p>\n";
+
+        let (mut old, _) = parse(src);
+
+        let start = src.find("paragraph").unwrap();
+
+        let new = reparse(&old, src, start..start + 9, "edited\nblock");
+
+        let expected_src = "# Title\n<p>First <em>edited\nblock</em>.</p>\n<p>Second\nline</p>\n";
+
+        let (expected, _) = parse(expected_src);
+
+        assert_eq!(new, expected);
+        assert_eq!(spans(&new), spans(&expected));
+
+        let NodePosition::Source(span) = &new.descendants().last().unwrap().position else { unreachable!() };
+
+        assert_eq!(span.start.to_string(), "5:9");
+
+        // nodes outside of the edited paragraph are not parsed again
+        if let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = &mut old.kind {
+            children[0] = NodeBuilder::text("kept");
+        }
+
+        let new = reparse(&old, src, start..start + 9, "again");
+
+        assert_eq!(new.descendants().next(), Some(&NodeBuilder::text("kept")));
+    }
+
+    #[test]
+    fn fallback() {
+
+        let src = "<p>a</p> <p>b</p>";
+
+        let (old, _) = parse(src);
+
+        // the edit leaves an env unclosed
+        assert_eq!(reparse(&old, src, 3..3, "<em>"), parse("<p><em>a</p> <p>b</p>").0);
+
+        // the edit is not within an env
+        assert_eq!(reparse(&old, src, 8..9, "x"), parse("<p>a</p>x<p>b</p>").0);
+    }
+
+}
@@ -0,0 +1,296 @@
+//!
+//! Renders a source to HTML in a single call.
+//!
+
+use std::path::Path;
+
+use std::fmt;
+
+use crate::document::visit::{transform_one, transform_slice, transform_to_fixpoint, TransformerOnce, VisitError, Visitor};
+use crate::document::{Node, NodePosition};
+use crate::error::{Diagnostic, Error};
+use crate::parse::{parse, parse_with_config, ParseConfig};
+use crate::visitors::callouts::Callouts;
+use crate::visitors::cleanup::Cleanup;
+use crate::visitors::components::components;
+use crate::visitors::html_emit::HTMLEmitter;
+use crate::visitors::include::{parse_file, Include};
+use crate::visitors::merge_text::MergeText;
+use crate::visitors::tabs::Tabs;
+use crate::visitors::variables::Variables;
+
+///
+/// Parses the source, runs the transformers until the document does not change anymore, and emits HTML.
+///
+/// Rendering does not stop at the first syntax error, which the parser recovers from.
+/// A transformer error ends rendering without output and an emitter error ends the output early.
+/// All of these are returned as diagnostics, along with the diagnostics reported by the transformers themselves.
+/// If config.error_nodes is set, the output shows placeholders where the parser found errors.
+///
+pub fn render_to_string(
+    src : &str,
+    config : &ParseConfig,
+    transformers : &mut [Box<dyn Visitor + '_>]
+) -> (String, Vec<Diagnostic>) {
+
+    let (document, tokens) = parse_with_config(src, config);
+
+    let mut diagnostics = Diagnostic::from_tokens(&tokens);
+
+    let result = transform_to_fixpoint(document, transformers);
+
+    for transformer in transformers.iter_mut() {
+        diagnostics.append(&mut transformer.take_diagnostics());
+    }
+
+    let document = match result {
+        Ok(document) => document,
+        Err(error) => {
+            diagnostics.push(error.into());
+            return (String::new(), diagnostics);
+        }
+    };
+
+    let mut html = String::new();
+
+    let mut emitter = HTMLEmitter::new(|s : &str| html.push_str(s));
+
+    // the errors of error nodes have been reported by the parser already
+    emitter.strict = !config.error_nodes;
+
+    let result = transform_one(document, &mut emitter, 1);
+
+    if let Err(error) = result {
+        diagnostics.push(error.into());
+    }
+
+    (html, diagnostics)
+}
+
+///
+/// Reads the file at path, resolves its imports, applies the transformers in order, and emits HTML.
+/// Imports are resolved relative to the directory of the importing file.
+///
+pub fn transpile_path(
+    path : &Path,
+    transformers : &mut [Box<dyn Visitor + '_>]
+) -> Result<String, VisitError> {
+
+    let document = parse_file(path)?;
+
+    let document = transform_slice(document, &mut [Box::new(Include::for_file(path)) as Box<dyn Visitor>], 2)?;
+
+    let document = transform_to_fixpoint(document, transformers)?;
+
+    let mut html = String::new();
+
+    transform_one(document, &mut HTMLEmitter::new(|s : &str| html.push_str(s)), 1)?;
+
+    Ok(html)
+}
+
+///
+/// Error returned by [transpile_to_html].
+/// Owns all of its data, so it can outlive the source. Lines and columns are 1-based.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranspileError {
+    /// The source is not valid syntax.
+    Syntax { line : usize, col : usize, message : String },
+    /// A transformer or the emitter failed. Nodes inserted by transformers have no position.
+    Transform { line : Option<usize>, col : Option<usize>, message : String },
+}
+
+impl TranspileError {
+
+    pub fn message(&self) -> &str {
+        match self {
+            TranspileError::Syntax { message, .. } | TranspileError::Transform { message, .. } => message
+        }
+    }
+
+    fn transform(error : VisitError) -> Self {
+
+        let diagnostic = Diagnostic::from(error);
+
+        let (line, col) = match diagnostic.position {
+            NodePosition::Source(span) => (Some(span.start.line() + 1), Some(span.start.col() + 1)),
+            NodePosition::Inserted => (None, None),
+        };
+
+        TranspileError::Transform { line, col, message: diagnostic.message }
+    }
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranspileError::Syntax { line, col, message }
+            | TranspileError::Transform { line: Some(line), col: Some(col), message } => write!(f, "{}:{}: {}", line, col, message),
+            TranspileError::Transform { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for TranspileError {}
+
+///
+/// Transpiles the source to HTML using the [default_transformers].
+/// Fails on the first syntax error instead of recovering from it.
+///
+pub fn transpile_to_html(src : &str) -> Result<String, TranspileError> {
+
+    let (document, tokens) = parse(src);
+
+    if let Some(token) = tokens.errors().first() {
+
+        return Err(TranspileError::Syntax {
+            line: token.position.line() + 1,
+            col: token.position.col() + 1,
+            message: Diagnostic::from_token(token).map(|d| d.message).unwrap_or_default(),
+        });
+    }
+
+    emit_default(document).map_err(TranspileError::transform)
+}
+
+///
+/// The canonical transformer pipeline, in the order it has to run:
+/// Cleanup, component registration, Tabs, Callouts, component insertion, Variables, and MergeText.
+/// Each transformer runs once per node, the pipeline runs until the document does not change anymore
+/// (see [transform_to_fixpoint]).
+///
+pub fn default_transformers() -> Vec<Box<dyn Visitor>> {
+
+    let (component_register, component_insert) = components();
+
+    vec![
+        Box::new(TransformerOnce::new(Cleanup::default())),
+        Box::new(TransformerOnce::new(component_register)),
+        Box::new(TransformerOnce::new(Tabs::new())),
+        Box::new(TransformerOnce::new(Callouts::new())),
+        Box::new(TransformerOnce::new(component_insert)),
+        Box::new(TransformerOnce::new(Variables::new())),
+        Box::new(TransformerOnce::new(MergeText::new())),
+    ]
+}
+
+/// Number of passes for [default_transformers].
+#[deprecated(note = "use transform_to_fixpoint")]
+pub const DEFAULT_PASSES : u32 = 1;
+
+///
+/// Parses the source, runs the [default_transformers], and emits HTML.
+/// Fails on the first syntax error.
+///
+pub fn render_html(src : &str) -> Result<String, Error<'_>> {
+
+    let (document, tokens) = parse(src);
+
+    if let Some(error) = tokens.errors().first().and_then(|token| Error::from_token(src, token)) {
+        return Err(error);
+    }
+
+    Ok(emit_default(document)?)
+}
+
+/// Runs the default pipeline on a parsed document and emits HTML.
+fn emit_default(document : Node) -> Result<String, VisitError> {
+
+    let document = transform_to_fixpoint(document, &mut default_transformers())?;
+
+    let mut html = String::new();
+
+    transform_one(document, &mut HTMLEmitter::new(|s : &str| html.push_str(s)), 1)?;
+
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::error::Severity;
+    use crate::visitors::accessibility::Accessibility;
+
+    #[test]
+    fn render_with_diagnostics() {
+
+        let (html, diagnostics) = render_to_string(
+            "# Title\n### Details\n<div>text",
+            &ParseConfig::default(),
+            &mut [Box::new(Accessibility::new())]
+        );
+
+        assert_eq!(html, "<h1>Title</h1><h3>Details</h3><div></div>text");
+
+        assert_eq!(
+            diagnostics.iter().map(|diagnostic| (diagnostic.severity, diagnostic.to_string())).collect::<Vec<_>>(),
+            [
+                (Severity::Error, "error at 3:1: <div> is never closed.".to_string()),
+                (Severity::Warning, "warning at 2:1: Heading level skipped: h3 follows h1.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_with_error_nodes() {
+
+        let config = ParseConfig { error_nodes: true, ..ParseConfig::default() };
+
+        let (html, diagnostics) = render_to_string("<p title=>text</p><div>more", &config, &mut []);
+
+        assert_eq!(
+            html, 
+            concat!(
+                "<p>text</p>",
+                "<div><span class=\"vtx-error\" title=\"&lt;div&gt; is never closed.\">⚠</span></div>more",
+            )
+        );
+
+        // reported once by the parser, not again by the emitter
+        assert_eq!(
+            diagnostics.iter().map(Diagnostic::to_string).collect::<Vec<_>>(),
+            [
+                "error at 1:9: Expected attribute value after '='.",
+                "error at 1:19: <div> is never closed.",
+            ]
+        );
+    }
+
+    #[test]
+    fn transpile_to_html_default_pipeline() {
+
+        let html = transpile_to_html(
+            "<Component Greeting who><p>Hello ${who}</p></Component>\n<Greeting who=\"World\" />"
+        ).unwrap();
+
+        assert!(html.contains("<p>Hello World</p>"), "{}", html);
+    }
+
+    #[test]
+    fn transpile_to_html_errors() {
+
+        let error = transpile_to_html("text\n<div>text").unwrap_err();
+
+        assert!(matches!(error, TranspileError::Syntax { line: 2, col: 1, .. }), "{:?}", error);
+        assert_eq!(error.to_string(), "2:1: <div> is never closed.");
+
+        let error = transpile_to_html("<p>${missing}</p>").unwrap_err();
+
+        assert!(matches!(error, TranspileError::Transform { line: Some(1), .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn render_html_heading() {
+
+        assert_eq!(render_html("# Hi").unwrap(), "<h1>Hi</h1>");
+    }
+
+    #[test]
+    fn render_html_passthrough() {
+
+        // <Html> is not a component
+        assert_eq!(render_html("<Html><b>${x}</b></Html>").unwrap(), "<b>${x}</b>");
+    }
+}
@@ -0,0 +1,142 @@
+//!
+//! Accessibility pass: adds landmark roles to sectioning elements
+//! and checks that heading levels are not skipped.
+//!
+
+use crate::error::Diagnostic;
+use crate::document::{
+    EnvNode,
+    EnvNodeHeader,
+    Node,
+    NodeId,
+    NodeKind,
+    NodePosition,
+    visit::{Action, TransformResult, Visitor}
+};
+
+/// Landmark roles of sectioning elements.
+const LANDMARKS : [(&str, &str); 5] = [
+    ("nav", "navigation"),
+    ("main", "main"),
+    ("header", "banner"),
+    ("footer", "contentinfo"),
+    ("aside", "complementary"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub message: String,
+    pub position: NodePosition,
+}
+
+#[derive(Default)]
+pub struct Accessibility {
+    /** Problems that do not prevent the document from being emitted. */
+    pub warnings: Vec<Warning>,
+    /** Level of the last heading. */
+    previous_level: Option<usize>,
+}
+
+impl Accessibility {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn check_heading(&mut self, level : usize, position : &NodePosition) {
+
+        if let Some(previous) = self.previous_level.filter(|previous| level > previous + 1) {
+            self.warnings.push(Warning {
+                message: format!("Heading level skipped: h{} follows h{}.", level, previous),
+                position: position.clone(),
+            });
+        }
+
+        self.previous_level = Some(level);
+    }
+
+}
+
+impl Visitor for Accessibility {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        let NodeKind::Env(EnvNode { header, .. }) = &node.kind else {
+            return Ok(Action::keep(node));
+        };
+
+        if let Some(level) = header.kind.heading_level() {
+            self.check_heading(level, &node.position);
+        }
+
+        let role = LANDMARKS
+            .iter()
+            .find(|(name, _)| *name == header.kind.get_name())
+            .map(|(_, role)| *role);
+
+        match node.kind {
+            NodeKind::Env(EnvNode { mut header, kind: env_kind })
+                if role.is_some() && !header.attrs.contains_key("role") => {
+
+                header.attrs.extend(EnvNodeHeader::generate_attrs(vec![("role", role)]));
+
+                Ok(Action::replace(Node {
+                    kind: NodeKind::Env(EnvNode { header, kind: env_kind }),
+                    ..node
+                }))
+            },
+            kind => Ok(Action::keep(Node { kind, ..node }))
+        }
+    }
+
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.warnings
+            .drain(..)
+            .map(|warning| Diagnostic::warning(warning.message, warning.position))
+            .collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::visitors::testing::emit_html;
+
+    fn emit(src : &str, accessibility : &mut Accessibility) -> String {
+
+        emit_html(src, &mut vec![Box::new(accessibility)], 1).unwrap()
+    }
+
+    #[test]
+    fn landmark_roles() {
+
+        let html = emit(
+            r#"<nav>Menu</nav><main>Content</main><footer role="none">Footer</footer>"#, 
+            &mut Accessibility::new()
+        );
+
+        assert_eq!(
+            html, 
+            r#"<nav role="navigation">Menu</nav><main role="main">Content</main><footer role="none">Footer</footer>"#
+        );
+    }
+
+    #[test]
+    fn skipped_heading_level() {
+
+        let mut accessibility = Accessibility::new();
+
+        emit("<h1>Title</h1><h2>Section</h2><h4>Too deep</h4><h2>Fine</h2>", &mut accessibility);
+
+        assert_eq!(accessibility.warnings.len(), 1);
+        assert_eq!(accessibility.warnings[0].message, "Heading level skipped: h4 follows h2.");
+
+        assert!(matches!(
+            &accessibility.warnings[0].position,
+            NodePosition::Source(span) if span.start.col() == 30
+        ));
+    }
+
+}
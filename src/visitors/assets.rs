@@ -0,0 +1,188 @@
+//!
+//! Collects all assets referenced by the document (src and href attributes)
+//! so build tools can copy or optimize them.
+//!
+
+use crate::document::{
+    EnvNode,
+    LeafNode,
+    Node,
+    NodeId,
+    NodeKind,
+    NodePosition,
+    visit::{Action, TransformResult, Visitor}
+};
+
+use crate::json;
+
+/// Attributes that reference assets.
+const ASSET_ATTRS : [&str; 2] = ["src", "href"];
+
+#[derive(Debug, Clone)]
+pub struct AssetRef {
+    /** Referenced URL as written in the document. */
+    pub url: String,
+    /** Name of the element referencing the asset. */
+    pub element: String,
+    /** Attribute containing the URL. */
+    pub attr: String,
+    /** Position of the referencing element. */
+    pub position: NodePosition,
+}
+
+impl AssetRef {
+
+    ///
+    /// Checks if the asset is hosted elsewhere (absolute http(s) or protocol-relative URL).
+    /// 
+    pub fn is_remote(&self) -> bool {
+        self.url.starts_with("http://") || 
+        self.url.starts_with("https://") || 
+        self.url.starts_with("//")
+    }
+
+}
+
+///
+/// Checks if the URL points to something that can be fetched.
+/// Excludes fragments within the document and schemes like mailto: or data:.
+/// 
+fn is_asset_url(url : &str) -> bool {
+
+    if url.is_empty() || url.starts_with('#') {
+        return false;
+    }
+
+    match url.find(':') {
+        // a ':' after the first '/' is part of the path
+        Some(i) => url.find('/').is_some_and(|slash| slash < i) 
+            || url.starts_with("http:") 
+            || url.starts_with("https:"),
+        None => true,
+    }
+}
+
+#[derive(Default)]
+pub struct AssetCollector {
+    /** Assets in document order. */
+    pub assets: Vec<AssetRef>,
+}
+
+impl AssetCollector {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Serializes the collected assets into a JSON array.
+    /// Positions are 1-based and null for inserted nodes.
+    /// 
+    pub fn to_json(&self) -> String {
+
+        let mut out = String::from("[");
+
+        for (i, asset) in self.assets.iter().enumerate() {
+
+            if i > 0 {
+                out.push(',');
+            }
+
+            out.push_str("{\"url\":");
+            json::write_string(&mut out, &asset.url);
+            out.push_str(",\"element\":");
+            json::write_string(&mut out, &asset.element);
+            out.push_str(",\"attr\":");
+            json::write_string(&mut out, &asset.attr);
+            out.push_str(",\"remote\":");
+            out.push_str(if asset.is_remote() { "true" } else { "false" });
+            out.push_str(",\"position\":");
+
+            match &asset.position {
+                NodePosition::Source(span) => out.push_str(&format!(
+                    "{{\"line\":{},\"col\":{}}}",
+                    span.start.line() + 1,
+                    span.start.col() + 1
+                )),
+                NodePosition::Inserted => out.push_str("null"),
+            }
+
+            out.push('}');
+        }
+
+        out.push(']');
+
+        out
+    }
+
+}
+
+impl Visitor for AssetCollector {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        if let NodeKind::Env(EnvNode { header, .. }) = &node.kind {
+
+            for attr in ASSET_ATTRS {
+
+                if let Some(Some(Node { kind: NodeKind::Leaf(LeafNode::Text(url)), .. })) = header.attrs.get(attr) {
+
+                    if is_asset_url(url) {
+                        self.assets.push(AssetRef {
+                            url: url.clone(),
+                            element: header.kind.get_name().to_string(),
+                            attr: attr.to_string(),
+                            position: node.position.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(Action::keep(node))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::parse::parse;
+
+    #[test]
+    fn collect_assets() {
+
+        let (document, _) = parse(concat!(
+            "<link rel=\"stylesheet\" href=\"https://cdn.example.com/style.css\"/>\n",
+            "<img src=\"images/cat.png\"/>\n",
+            "[mail](mailto:me@example.com) [section](#intro)\n",
+        ));
+
+        let mut collector = AssetCollector::new();
+
+        transform(document, &mut vec![Box::new(&mut collector)], 1).unwrap();
+
+        let assets = &collector.assets;
+
+        assert_eq!(assets.len(), 2);
+
+        assert_eq!(assets[0].url, "https://cdn.example.com/style.css");
+        assert_eq!(assets[0].element, "link");
+        assert!(assets[0].is_remote());
+
+        assert_eq!(assets[1].url, "images/cat.png");
+        assert_eq!(assets[1].element, "img");
+        assert!(!assets[1].is_remote());
+
+        assert_eq!(
+            collector.to_json(),
+            concat!(
+                r#"[{"url":"https://cdn.example.com/style.css","element":"link","attr":"href","remote":true,"position":{"line":1,"col":1}},"#,
+                r#"{"url":"images/cat.png","element":"img","attr":"src","remote":false,"position":{"line":2,"col":1}}]"#
+            )
+        );
+    }
+
+}
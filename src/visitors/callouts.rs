@@ -0,0 +1,143 @@
+//!
+//! Expands admonitions into styled containers.
+//!
+//! <Warning>Contents</Warning>
+//!
+//! becomes
+//!
+//! <div class="callout callout-warning"><div class="callout-title">Warning</div>Contents</div>
+//!
+//! The title can be overridden using the title attribute.
+//! This must run before ComponentInsert as callouts would otherwise be treated as components.
+//!
+
+use std::collections::VecDeque;
+
+use crate::document::{
+    EnvNode,
+    EnvNodeKind,
+    EnvNodeHeader,
+    EnvNodeHeaderKind,
+    Node,
+    NodeBuilder,
+    NodeId,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor}
+};
+
+/** Callout kinds handled by default. */
+pub const DEFAULT_KINDS : [&str; 5] = ["Note", "Tip", "Important", "Warning", "Caution"];
+
+pub struct Callouts {
+    ///
+    /// Element names that are treated as callouts.
+    /// The callout type is the lowercase element name.
+    ///
+    pub kinds: Vec<String>,
+    ///
+    /// Base class of all callouts.
+    /// Containers get the classes "<class> <class>-<type>", titles get "<class>-title".
+    ///
+    pub class: String,
+}
+
+impl Default for Callouts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Callouts {
+
+    /** Create new callout visitor handling Note, Tip, Important, Warning, and Caution. */
+    pub fn new() -> Self {
+        Self {
+            kinds: DEFAULT_KINDS
+                .map(String::from)
+                .to_vec(),
+            class: "callout".to_string(),
+        }
+    }
+
+}
+
+impl Visitor for Callouts {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+        match node.kind {
+            NodeKind::Env(
+                EnvNode {
+                    header: EnvNodeHeader {
+                        kind: EnvNodeHeaderKind::Other(name),
+                        mut attrs,
+                        ..
+                    },
+                    kind,
+                }
+            ) if self.kinds.contains(&name) => {
+
+                let title = attrs.shift_remove("title").flatten().unwrap_or(NodeBuilder::text(&name));
+
+                let title = NodeBuilder::env("div")
+                    .attr("class", format!("{}-title", self.class))
+                    .child(title)
+                    .build();
+
+                let mut children = match kind {
+                    EnvNodeKind::Open(children) => children,
+                    EnvNodeKind::SelfClosing => VecDeque::new(),
+                };
+
+                children.push_front(title);
+
+                let class = format!("{} {}-{}", self.class, self.class, name.to_lowercase());
+
+                Ok(Action::replace(
+                    NodeBuilder::env("div")
+                        .attr("class", class)
+                        .children(children)
+                        .position(node.position)
+                        .build()
+                ))
+            },
+            _ => Ok(Action::keep(node))
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::visitors::testing::emit_html;
+
+    fn emit(src : &str, callouts : Callouts) -> String {
+
+        emit_html(src, &mut vec![Box::new(callouts)], 1).unwrap()
+    }
+
+    #[test]
+    fn warning() {
+
+        assert_eq!(
+            emit("<Warning>Do not do this.</Warning>", Callouts::new()),
+            r#"<div class="callout callout-warning"><div class="callout-title">Warning</div>Do not do this.</div>"#
+        );
+
+        assert_eq!(
+            emit(r#"<Warning title="Careful">Do not do this.</Warning>"#, Callouts {
+                class: "admonition".to_string(),
+                ..Callouts::new()
+            }),
+            r#"<div class="admonition admonition-warning"><div class="admonition-title">Careful</div>Do not do this.</div>"#
+        );
+
+        // only configured kinds are expanded
+        assert_eq!(
+            emit("<Danger>Contents</Danger>", Callouts::new()),
+            "<Danger>Contents</Danger>"
+        );
+    }
+
+}
@@ -1,11 +1,25 @@
-///
-/// Cleans up text and removes nodes that do not contribute to the contents of the document.
-/// These include empty lines at the start or end of env bodies.
-///
+//!
+//! Cleans up text and removes nodes that do not contribute to the contents of the document.
+//! These include empty lines at the start or end of env bodies and comments.
+//!
+//! Only block containers are trimmed. The boundaries of inline elements lie within 
+//! the surrounding prose, where blank lines may separate paragraphs.
+//! Fragments are transparent, their children are trimmed along with their siblings.
+//!
+//! Optionally, runs of whitespace in text are collapsed into a single space,
+//! except within raw envs like <Code> or components with content="raw".
+//!
+
+use std::collections::{HashSet, VecDeque};
 
+use crate::parse::dynamic_parse::{component_name_definition_attrs, ContentParseMode};
+use crate::visitors::html_emit::{BLOCK_ELEMENTS, INLINE_ELEMENTS, PREFORMATTED_ELEMENTS};
 use crate::document::{
     EnvNode,
+    EnvNodeHeader,
+    EnvNodeHeaderKind,
     EnvNodeKind,
+    EquationKind,
     LeafNode,
     Node, 
     NodeId,
@@ -13,7 +27,17 @@ use crate::document::{
     visit::{Action, TransformResult, Visitor}
 };
 
-pub struct Cleanup;
+#[derive(Default)]
+pub struct Cleanup {
+    /** Keeps comments in the document, e.g. to emit them as HTML comments (see HTMLEmitter::comments). */
+    pub keep_comments: bool,
+    /** Replaces runs of whitespace in text with a single space. */
+    pub collapse_whitespace: bool,
+    /** Names of components defined with content="raw". */
+    raw_components: HashSet<String>,
+    /** Id of the outermost raw env that is currently being visited. */
+    raw_ancestor: Option<NodeId>,
+}
 
 fn is_empty_text(node : &Node) -> bool {
 
@@ -27,56 +51,357 @@ fn is_empty_text(node : &Node) -> bool {
 
 }
 
+/** Replaces every run of whitespace with a single space. Returns None if nothing changes. */
+fn collapse_whitespace(text : &str) -> Option<String> {
+
+    let mut collapsed = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if !c.is_whitespace() {
+            collapsed.push(c);
+        } else if !collapsed.ends_with(' ') {
+            collapsed.push(' ');
+        }
+    }
+
+    (collapsed != text).then_some(collapsed)
+}
+
+fn is_block(header : &EnvNodeHeader) -> bool {
+    match &header.kind {
+        EnvNodeHeaderKind::Fragment => false,
+        EnvNodeHeaderKind::Other(name) => !INLINE_ELEMENTS.contains(&name.as_str()),
+        _ => true,
+    }
+}
+
+/** Checks if the node is known to be rendered as a block. */
+fn is_block_node(node : &Node) -> bool {
+    match &node.kind {
+        NodeKind::Env(EnvNode { header, .. }) => match &header.kind {
+            EnvNodeHeaderKind::Other(name) => BLOCK_ELEMENTS.contains(&name.as_str()),
+            EnvNodeHeaderKind::Heading(_) 
+            | EnvNodeHeaderKind::Eq(EquationKind::Block)
+            | EnvNodeHeaderKind::ComponentDefinition => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+///
+/// Checks if the first (or last) non-blank node is a block, looking into fragments.
+/// None if there is only whitespace.
+///
+fn edge_is_block(node : &Node, from_end : bool) -> Option<bool> {
+    match &node.kind {
+        NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(children) }) 
+            if header.kind == EnvNodeHeaderKind::Fragment => {
+
+            let edge = |child : &Node| edge_is_block(child, from_end);
+
+            if from_end {
+                children.iter().rev().find_map(edge)
+            } else {
+                children.iter().find_map(edge)
+            }
+        },
+        _ if is_empty_text(node) => None,
+        _ => Some(is_block_node(node)),
+    }
+}
+
+///
+/// Removes whitespace-only text between block elements and at the start or end of the children.
+/// Whitespace next to text, inline elements or elements that may be inline (e.g. components) is kept.
+/// 
+/// Fragments are transparent: their children are cleaned up as part of the sequence they are in.
+/// before and after tell whether the closest non-blank node outside of the sequence is a block 
+/// (None at the boundaries of the container).
+/// Returns the kept children and whether any have been removed.
+///
+fn remove_blank_between_blocks(
+    children : VecDeque<Node>, 
+    before : Option<bool>, 
+    after : Option<bool>
+) -> (VecDeque<Node>, bool) {
+
+    // for each child: whether the closest non-blank node before it is a block
+    let mut after_block = Vec::with_capacity(children.len());
+
+    let mut previous_is_block = before;
+
+    for child in &children {
+
+        after_block.push(previous_is_block);
+
+        previous_is_block = edge_is_block(child, true).or(previous_is_block);
+    }
+
+    let mut next_is_block = after;
+
+    let mut changed = false;
+
+    let mut kept : VecDeque<Node> = VecDeque::with_capacity(children.len());
+
+    for (child, after_block) in children.into_iter().zip(after_block).rev() {
+
+        let before_block = next_is_block;
+
+        next_is_block = edge_is_block(&child, false).or(next_is_block);
+
+        match child.kind {
+            NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(fragment_children) }) 
+                if header.kind == EnvNodeHeaderKind::Fragment => {
+
+                let (fragment_children, fragment_changed) = remove_blank_between_blocks(
+                    fragment_children, 
+                    after_block, 
+                    before_block
+                );
+
+                changed |= fragment_changed;
+
+                kept.push_front(Node { 
+                    kind: NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(fragment_children) }), 
+                    ..child 
+                });
+            },
+            _ if !is_empty_text(&child) => kept.push_front(child),
+            _ => match (after_block, before_block) {
+                (Some(previous), Some(next)) if !(previous && next) => kept.push_front(child),
+                // at the start or end of the container or between blocks
+                _ => changed = true,
+            },
+        }
+    }
+
+    (kept, changed)
+}
+
+impl Cleanup {
+
+    pub fn new(keep_comments : bool) -> Self {
+        Self { keep_comments, ..Self::default() }
+    }
+
+    /** Checks if the whitespace in the env is significant. */
+    fn is_raw(&self, header : &EnvNodeHeader) -> bool {
+        match &header.kind {
+            EnvNodeHeaderKind::Code | EnvNodeHeaderKind::Eq(_) => true,
+            EnvNodeHeaderKind::Other(name) => {
+                PREFORMATTED_ELEMENTS.contains(&name.as_str()) || self.raw_components.contains(name)
+            },
+            _ => false,
+        }
+    }
+
+    fn register_component(&mut self, header : &EnvNodeHeader) {
+
+        let is_raw = matches!(
+            ContentParseMode::from_attrs(&header.attrs),
+            Ok(ContentParseMode::Raw | ContentParseMode::RawStrict)
+        );
+
+        if let Some(name) = component_name_definition_attrs(&header.attrs).filter(|_| is_raw) {
+            self.raw_components.insert(name.clone());
+        }
+    }
+
+}
+
 impl Visitor for Cleanup {
 
     fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        if let NodeKind::Env(EnvNode { header, .. }) = &node.kind {
+
+            if header.kind == EnvNodeHeaderKind::ComponentDefinition {
+                self.register_component(header);
+            }
+
+            if self.raw_ancestor.is_none() && self.is_raw(header) {
+                self.raw_ancestor = Some(node.id);
+            }
+        }
+
         match node.kind {
+            NodeKind::Leaf(LeafNode::Comment(_)) if !self.keep_comments => Ok(Action::remove(node)),
+            NodeKind::Leaf(LeafNode::Text(text)) if self.collapse_whitespace && self.raw_ancestor.is_none() => {
+                match collapse_whitespace(&text) {
+                    Some(text) => Ok(Action::replace(Node { kind: NodeKind::Leaf(LeafNode::Text(text)), ..node })),
+                    None => Ok(Action::keep(Node { kind: NodeKind::Leaf(LeafNode::Text(text)), ..node })),
+                }
+            },
             NodeKind::Env(
                 EnvNode { 
                     kind: EnvNodeKind::Open(mut children), 
                     header,
                 }
-            ) => {
-                let front_is_empty = children.front().map_or(
-                    false,
-                    is_empty_text
-                );
+            ) if is_block(&header) && !self.is_raw(&header) => {
 
-                let back_is_empty = children.back().map_or(
-                    false,
-                    is_empty_text
-                );
+                let changed;
 
-                if front_is_empty {
-                    children.pop_front();
-                }
-
-                if back_is_empty {
-                    children.pop_back();
-                }
+                (children, changed) = remove_blank_between_blocks(children, None, None);
 
                 let node = Node {
                     kind: NodeKind::Env(
                         EnvNode  {
                             kind: EnvNodeKind::Open(children),
-                            header: header.clone(),
+                            header,
                         }
                     ),
                     ..node
                 };
 
-                if !back_is_empty && !front_is_empty {
-
-                    Ok(Action::keep(node))
-
-                } else {
-
+                if changed {
                     Ok(Action::replace(node))
+                } else {
+                    Ok(Action::keep(node))
                 }
             },
-            _ => Ok(Action::keep(node))
+            kind => Ok(Action::keep(Node { kind, ..node }))
+        }
+
+    }
+
+    fn leave(&mut self, _node : &Node, original_id : NodeId, _parent_id : Option<NodeId>) {
+        if self.raw_ancestor == Some(original_id) {
+            self.raw_ancestor = None;
         }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::parse::parse;
+    use crate::visitors::components::components;
+    use crate::visitors::testing::{to_html, to_html_with};
+    use crate::visitors::variables::Variables;
+
+    fn emit_with(src : &str, cleanup : Cleanup) -> String {
+
+        let (document, _) = parse(src);
+
+        let document = transform(document, &mut vec![Box::new(cleanup)], 2).unwrap();
+
+        // emits whatever comments the cleanup kept
+        to_html_with(document, |emitter| emitter.comments = true).unwrap()
+    }
+
+    fn emit(src : &str) -> String {
+        emit_with(src, Cleanup::default())
+    }
+
+    #[test]
+    fn keep_paragraph_separators() {
+
+        // blank lines at the boundaries of block containers and between blocks are removed
+        assert_eq!(emit("<div>\n\n<p>First</p>\n\n<p>Second</p>\n\n</div>"), "<div><p>First</p><p>Second</p></div>");
+
+        // the blank line after the text separates the paragraphs, also within fragments
+        assert_eq!(emit("<div>First<>\n\n<p>Second</p></></div>"), "<div>First\n\n<p>Second</p></div>");
+
+        assert_eq!(emit("a <em> </em>b"), "a <em> </em>b");
+    }
+
+    #[test]
+    fn blank_between_blocks() {
+
+        // comments leave multiple blank nodes behind
+        assert_eq!(
+            emit("<div>\n\n <p/>\n/** a */\n\n<p>A</p>\n  \n<ul><li>x</li> <li>y</li></ul>\n/** b */\n</div>"),
+            "<div><p></p><p>A</p><ul><li>x</li><li>y</li></ul></div>"
+        );
+
+        // whitespace next to text and inline elements is significant
+        assert_eq!(
+            emit("<p><em>a</em> <b>b</b> $x$ <p>c</p> d</p>"),
+            "<p><em>a</em> <b>b</b> <Eq>x</Eq> <p>c</p> d</p>"
+        );
+
+        assert_eq!(
+            emit("<p>Press <kbd>Ctrl</kbd> <kbd>C</kbd></p><p><img src=\"a.png\"/> <img src=\"b.png\"/></p>"),
+            "<p>Press <kbd>Ctrl</kbd> <kbd>C</kbd></p><p><img src=\"a.png\" loading=\"lazy\" decoding=\"async\"> <img src=\"b.png\" loading=\"lazy\" decoding=\"async\"></p>"
+        );
+
+        // elements which are not known to be blocks may be rendered inline
+        assert_eq!(emit("<div><Icon/> <Icon/>\n\n<p>a</p></div>"), "<div><Icon/> <Icon/>\n\n<p>a</p></div>");
+    }
+
+    #[test]
+    fn blank_in_fragments() {
+
+        // fragments do not separate the blocks around them
+        assert_eq!(emit("<div><p>First</p><>\n\n<p>Second</p></></div>"), "<div><p>First</p><p>Second</p></div>");
+        assert_eq!(emit("<div>\n<><>\n<p>a</p>\n</>\n</>\n<p>b</p></div>"), "<div><p>a</p><p>b</p></div>");
+
+        // blocks inside of fragments are only blocks on that side
+        assert_eq!(emit("<div><p>a</p> <><p>b</p>c</> <p>d</p></div>"), "<div><p>a</p><p>b</p>c <p>d</p></div>");
+
+        // fragment-in-fragment as produced by nested components
+        let (document, _) = parse(concat!(
+            "<Component Card><div>\n${children}\n</div></Component>",
+            "<Component Item><p>${children}</p>\n</Component>",
+            "<Card>\n<Item>a</Item>\n<Item>b</Item>\n</Card>",
+        ));
+
+        let (register, insert) = components();
+
+        let document = transform(document, &mut vec![Box::new(register), Box::new(insert)], 2).unwrap();
+        let document = transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap();
+        let document = transform(document, &mut vec![Box::new(Cleanup::default())], 2).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, "<div><p>a</p><p>b</p></div>");
+    }
+
+    #[test]
+    fn collapse_whitespace() {
+
+        let collapse = || Cleanup { collapse_whitespace: true, ..Cleanup::default() };
+
+        assert_eq!(
+            emit_with("<p>\n  Some   text\n\n with <em> spaced </em>  words.\n</p>", collapse()),
+            "<p> Some text with <em> spaced </em> words. </p>"
+        );
+
+        assert_eq!(
+            emit_with("<p>a  b</p><Code>x  =\n  1</Code><pre>  <b>y   z</b></pre>", collapse()),
+            "<p>a b</p><Code>x  =\n  1</Code><pre>  <b>y   z</b></pre>"
+        );
+
+        // components are inserted after the cleanup
+        let (document, _) = parse("<Component Listing content=\"raw\"><pre>${children}</pre></Component><Listing>a  b</Listing>");
+
+        let document = transform(document, &mut vec![Box::new(collapse())], 2).unwrap();
+
+        let (register, insert) = components();
+
+        let document = transform(document, &mut vec![Box::new(register), Box::new(insert)], 2).unwrap();
+        let document = transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, "<pre>a  b</pre>");
+    }
+
+    #[test]
+    fn comments() {
+
+        let src = "<div>\n/** TODO: rewrite --> soon */\n<p>Text</p></div>";
+
+        assert_eq!(emit(src), "<div><p>Text</p></div>");
 
+        assert_eq!(
+            emit_with(src, Cleanup::new(true)), 
+            "<div><!-- TODO: rewrite --&gt; soon -->\n<p>Text</p></div>"
+        );
     }
 
 }
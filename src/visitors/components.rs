@@ -1,16 +1,38 @@
-use std::collections::VecDeque;
+//!
+//! Components works by simply transforming the <Component> tag 
+//! into a variable definition.
+//! Usage of the component is then transformed from 
+//! <MyComponent foo="bar">Contents</MyComponent>
+//! <> <var foo="bar"/><var children>Contents</var> ${MyComponent} </>
+//! 
+//! Components may declare parameters after their name:
+//! <Component Button variant="primary" label>...</Component>
+//! Parameters with a value are optional and default to that value, 
+//! parameters without a value are required.
+//! Attributes that are not declared as parameters are passed on as variables as well.
+//! 
+//! The parameters are defined in the scope of the inserted fragment, so they shadow 
+//! variables of the caller and go out of scope after the component.
+//! The body is expanded into a scope of its own: a <var> in the body shadows a parameter
+//! of the same name until the env it is defined in is left.
+//! 
+//! Content can be routed to variables other than children using named slots:
+//! <Card><slot name="header">Title</slot>Body text</Card>
+//! defines header as "Title" and children as "Body text".
+//! Slots must be referenced in the component definition.
+//! 
+//! Definitions are moved to the beginning of the module, so components 
+//! may be used before they are defined.
+//! 
 
-///
-/// Components works by simply transforming the <Component> tag 
-/// into a variable definition.
-/// Usage of the component is then transformed from 
-/// <MyComponent foo="bar">Contents</MyComponent>
-/// <> <var foo="bar"/><var children>Contents</var> ${MyComponent} </>
-/// 
-/// 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::rc::Rc;
 
 use crate::document::{
     EnvNode,
+    EnvNodeAttrs,
     EnvNodeKind,
     LeafNode,
     Node, 
@@ -22,11 +44,184 @@ use crate::document::{
     visit::{Action, TransformResult, Visitor, VisitError}
 };
 
-use crate::parse::dynamic_parse::component_name_definition_attrs;
+use crate::document::visit::transform;
+use crate::parse::dynamic_parse::{component_name_definition_attrs, RAW_HTML_ENV};
+use crate::visitors::callouts::DEFAULT_KINDS;
+
+
+#[derive(Debug, Clone, Default)]
+pub struct ComponentDefinition {
+    /** Declared parameters with their default values. */
+    pub parameters: EnvNodeAttrs,
+    /** Names of all variables referenced in the component body. */
+    pub variables: HashSet<String>,
+}
+
+/// Definitions of all components by name.
+pub type ComponentDefinitions = Rc<RefCell<HashMap<String, ComponentDefinition>>>;
+
+/// Attributes of component definitions that control parsing and are not parameters.
+const PARSER_ATTRS : [&str; 1] = ["content"];
+
+/// Variable definitions of registered components that are yet to be moved to the beginning of the module.
+type HoistedDefinitions = Rc<RefCell<VecDeque<Node>>>;
+
+pub struct ComponentRegister {
+    definitions: ComponentDefinitions,
+    hoisted: HoistedDefinitions,
+}
+
+pub struct ComponentInsert {
+    definitions: ComponentDefinitions,
+    hoisted: HoistedDefinitions,
+}
+
+///
+/// Creates a pair of visitors sharing the component definitions.
+/// ComponentRegister must run before ComponentInsert.
+/// 
+pub fn components() -> (ComponentRegister, ComponentInsert) {
+
+    let definitions = ComponentDefinitions::default();
+    let hoisted = HoistedDefinitions::default();
+
+    (
+        ComponentRegister { definitions: definitions.clone(), hoisted: hoisted.clone() },
+        ComponentInsert { definitions, hoisted },
+    )
+}
+
+///
+/// Collects the names of all variable expressions in the nodes and their attributes.
+/// 
+fn collect_variables<'a>(nodes : impl IntoIterator<Item = &'a Node>, variables : &mut HashSet<String>) {
+    for node in nodes {
+        match &node.kind {
+            NodeKind::Leaf(LeafNode::VariableExpression(name)) => { variables.insert(name.clone()); },
+            NodeKind::Env(EnvNode { header, kind }) => {
+
+                collect_variables(header.attrs.values().flatten(), variables);
+
+                if let EnvNodeKind::Open(children) = kind {
+                    collect_variables(children, variables);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Envs with capitalized names are treated as component usages.
+fn is_component_name(name : &str) -> bool {
+    // <Html> is built in
+    name.chars().next().is_some_and(|c| c.is_uppercase()) && name != RAW_HTML_ENV
+}
+
+/// Capitalized envs handled by other visitors, which are not reported as undefined components.
+fn is_built_in(name : &str) -> bool {
+    name == RAW_HTML_ENV || name == "Tabs" || name == "Tab" || DEFAULT_KINDS.contains(&name)
+}
+
+fn slot_name(node : &Node) -> Option<Result<String, VisitError>> {
+    match &node.kind {
+        NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), attrs, .. }, .. })
+            if name == "slot" => Some(match attrs.get("name") {
+                Some(Some(Node { kind: NodeKind::Leaf(LeafNode::Text(name)), .. })) => Ok(name.clone()),
+                _ => Err(VisitError::Unknown("Slot must have a name.".to_string())),
+            }),
+        _ => None,
+    }
+}
+
+impl ComponentRegister {
+
+    /** Components registered so far by name. */
+    pub fn definitions(&self) -> &ComponentDefinitions {
+        &self.definitions
+    }
+
+}
+
+impl ComponentInsert {
+
+    ///
+    /// Merges the attributes passed to a component over its declared defaults.
+    /// 
+    /// Parameters provided by slots are not required as attributes.
+    /// 
+    fn parameters(&self, name : &str, attrs : EnvNodeAttrs, slots : &EnvNodeAttrs) -> Result<EnvNodeAttrs, VisitError> {
+
+        let definitions = self.definitions.borrow();
+
+        let Some(definition) = definitions.get(name) else {
+            return Ok(attrs);
+        };
+
+        let declared = &definition.parameters;
+
+        let mut parameters = EnvNodeAttrs::with_capacity(declared.len() + attrs.len());
+
+        for (key, default) in declared {
+            match (attrs.get(key).or(slots.get(key)), default) {
+                (Some(_), _) => {},
+                (None, Some(default)) => { parameters.insert(key.clone(), Some(default.clone())); },
+                (None, None) => return Err(VisitError::Unknown(
+                    format!("Component {} requires parameter \"{}\".", name, key)
+                )),
+            }
+        }
+
+        parameters.extend(attrs);
+
+        Ok(parameters)
+    }
+
+    ///
+    /// Moves all <slot> elements out of children.
+    /// Returns the contents of each slot by name.
+    /// 
+    fn take_slots(&self, name : &str, children : VecDeque<Node>) -> Result<(EnvNodeAttrs, VecDeque<Node>), VisitError> {
+
+        let mut slots = EnvNodeAttrs::new();
+        let mut rest = VecDeque::with_capacity(children.len());
+
+        for child in children {
+
+            let slot = match slot_name(&child) {
+                Some(slot) => slot?,
+                None => {
+                    rest.push_back(child);
+                    continue;
+                }
+            };
+
+            let is_known = self.definitions
+                .borrow()
+                .get(name)
+                .is_none_or(|definition| definition.variables.contains(&slot));
+
+            if !is_known {
+                return Err(VisitError::Unknown(
+                    format!("Component {} has no slot \"{}\".", name, slot)
+                ));
+            }
+
+            if slots.contains_key(&slot) {
+                return Err(VisitError::Unknown(format!("Duplicate slot \"{}\".", slot)));
+            }
 
+            let contents = match child.kind {
+                NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(contents), .. }) => contents,
+                _ => VecDeque::new(),
+            };
 
-pub struct ComponentRegister;
-pub struct ComponentInsert;
+            slots.insert(slot, Some(Node { kind: NodeKind::new_fragment(contents), ..child }));
+        }
+
+        Ok((slots, rest))
+    }
+
+}
 
 impl Visitor for ComponentRegister {
 
@@ -48,19 +243,40 @@ impl Visitor for ComponentRegister {
                 let name = component_name_definition_attrs(&attrs).ok_or(
                     VisitError::Unknown("Component must have a name.".to_string())
                 )?;
+
+                let parameters = attrs
+                    .iter()
+                    .skip(1)
+                    .filter(|(key, _)| !PARSER_ATTRS.contains(&key.as_str()))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect::<EnvNodeAttrs>();
+
+                let mut variables = HashSet::new();
+
+                collect_variables(&children, &mut variables);
+
+                self.definitions.borrow_mut().insert(
+                    name.clone(), 
+                    ComponentDefinition { parameters, variables }
+                );
                 
                 let children_container = Node {
                     kind: NodeKind::new_fragment(children),
                     ..node
                 };
 
-                let node = Node {
+                // ComponentInsert moves the definition to the beginning of the module
+                self.hoisted.borrow_mut().push_back(Node {
                     kind: NodeKind::new_variable_definition(name, children_container),
                     id: Node::generate_id(),
                     position: NodePosition::Inserted
-                };
+                });
 
-                Ok(Action::replace(node))
+                Ok(Action::remove(Node {
+                    kind: NodeKind::new_fragment(VecDeque::new()),
+                    id: Node::generate_id(),
+                    position: NodePosition::Inserted
+                }))
             },
             _ => Ok(Action::keep(node))
         }
@@ -69,8 +285,24 @@ impl Visitor for ComponentRegister {
 }
 
 impl Visitor for ComponentInsert {
-    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+    fn enter(&mut self, node : Node, parent_id : Option<NodeId>) -> TransformResult {
         match node.kind {
+            NodeKind::Env(
+                EnvNode { 
+                    header: header @ EnvNodeHeader { kind: EnvNodeHeaderKind::Module, .. },
+                    kind: EnvNodeKind::Open(mut children),
+                }
+            ) if parent_id.is_none() && !self.hoisted.borrow().is_empty() => {
+
+                let mut definitions = std::mem::take(&mut *self.hoisted.borrow_mut());
+
+                definitions.append(&mut children);
+
+                Ok(Action::replace(Node {
+                    kind: NodeKind::Env(EnvNode::new_open(header, definitions)),
+                    ..node
+                }))
+            },
             NodeKind::Env(
                 EnvNode { 
                     header: EnvNodeHeader { 
@@ -82,17 +314,26 @@ impl Visitor for ComponentInsert {
                     ..
                     // TODO: should "var" be an internal type? 
                 }
-            ) if name.chars().next().map_or(false, |c| c.is_uppercase()) => {
+            ) if is_component_name(&name) => {
+
+                let (slots, component_children) = match kind {
+                    EnvNodeKind::Open(children) => {
+                        let (slots, rest) = self.take_slots(&name, children)?;
+
+                        (slots, Some(rest))
+                    },
+                    EnvNodeKind::SelfClosing => (EnvNodeAttrs::new(), None),
+                };
+
+                let mut attrs = self.parameters(&name, attrs, &slots)?;
+
+                attrs.extend(slots);
+
                 // capacity of the children container of <></>
                 // list of variable definitions and
                 // variable insertion of the actual component (+1)
                 let mut capacity = attrs.len() + 1;
 
-                let component_children = match kind {
-                    EnvNodeKind::Open(children) => Some(children),
-                    EnvNodeKind::SelfClosing => None,
-                };
-
                 if component_children.is_some() {
                     capacity += 1;
                 }
@@ -115,8 +356,8 @@ impl Visitor for ComponentInsert {
                     });
                 }
 
-                match component_children {
-                    Some(component_children) => children.push_back(
+                if let Some(component_children) = component_children {
+                    children.push_back(
                         Node::new_variable_definition(
                             "children",
                             Node {
@@ -125,9 +366,8 @@ impl Visitor for ComponentInsert {
                                 ..node
                             }
                         ),
-                    ),
-                    None => {}
-                };
+                    );
+                }
 
                 // insert the component
                 children.push_back(Node {
@@ -149,5 +389,264 @@ impl Visitor for ComponentInsert {
     }
 }
 
-// TODO: test
+/// Collects the names of all used components in order of first use.
+#[derive(Default)]
+struct ComponentUsages {
+    names: Vec<String>,
+}
+
+impl Visitor for ComponentUsages {
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        if let NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), .. }, .. }) = &node.kind {
+            if is_component_name(name) && !is_built_in(name) && !self.names.contains(name) {
+                self.names.push(name.clone());
+            }
+        }
+
+        Ok(Action::keep(node))
+    }
+}
+
+#[derive(Debug)]
+pub struct ComponentReport {
+    /** Defined components sorted by name. */
+    pub defined: Vec<(String, ComponentDefinition)>,
+    /** Components that are used but never defined, in order of first use. */
+    pub undefined: Vec<String>,
+}
+
+///
+/// Lists all components defined in the document and all components used without a definition.
+/// Built-in envs like <Tabs> and the default callouts are not reported.
+/// 
+pub fn inspect_components(document : Node) -> Result<ComponentReport, VisitError> {
+
+    let (mut register, _) = components();
+    let mut usages = ComponentUsages::default();
+
+    transform(document, &mut vec![Box::new(&mut register), Box::new(&mut usages)], 1)?;
+
+    let definitions = register.definitions().borrow();
+
+    let mut defined = definitions
+        .iter()
+        .map(|(name, definition)| (name.clone(), definition.clone()))
+        .collect::<Vec<_>>();
+
+    defined.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let undefined = usages.names
+        .into_iter()
+        .filter(|name| !definitions.contains_key(name))
+        .collect();
+
+    Ok(ComponentReport { defined, undefined })
+}
+
+impl fmt::Display for ComponentReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        writeln!(f, "Defined components:")?;
+
+        for (name, definition) in &self.defined {
+
+            write!(f, "  {}", name)?;
+
+            for (key, default) in &definition.parameters {
+                match default {
+                    Some(Node { kind: NodeKind::Leaf(LeafNode::Text(default)), .. }) => write!(f, " {}=\"{}\"", key, default)?,
+                    Some(_) => write!(f, " {}=...", key)?,
+                    None => write!(f, " {}", key)?,
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        writeln!(f, "Undefined components:")?;
+
+        for name in &self.undefined {
+            writeln!(f, "  {}", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::parse::parse;
+    use crate::visitors::testing::to_html;
+    use crate::visitors::variables::Variables;
+
+    fn emit(src : &str) -> Result<String, VisitError> {
+
+        let (document, _) = parse(src);
+
+        let (register, insert) = components();
+
+        let document = transform(document, &mut vec![Box::new(register)], 1)?;
+        let document = transform(document, &mut vec![Box::new(insert)], 1)?;
+        let document = transform(document, &mut vec![Box::new(Variables::new())], 1)?;
+
+        to_html(document)
+    }
+
+    const BUTTON : &str = r#"<Component Button variant="primary" label><button class="${variant}">${label}</button></Component>"#;
+
+    #[test]
+    fn default_parameters() {
+
+        assert_eq!(
+            emit(&format!(r#"{}<Button label="Save"/>"#, BUTTON)).unwrap(),
+            r#"<button class="primary">Save</button>"#
+        );
+
+        assert_eq!(
+            emit(&format!(r#"{}<Button label="Delete" variant="danger"/>"#, BUTTON)).unwrap(),
+            r#"<button class="danger">Delete</button>"#
+        );
+    }
+
+    const CARD : &str = r#"<Component Card><div><h2>${header}</h2>${children}<p>${footer}</p></div></Component>"#;
+
+    #[test]
+    fn named_slots() {
+
+        assert_eq!(
+            emit(&format!(
+                r#"{}<Card><slot name="header">Title</slot>Body <b>text</b><slot name="footer">End</slot></Card>"#, 
+                CARD
+            )).unwrap(),
+            "<div><h2>Title</h2>Body <b>text</b><p>End</p></div>"
+        );
+    }
+
+    #[test]
+    fn invalid_slots() {
+
+        let error = emit(&format!(
+            r#"{}<Card><slot name="header">A</slot><slot name="header">B</slot></Card>"#, 
+            CARD
+        )).unwrap_err();
+
+        assert_eq!(error.to_string(), "at 1:85: Duplicate slot \"header\".");
+
+        let error = emit(&format!(r#"{}<Card><slot name="title">A</slot></Card>"#, CARD)).unwrap_err();
+
+        assert_eq!(error.to_string(), "at 1:85: Component Card has no slot \"title\".");
+    }
+
+    #[test]
+    fn report() {
+
+        let (document, _) = parse(&format!("{}{}<Card><Button label=\"x\"/><Icon/></Card><Icon/><Badge/>", BUTTON, CARD));
+
+        let report = inspect_components(document).unwrap();
+
+        assert_eq!(
+            report.defined.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(),
+            ["Button", "Card"]
+        );
+
+        assert_eq!(report.undefined, ["Icon", "Badge"]);
+
+        assert_eq!(
+            report.to_string(),
+            "Defined components:\n  Button variant=\"primary\" label\n  Card\nUndefined components:\n  Icon\n  Badge\n"
+        );
+    }
+
+    #[test]
+    fn report_ignores_built_ins() {
+
+        let (document, _) = parse(r#"<Tabs><Tab title="A">a</Tab></Tabs><Warning>w</Warning><Html><b>h</b></Html><Icon/>"#);
+
+        assert_eq!(inspect_components(document).unwrap().undefined, ["Icon"]);
+    }
+
+    #[test]
+    fn missing_parameter() {
+
+        let error = emit(&format!("{}<Button/>", BUTTON)).unwrap_err();
+
+        assert_eq!(error.to_string(), "at 1:99: Component Button requires parameter \"label\".");
+    }
+
+    #[test]
+    fn parameter_scopes() {
+
+        let src = r#"
+            <Component Section title><h2>${title}</h2><div><var title="inner"/>${title}</div>${title}</Component>
+            <var title="document"/>
+            ${title}<Section title="caller"/>${title}<Section title="again"/>
+        "#;
+
+        // the component body shadows the parameters, which shadow the variables of the caller
+        assert_eq!(
+            emit(src).unwrap().split_whitespace().collect::<String>(),
+            "document<h2>caller</h2><div>inner</div>callerdocument<h2>again</h2><div>inner</div>again"
+        );
+
+        // a definition at the top level of the body shadows the parameter for the rest of the body
+        assert_eq!(
+            emit(r#"<Component A x>${x}<var x="inner"/>${x}</Component><A x="outer"/>"#).unwrap(),
+            "outerinner"
+        );
+
+        // parameters are not defined outside of the component
+        let error = emit(r#"<Component A x>${x}</Component><A x="a"/>${x}"#).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "Cannot resolve variable \"x\".");
+    }
+
+    #[test]
+    fn use_before_definition() {
+
+        assert_eq!(
+            emit(&format!(r#"<p><Button label="Early"/></p>{}<Button label="Late"/>"#, BUTTON)).unwrap(),
+            r#"<p><button class="primary">Early</button></p><button class="primary">Late</button>"#
+        );
+    }
+
+    #[test]
+    fn repeated_use() {
+
+        use std::collections::HashSet;
+        use crate::document::visit::TransformerOnce;
+
+        let (document, _) = parse(&format!(
+            r#"{}<Button label="a"/><Button label="b"/><p><Button label="c"/></p>"#, 
+            BUTTON
+        ));
+
+        let (register, insert) = components();
+
+        // every use is an instance of the body, so transformers that skip visited nodes still expand all of them
+        let document = transform(
+            document,
+            &mut vec![
+                Box::new(TransformerOnce::new(register)),
+                Box::new(TransformerOnce::new(insert)),
+                Box::new(TransformerOnce::new(Variables::new())),
+            ],
+            1
+        ).unwrap();
+
+        let ids = document.descendants().map(|node| node.id).collect::<HashSet<NodeId>>();
+
+        assert_eq!(ids.len(), document.descendants().count());
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(
+            html,
+            r#"<button class="primary">a</button><button class="primary">b</button><p><button class="primary">c</button></p>"#
+        );
+    }
+}
 
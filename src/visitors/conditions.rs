@@ -0,0 +1,173 @@
+//!
+//! Conditional content evaluated against a context map.
+//!
+//! <if lang="en">Hello</if>
+//! <if lang="de">Hallo</if>
+//! <if draft>This is a draft.</if>
+//!
+//! An attribute with a value matches if the context contains the same value for the key.
+//! An attribute without a value matches if the context value for the key is "true".
+//! The special attribute test matches if its value is "true", which allows for
+//! expressions like <if test="${count > 3}">.
+//! All attributes must match. Matching <if> nodes are replaced by their children,
+//! others are removed including their children.
+//!
+//! Runs after Variables so that conditions may depend on resolved content.
+//!
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::document::{
+    EnvNode,
+    EnvNodeHeader,
+    EnvNodeHeaderKind,
+    EnvNodeKind,
+    LeafNode,
+    Node,
+    NodeId,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor, VisitError}
+};
+
+pub struct If {
+    /** Values conditions are evaluated against. */
+    pub context: HashMap<String, String>,
+}
+
+impl If {
+
+    pub fn new(context : HashMap<String, String>) -> Self {
+        Self { context }
+    }
+
+    ///
+    /// Evaluates a single condition attribute.
+    /// Returns None if the key is not part of the context.
+    ///
+    fn evaluate(&self, key : &str, value : &Option<Node>) -> Result<Option<bool>, VisitError> {
+
+        if key == "test" {
+            return match value {
+                Some(Node { kind: NodeKind::Leaf(LeafNode::Text(result)), .. }) => match result.as_str() {
+                    "true" => Ok(Some(true)),
+                    "false" => Ok(Some(false)),
+                    _ => Err(VisitError::Unknown(format!("Test must be true or false, got \"{}\".", result))),
+                },
+                _ => Err(VisitError::Unknown("Test must be true or false.".to_string())),
+            };
+        }
+
+        let Some(actual) = self.context.get(key) else {
+            return Ok(None);
+        };
+
+        match value {
+            None => Ok(Some(actual == "true")),
+            Some(Node { kind: NodeKind::Leaf(LeafNode::Text(expected)), .. }) => Ok(Some(actual == expected)),
+            Some(_) => Err(VisitError::Unknown(
+                format!("Condition \"{}\" must be compared to text.", key)
+            )),
+        }
+    }
+
+}
+
+impl Visitor for If {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+        match &node.kind {
+            NodeKind::Env(
+                EnvNode {
+                    header: EnvNodeHeader {
+                        kind: EnvNodeHeaderKind::Other(name),
+                        attrs,
+                        ..
+                    },
+                    ..
+                }
+            ) if name == "if" => {
+
+                if attrs.is_empty() {
+                    return Err(VisitError::Unknown(
+                        "<if> requires at least one condition.".to_string()
+                    ));
+                }
+
+                let mut matches = true;
+
+                for (key, value) in attrs {
+                    match self.evaluate(key, value)? {
+                        Some(result) => matches &= result,
+                        None => return Err(VisitError::Unknown(
+                            format!("Unknown condition \"{}\".", key)
+                        )),
+                    }
+                }
+
+                if !matches {
+                    return Ok(Action::remove(node));
+                }
+
+                let children = match node.kind {
+                    NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children,
+                    _ => VecDeque::new(),
+                };
+
+                Ok(Action::replace(Node {
+                    kind: NodeKind::new_fragment(children),
+                    ..node
+                }))
+            },
+            _ => Ok(Action::keep(node))
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::visitors::testing::emit_html;
+
+    fn emit(src : &str, context : &[(&str, &str)]) -> Result<String, VisitError> {
+
+        let context = context
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        emit_html(src, &mut vec![Box::new(If::new(context))], 1)
+    }
+
+    #[test]
+    fn language() {
+
+        let src = r#"<p><if lang="en">Hello</if><if lang="de">Hallo</if></p>"#;
+
+        assert_eq!(emit(src, &[("lang", "en")]).unwrap(), "<p>Hello</p>");
+        assert_eq!(emit(src, &[("lang", "de")]).unwrap(), "<p>Hallo</p>");
+    }
+
+    #[test]
+    fn boolean_and_nested() {
+
+        let src = r#"<p><if draft>Draft<if lang="en"> in English</if></if></p>"#;
+
+        assert_eq!(
+            emit(src, &[("draft", "true"), ("lang", "en")]).unwrap(),
+            "<p>Draft in English</p>"
+        );
+
+        // the outer condition removes the inner one before it is evaluated
+        assert_eq!(emit(src, &[("draft", "false")]).unwrap(), "<p></p>");
+    }
+
+    #[test]
+    fn unknown_condition() {
+
+        assert!(emit(r#"<if color="red">Red</if>"#, &[("lang", "en")]).is_err());
+        assert!(emit("<if>Always?</if>", &[]).is_err());
+    }
+
+}
@@ -0,0 +1,166 @@
+//!
+//! Syntax highlighting for <Code lang="..."> envs.
+//!
+//! The code is passed to a Highlighter, which returns the nodes replacing the code text,
+//! usually text nodes wrapped in <span class="..."> elements.
+//! Text nodes are escaped by the emitter, so highlighters return the code as it is.
+//! Only <Code> envs are highlighted, equations are left untouched.
+//!
+
+use crate::document::{
+    EnvNode,
+    EnvNodeHeaderKind,
+    EnvNodeKind,
+    Node,
+    NodeBuilder,
+    NodeId,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor}
+};
+
+pub trait Highlighter {
+    ///
+    /// Returns the nodes replacing the code.
+    /// Languages the highlighter does not know should be returned as a single text node.
+    ///
+    fn highlight(&self, lang : &str, code : &str) -> Vec<Node>;
+}
+
+/// Highlighter that keeps the code as plain text.
+#[derive(Default)]
+pub struct NoHighlighting;
+
+impl Highlighter for NoHighlighting {
+    fn highlight(&self, _lang : &str, code : &str) -> Vec<Node> {
+        vec![NodeBuilder::text(code)]
+    }
+}
+
+pub struct CodeHighlighter<H : Highlighter> {
+    pub highlighter: H,
+}
+
+impl<H : Highlighter> CodeHighlighter<H> {
+
+    pub fn new(highlighter : H) -> Self {
+        Self { highlighter }
+    }
+
+}
+
+impl Default for CodeHighlighter<NoHighlighting> {
+    fn default() -> Self {
+        Self::new(NoHighlighting)
+    }
+}
+
+impl<H : Highlighter> Visitor for CodeHighlighter<H> {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        let NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(children) }) = &node.kind else {
+            return Ok(Action::keep(node));
+        };
+
+        if header.kind != EnvNodeHeaderKind::Code {
+            return Ok(Action::keep(node));
+        }
+
+        // code that has been highlighted already is not a single text node anymore
+        let Some(lang) = header.attr_text("lang").filter(|_| children.len() == 1) else {
+            return Ok(Action::keep(node));
+        };
+
+        let Some(code) = children.front().and_then(Node::literal_text) else {
+            return Ok(Action::keep(node));
+        };
+
+        let highlighted = self.highlighter.highlight(&lang, &code);
+
+        if highlighted.iter().map(Node::literal_text).eq([Some(code)]) {
+            return Ok(Action::keep(node));
+        }
+
+        let NodeKind::Env(EnvNode { header, .. }) = node.kind else {
+            unreachable!()
+        };
+
+        Ok(Action::replace(Node {
+            kind: NodeKind::Env(EnvNode::new_open(header, highlighted.into())),
+            ..node
+        }))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::visitors::testing::emit_html;
+
+    /// Highlights the keywords of a made-up language.
+    struct Keywords;
+
+    impl Highlighter for Keywords {
+        fn highlight(&self, lang : &str, code : &str) -> Vec<Node> {
+
+            if lang != "toy" {
+                return vec![NodeBuilder::text(code)];
+            }
+
+            let mut nodes = Vec::new();
+            let mut text = String::new();
+
+            for word in code.split_inclusive(|c : char| !c.is_alphanumeric()) {
+
+                let name = word.trim_end_matches(|c : char| !c.is_alphanumeric());
+
+                if name == "let" || name == "fn" {
+
+                    if !text.is_empty() {
+                        nodes.push(NodeBuilder::text(std::mem::take(&mut text)));
+                    }
+
+                    nodes.push(NodeBuilder::env("span").attr("class", "kw").child(NodeBuilder::text(name)).build());
+
+                    text.push_str(&word[name.len()..]);
+                } else {
+                    text.push_str(word);
+                }
+            }
+
+            if !text.is_empty() {
+                nodes.push(NodeBuilder::text(text));
+            }
+
+            nodes
+        }
+    }
+
+    fn emit<H : Highlighter + 'static>(src : &str, highlighter : H) -> String {
+
+        emit_html(src, &mut vec![Box::new(CodeHighlighter::new(highlighter))], 2).unwrap()
+    }
+
+    #[test]
+    fn keywords() {
+
+        assert_eq!(
+            emit(r#"<Code lang="toy">fn f() { let x = a < b; }</Code>"#, Keywords),
+            r#"<Code lang="toy"><span class="kw">fn</span> f() { <span class="kw">let</span> x = a &lt; b; }</Code>"#
+        );
+
+        // unknown languages and equations are left as they are
+        assert_eq!(
+            emit(r#"<Code lang="other">let a < b</Code><Eq lang="toy">let x</Eq>"#, Keywords),
+            r#"<Code lang="other">let a &lt; b</Code><Eq block lang="toy">let x</Eq>"#
+        );
+
+        assert_eq!(
+            emit(r#"<Code lang="toy">let a < b</Code>"#, NoHighlighting),
+            r#"<Code lang="toy">let a &lt; b</Code>"#
+        );
+    }
+
+}
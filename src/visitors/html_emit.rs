@@ -1,128 +1,614 @@
 
+use std::collections::{HashMap, HashSet};
+
 use crate::document::*;
+use crate::parse::dynamic_parse::{is_void_element, RAW_HTML_ENV};
+use crate::visitors::toc::{slugify, text_content};
 use visit::{Action, VisitError, TransformResult, Visitor};
-use html_escape::encode_safe;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+///
+/// Writes the opening or closing part of an env in place of the default tag.
+/// Receives the env and a function collecting the emitted HTML.
+///
+pub type EnvEmitFn = fn(&EnvNode, &mut dyn FnMut(&str));
 
-pub struct HTMLEmitter {
+pub struct HTMLEmitter<F : FnMut(&str)> {
     /// 
     /// Called for every sub-string in the emitted HTML.
     /// Can be used to concatenate into a string or stream to a file or socket.
     /// 
-    pub collector: fn (&str),
+    pub collector: F,
     pub debug: bool,
+    /// 
+    /// Adds loading="lazy" and decoding="async" to <img> elements 
+    /// unless the author specified them.
+    /// 
+    pub lazy_images: bool,
+    ///
+    /// Aborts with VisitError::OutputLimitExceeded if the output would exceed this many bytes.
+    /// Guards against expansion bombs when rendering untrusted input.
+    ///
+    pub max_bytes: Option<usize>,
+    ///
+    /// Adds an id derived from the text to every heading without an id.
+    /// 
+    pub heading_ids: bool,
+    ///
+    /// Inserts a link to the heading itself at the start of every heading with an id.
+    /// 
+    pub heading_anchors: bool,
+    /** Ids generated for headings so far. */
+    heading_slugs: HashSet<String>,
+    /** What to do with custom elements that are not valid HTML, e.g. <Widget>. */
+    pub unknown_tags: UnknownTagPolicy,
+    /** Writes non-ASCII characters as numeric character references, e.g. &#xE4; for ä. */
+    pub ascii_only: bool,
+    ///
+    /// Emits boolean attributes (e.g. disabled) with a value of "true" as the bare attribute name 
+    /// and omits them if the value is "false".
+    ///
+    pub boolean_attrs: bool,
+    ///
+    /// Fails on error nodes in the document. 
+    /// Otherwise they are emitted as <span class="vtx-error"> placeholders with the message as the title,
+    /// and attributes with an error node as their value are left out.
+    ///
+    pub strict: bool,
+    ///
+    /// Emits comments as <!-- -->. They are removed by Cleanup unless it keeps them.
+    /// Otherwise comments are left out.
+    ///
+    pub comments: bool,
+    ///
+    /// Puts block elements on separate lines, indented by this many spaces per level.
+    /// The contents of <pre>, <Code>, and equations are emitted as they are.
+    ///
+    pub indent: Option<usize>,
+    /** For each open block element: whether it contains block elements. */
+    open_blocks: Vec<bool>,
+    /** Id of the element whose contents are emitted without formatting. */
+    preformatted: Option<NodeId>,
+    /** Id of the <Html> env whose text is emitted without escaping. */
+    raw_html: Option<NodeId>,
+    /** Id of the unknown element whose subtree is currently being dropped. */
+    dropping: Option<NodeId>,
+    /** Custom open and close functions by env name, see register. */
+    overrides: HashMap<String, (EnvEmitFn, EnvEmitFn)>,
+    /** Closing HTML of the currently open overridden envs. */
+    override_closings: Vec<(NodeId, String)>,
+    /** Bytes passed to the collector so far. */
+    emitted: usize,
+    /** Bytes of closing tags of the currently open elements. */
+    reserved: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownTagPolicy {
+    /** Emit the element as it is. */
+    Keep,
+    /** Emit neither the element nor its children. */
+    Drop,
+    /** Emit only the children of the element. */
+    Unwrap,
 }
 
-// there must be a library for this... 
-// TODO: tidy this up...
-fn encode(text: &str) -> String {
-    encode_safe(text)
-        .replace("ä", "&auml;")
-        .replace("ö", "&ouml;")
-        .replace("ü", "&uuml;")
-        .replace("Ä", "&Auml;")
-        .replace("Ö", "&Ouml;")
-        .replace("Ü", "&Uuml;")
-        .replace("ß", "&szlig;")
-        .replace("á", "&aacute;")
-        .replace("é", "&eacute;")
-        .replace("í", "&iacute;")
-        .replace("ó", "&oacute;")
-        .replace("ú", "&uacute;")
-        .replace("Á", "&Aacute;")
-        .replace("É", "&Eacute;")
-        .replace("Í", "&Iacute;")
-        .replace("Ó", "&Oacute;")
-        .replace("Ú", "&Uacute;")
-        .replace("à", "&agrave;")
-        .replace("è", "&egrave;")
-        .replace("ì", "&igrave;")
-        .replace("ò", "&ograve;")
-        .replace("ù", "&ugrave;")
-        .replace("À", "&Agrave;")
-        .replace("È", "&Egrave;")
-        .replace("Ì", "&Igrave;")
-        .replace("Ò", "&Ograve;")
-        .replace("Ù", "&Ugrave;")
-        .replace("â", "&acirc;")
-        .replace("ê", "&ecirc;")
-        .replace("î", "&icirc;")
-        .replace("ô", "&ocirc;")
-        .replace("û", "&ucirc;")
-        .replace("Â", "&Acirc;")
-        .replace("Ê", "&Ecirc;")
-        .replace("Î", "&Icirc;")
-        .replace("Ô", "&Ocirc;")
-        .replace("Û", "&Ucirc;")
-        .replace("ã", "&atilde;")
-        .replace("ñ", "&ntilde;")
-        .replace("õ", "&otilde;")
-        .replace("Ã", "&Atilde;")
-        .replace("Ñ", "&Ntilde;")
-        .replace("Õ", "&Otilde;")
-        .replace("å", "&aring;")
-        .replace("Å", "&Aring;")
-        .replace("ç", "&ccedil;")
-        .replace("Ç", "&Ccedil;")
-        .replace("ë", "&euml;")
-        .replace("ï", "&iuml;")
-        .replace("Ö", "&Ouml;")
-        .replace("ÿ", "&yuml;")
+/// Elements defined by the HTML standard (including SVG and MathML roots).
+const HTML_ELEMENTS : [&str; 114] = [
+    "a", "abbr", "address", "area", "article", "aside", "audio", "b", "base", "bdi", "bdo",
+    "blockquote", "body", "br", "button", "canvas", "caption", "cite", "code", "col", "colgroup",
+    "data", "datalist", "dd", "del", "details", "dfn", "dialog", "div", "dl", "dt", "em", "embed",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6",
+    "head", "header", "hgroup", "hr", "html", "i", "iframe", "img", "input", "ins", "kbd", "label",
+    "legend", "li", "link", "main", "map", "mark", "math", "menu", "meta", "meter", "nav",
+    "noscript", "object", "ol", "optgroup", "option", "output", "p", "picture", "pre", "progress",
+    "q", "rp", "rt", "ruby", "s", "samp", "script", "search", "section", "select", "slot", "small",
+    "source", "span", "strong", "style", "sub", "summary", "sup", "svg", "table", "tbody", "td",
+    "template", "textarea", "tfoot", "th", "thead", "time", "title", "tr", "track", "u", "ul",
+    "var", "video", "wbr",
+];
+
+///
+/// Checks if the env is a custom element that is not part of HTML.
+/// Names containing '-' are valid custom elements (web components).
+///
+fn is_unknown_tag(kind : &EnvNodeHeaderKind) -> bool {
+    match kind {
+        EnvNodeHeaderKind::Other(name) => !name.contains('-') && !HTML_ELEMENTS.contains(&name.as_str()),
+        _ => false,
+    }
 }
 
-fn collect_env_attrs(attrs : &EnvNodeAttrs, f: &fn(&str)) {
+/// Attributes defined by the HTML standard whose presence alone means true.
+const BOOLEAN_ATTRS : [&str; 25] = [
+    "allowfullscreen", "async", "autofocus", "autoplay", "checked", "controls", "default", "defer", 
+    "disabled", "formnovalidate", "hidden", "inert", "ismap", "itemscope", "loop", "multiple", "muted", 
+    "nomodule", "novalidate", "open", "playsinline", "readonly", "required", "reversed", "selected",
+];
+
+/// Attributes added to <img> elements if lazy_images is set.
+const LAZY_IMAGE_ATTRS : [(&str, &str); 2] = [
+    ("loading", "lazy"),
+    ("decoding", "async"),
+];
+
+impl<F : FnMut(&str)> HTMLEmitter<F> {
+
+    /** Create new emitter with default settings. */
+    pub fn new(collector : F) -> Self {
+        Self {
+            collector,
+            debug: false,
+            lazy_images: true,
+            max_bytes: None,
+            heading_ids: false,
+            heading_anchors: false,
+            heading_slugs: HashSet::new(),
+            unknown_tags: UnknownTagPolicy::Keep,
+            ascii_only: false,
+            boolean_attrs: true,
+            strict: true,
+            comments: false,
+            indent: None,
+            open_blocks: Vec::new(),
+            preformatted: None,
+            raw_html: None,
+            dropping: None,
+            overrides: HashMap::new(),
+            override_closings: Vec::new(),
+            emitted: 0,
+            reserved: 0,
+        }
+    }
+
+    ///
+    /// Emits envs with the given name using open and close instead of the default tags.
+    /// The children are emitted as usual in between.
+    /// Registered envs are never treated as unknown tags.
+    ///
+    pub fn register(&mut self, name : &str, open : EnvEmitFn, close : EnvEmitFn) {
+        self.overrides.insert(name.to_string(), (open, close));
+    }
+
+    ///
+    /// Generates an id from the text of a heading that is unique within the document.
+    ///
+    fn heading_id(&mut self, heading : &Node) -> String {
+
+        let slug = slugify(&text_content(heading));
+
+        let mut id = slug.clone();
+
+        let mut n = 1;
+
+        while self.heading_slugs.contains(&id) {
+            id = format!("{}-{}", slug, n);
+            n += 1;
+        }
+
+        self.heading_slugs.insert(id.clone());
+
+        id
+    }
+
+    /** Checks if the element is put on a separate line. */
+    fn is_pretty_block(&self, kind : &EnvNodeHeaderKind) -> bool {
+        self.indent.is_some() && self.preformatted.is_none() && is_block_element(kind)
+    }
+
+    /** Line break and indentation at the current depth. */
+    fn line_break(&self) -> String {
+        format!("\n{}", " ".repeat(self.indent.unwrap_or(0) * self.open_blocks.len()))
+    }
+
+    ///
+    /// Writes the opening tag of an env, preceded by a line break if pretty printing.
+    ///
+    fn write_open(&mut self, id : NodeId, env : &EnvNode, tag : &str) -> Result<(), VisitError> {
+
+        let pretty = self.is_pretty_block(&env.header.kind);
+
+        let mut reserve = closing_len(env);
+
+        let mut html = String::new();
+
+        if pretty {
+
+            if let Some(has_blocks) = self.open_blocks.last_mut() {
+                *has_blocks = true;
+            }
+
+            let line_break = self.line_break();
+
+            // the closing tag may be put on a separate line at the same depth
+            reserve += line_break.len();
+
+            if self.emitted > 0 {
+                html.push_str(&line_break);
+            }
+        }
+
+        html.push_str(tag);
+
+        self.write(&html, reserve)?;
+
+        if pretty {
+            self.open_blocks.push(false);
+        }
+
+        if self.preformatted.is_none() && is_preformatted(&env.header.kind) {
+            self.preformatted = Some(id);
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Passes s to the collector and reserves space for a closing tag.
+    /// Fails without emitting anything if the output limit would be exceeded.
+    ///
+    fn write(&mut self, s : &str, reserve : usize) -> Result<(), VisitError> {
+
+        if let Some(max_bytes) = self.max_bytes {
+            if self.emitted + self.reserved + s.len() + reserve > max_bytes {
+                return Err(VisitError::OutputLimitExceeded(max_bytes));
+            }
+        }
+
+        self.emitted += s.len();
+        self.reserved += reserve;
+
+        (self.collector)(s);
+
+        Ok(())
+    }
+
+}
+
+///
+/// Escapes the characters with a special meaning in HTML in a single pass.
+/// With ascii_only, all non-ASCII characters are written as numeric character references,
+/// otherwise they are kept as UTF-8.
+///
+fn encode(text : &str, ascii_only : bool) -> Cow<'_, str> {
+
+    let needs_escape = |b : &u8| matches!(b, b'<' | b'>' | b'&' | b'"') || (ascii_only && !b.is_ascii());
+
+    // fast path for text without special characters
+    if !text.as_bytes().iter().any(needs_escape) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut encoded = String::with_capacity(text.len() + text.len() / 4);
+
+    for c in text.chars() {
+        match c {
+            '<' => encoded.push_str("&lt;"),
+            '>' => encoded.push_str("&gt;"),
+            '&' => encoded.push_str("&amp;"),
+            '"' => encoded.push_str("&quot;"),
+            c if ascii_only && !c.is_ascii() => { let _ = write!(encoded, "&#x{:X};", c as u32); },
+            c => encoded.push(c),
+        }
+    }
+
+    Cow::Owned(encoded)
+}
+
+///
+/// Concatenates the text of an attribute value.
+/// Values must only consist of text nodes and fragments.
+/// 
+fn attr_value_text(value : &Node, out : &mut String) -> Result<(), VisitError> {
+    match &value.kind {
+        NodeKind::Leaf(LeafNode::Text(text)) => out.push_str(text),
+        NodeKind::Env(EnvNode { 
+            header: EnvNodeHeader { kind: EnvNodeHeaderKind::Fragment, .. }, 
+            kind: EnvNodeKind::Open(children) 
+        }) => {
+            for child in children {
+                attr_value_text(child, out)?;
+            }
+        },
+        NodeKind::Leaf(LeafNode::VariableExpression(expr)) => return Err(
+            VisitError::Unknown(format!("Unresolved variable \"{}\" in attribute value.", expr))
+        ),
+        _ => return Err(
+            VisitError::Unknown("Attribute values must only contain text.".to_string())
+        ),
+    }
+
+    Ok(())
+}
+
+fn collect_env_attrs(
+    attrs : &EnvNodeAttrs, 
+    ascii_only : bool, 
+    boolean_attrs : bool, 
+    strict : bool,
+    f: &mut impl FnMut(&str)
+) -> Result<(), VisitError> {
 
     for (key, value) in attrs {
 
-        f(key);
+        // attributes whose value could not be parsed are left out
+        if let Some(Node { kind: NodeKind::Leaf(LeafNode::Error(message)), .. }) = value {
 
-        if let Some(value) = value  {
-            f("=\"");
-            
-            match &value.kind {
-                NodeKind::Leaf(LeafNode::Text(text)) => f(&encode(text)),
-                _ =>  todo!("Attr values must be text nodes.")
+            if strict {
+                return Err(VisitError::Unknown(message.clone()));
+            }
+
+            continue;
+        }
+
+        let mut text = None;
+
+        if let Some(value) = value {
+
+            let mut value_text = String::new();
+
+            attr_value_text(value, &mut value_text)?;
+
+            text = Some(value_text);
+        }
+
+        if boolean_attrs && BOOLEAN_ATTRS.contains(&key.as_str()) {
+            // surrounding whitespace is ignored, other values are emitted as they are
+            match text.as_deref().map(str::trim) {
+                Some("false") => continue,
+                Some("true") => text = None,
+                _ => {},
             }
+        }
+
+        f(" ");
+        f(key);
 
-            f("\" ");
-        } else {
-            f(" ");
+        if let Some(text) = text {
+            f("=\"");
+            f(&encode(&text, ascii_only));
+            f("\"");
         }
 
     }
+
+    Ok(())
 }
 
-fn collect_env_header(header : &EnvNodeHeader, f: &fn(&str)) {
+///
+/// Emits the opening tag of an env.
+/// Self-closing custom elements end with "/>".
+///
+fn collect_env_header(
+    header : &EnvNodeHeader, 
+    self_closing : bool, 
+    lazy_images : bool, 
+    ascii_only : bool,
+    boolean_attrs : bool,
+    strict : bool,
+    f: &mut impl FnMut(&str)
+) -> Result<(), VisitError> {
 
     match header.kind {
         EnvNodeHeaderKind::Module => {},
         _ => {
+            let name = header.kind.get_name();
+
             f("<");
-            f(header.kind.get_name());
+            f(name);
 
-            if !header.attrs.is_empty() {
-                f(" ");
-                collect_env_attrs(&header.attrs, f)
+            collect_env_attrs(&header.attrs, ascii_only, boolean_attrs, strict, f)?;
+
+            if lazy_images && name == "img" {
+                // only add what the author did not specify
+                for (key, value) in LAZY_IMAGE_ATTRS {
+                    if !header.attrs.contains_key(key) {
+                        f(" ");
+                        f(key);
+                        f("=\"");
+                        f(value);
+                        f("\"");
+                    }
+                }
             }
 
-            f(">");
+            if self_closing && !is_html_element(&header.kind) {
+                f("/>");
+            } else {
+                f(">");
+            }
         }
     }
+
+    Ok(())
+}
+
+/// Elements which are part of the surrounding text
+pub(crate) const INLINE_ELEMENTS : [&str; 26] = [
+    "a", "abbr", "b", "bdi", "bdo", "br", "cite", "code", "data", "dfn", "em", "i", "img", 
+    "kbd", "mark", "q", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var",
+];
+
+/// Elements which are rendered as blocks, whitespace between them is not displayed
+pub(crate) const BLOCK_ELEMENTS : [&str; 42] = [
+    "address", "article", "aside", "blockquote", "caption", "dd", "details", "dialog", "div", "dl", 
+    "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", 
+    "header", "hgroup", "hr", "li", "main", "menu", "nav", "ol", "p", "pre", "section", "summary", 
+    "table", "tbody", "td", "tfoot", "th", "thead", "tr", "ul",
+];
+
+/// Elements whose contents are displayed as they are
+pub(crate) const PREFORMATTED_ELEMENTS : [&str; 4] = ["pre", "script", "style", "textarea"];
+
+pub(crate) fn is_block_element(kind : &EnvNodeHeaderKind) -> bool {
+    match kind {
+        EnvNodeHeaderKind::Module | EnvNodeHeaderKind::Fragment => false,
+        EnvNodeHeaderKind::Eq(EquationKind::Inline) => false,
+        EnvNodeHeaderKind::Other(name) => !INLINE_ELEMENTS.contains(&name.as_str()),
+        _ => true,
+    }
+}
+
+pub(crate) fn is_preformatted(kind : &EnvNodeHeaderKind) -> bool {
+    match kind {
+        EnvNodeHeaderKind::Code | EnvNodeHeaderKind::Eq(_) => true,
+        EnvNodeHeaderKind::Other(name) => PREFORMATTED_ELEMENTS.contains(&name.as_str()),
+        _ => false,
+    }
+}
+
+/** Checks if the env is defined by the HTML standard. */
+fn is_html_element(kind : &EnvNodeHeaderKind) -> bool {
+    match kind {
+        EnvNodeHeaderKind::Heading(_) => true,
+        EnvNodeHeaderKind::Other(name) => HTML_ELEMENTS.contains(&name.as_str()),
+        _ => false,
+    }
 }
 
-impl Visitor for HTMLEmitter {
+///
+/// Checks if a closing tag is emitted in leave.
+/// <p/> is emitted as <p></p>, while void elements like <img> and custom elements like <x/> are not closed.
+///
+fn has_closing_tag(env : &EnvNode) -> bool {
+    match env.kind {
+        EnvNodeKind::Open(_) => true,
+        EnvNodeKind::SelfClosing => is_html_element(&env.header.kind) && !is_void_element(&env.header.kind),
+    }
+}
+
+/** Length of the closing tag emitted in leave. */
+fn closing_len(env : &EnvNode) -> usize {
+    match has_closing_tag(env) {
+        true => env.header.kind.get_closing_string().len(),
+        false => 0,
+    }
+}
+
+impl<F : FnMut(&str)> Visitor for HTMLEmitter<F> {
 
     fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
 
+        if self.dropping.is_some() {
+            return Ok(Action::keep(node));
+        }
+
         match &node.kind {
-            NodeKind::Env(node) => match &node.header.kind {
+            NodeKind::Env(env) if self.overrides.contains_key(env.header.kind.get_name()) => {
+
+                let (open, close) = self.overrides[env.header.kind.get_name()];
+
+                let mut opening = String::new();
+                let mut closing = String::new();
+
+                open(env, &mut |s| opening.push_str(s));
+                close(env, &mut |s| closing.push_str(s));
+
+                // the closing part is emitted in leave, which cannot fail
+                self.write(&opening, closing.len())?;
+
+                self.override_closings.push((node.id, closing));
+            },
+            // only the contents are emitted
+            NodeKind::Env(env) if env.header.kind.get_name() == RAW_HTML_ENV => {
+                if self.raw_html.is_none() {
+                    self.raw_html = Some(node.id);
+                }
+            },
+            NodeKind::Env(env) if is_unknown_tag(&env.header.kind) && self.unknown_tags != UnknownTagPolicy::Keep => {
+                if self.unknown_tags == UnknownTagPolicy::Drop {
+                    self.dropping = Some(node.id);
+                }
+            },
+            NodeKind::Env(env) if env.header.kind.heading_level().is_some() && (self.heading_ids || self.heading_anchors) => {
+
+                let mut header = env.header.clone();
+
+                let id = match header.attrs.get("id") {
+                    Some(Some(value)) => {
+                        let mut id = String::new();
+
+                        attr_value_text(value, &mut id)?;
+
+                        Some(id)
+                    },
+                    _ if self.heading_ids => {
+                        let id = self.heading_id(&node);
+
+                        header.attrs.insert(
+                            "id".to_string(), 
+                            Some(Node::new(NodeKind::Leaf(LeafNode::Text(id.clone())), NodePosition::Inserted))
+                        );
+
+                        Some(id)
+                    },
+                    _ => None,
+                };
+
+                let mut html = String::new();
+
+                let self_closing = matches!(env.kind, EnvNodeKind::SelfClosing);
+
+                collect_env_header(
+                    &header, 
+                    self_closing, 
+                    self.lazy_images, 
+                    self.ascii_only, 
+                    self.boolean_attrs, 
+                    self.strict,
+                    &mut |s| html.push_str(s)
+                )?;
+
+                if let Some(id) = id.filter(|_| self.heading_anchors) {
+                    html.push_str(&format!("<a class=\"anchor\" href=\"#{}\">#</a>", encode(&id, self.ascii_only)));
+                }
+
+                self.write_open(node.id, env, &html)?
+            },
+            NodeKind::Env(env) => match &env.header.kind {
                 EnvNodeHeaderKind::Fragment => { },
-                _ => collect_env_header(&node.header, &self.collector)
+                _ => {
+                    let mut header = String::new();
+
+                    let self_closing = matches!(env.kind, EnvNodeKind::SelfClosing);
+
+                    collect_env_header(
+                        &env.header, 
+                        self_closing, 
+                        self.lazy_images, 
+                        self.ascii_only, 
+                        self.boolean_attrs, 
+                        self.strict,
+                        &mut |s| header.push_str(s)
+                    )?;
+
+                    // the closing tag is emitted in leave, which cannot fail
+                    self.write_open(node.id, env, &header)?
+                }
             }
 
-            NodeKind::Leaf(LeafNode::Text(text)) => (self.collector)(&encode(text)),
+            NodeKind::Leaf(LeafNode::Text(text)) if self.raw_html.is_some() => self.write(text, 0)?,
+            NodeKind::Leaf(LeafNode::Text(text)) => self.write(&encode(text, self.ascii_only), 0)?,
+            // pre-rendered HTML, invalid UTF-8 is replaced with U+FFFD
+            NodeKind::Leaf(LeafNode::RawBytes(bytes)) => self.write(&String::from_utf8_lossy(bytes), 0)?,
+            NodeKind::Leaf(LeafNode::Comment(comment)) if self.comments => self.write(
+                &format!("<!--{}-->", comment.replace("-->", "--&gt;")), 
+                0
+            )?,
+            NodeKind::Leaf(LeafNode::Comment(_)) => {},
+            NodeKind::Leaf(LeafNode::Error(message)) if !self.strict => self.write(
+                &format!(
+                    "<span class=\"vtx-error\" title=\"{}\">{}</span>", 
+                    encode(message, self.ascii_only), 
+                    encode("⚠", self.ascii_only)
+                ),
+                0
+            )?,
             kind if self.debug => {
                 dbg!(kind);
             },
+            NodeKind::Leaf(LeafNode::Error(message)) => return Err(VisitError::Unknown(message.clone())),
             _ => return Err(
                 VisitError::Unknown(
                     "Encountered a node which cannot be emitted as HTML.".to_string()
@@ -134,17 +620,410 @@ impl Visitor for HTMLEmitter {
 
     }
 
-    fn leave(&mut self, node : &Node, _original_id : NodeId, _parent_id : Option<NodeId>) {
+    fn leave(&mut self, node : &Node, original_id : NodeId, _parent_id : Option<NodeId>) {
 
-        match &node.kind {
-            NodeKind::Env(node) => match &node.header.kind {
+        if let Some(id) = self.dropping {
+            if id == original_id {
+                self.dropping = None;
+            }
+
+            return;
+        }
+
+        if self.preformatted == Some(original_id) {
+            self.preformatted = None;
+        }
+
+        if self.override_closings.last().is_some_and(|(id, _)| *id == original_id) {
+
+            let (_, closing) = self.override_closings.pop().unwrap();
+
+            self.reserved -= closing.len();
+            self.emitted += closing.len();
+
+            if !closing.is_empty() {
+                (self.collector)(&closing)
+            }
+
+            return;
+        }
+
+        if let NodeKind::Env(env) = &node.kind {
+            if env.header.kind.get_name() == RAW_HTML_ENV {
+
+                if self.raw_html == Some(original_id) {
+                    self.raw_html = None;
+                }
+
+                return;
+            }
+        }
+
+        if let NodeKind::Env(node) = &node.kind {
+            match &node.header.kind {
+                _ if is_unknown_tag(&node.header.kind) && self.unknown_tags != UnknownTagPolicy::Keep => { },
                 EnvNodeHeaderKind::Fragment => { },
-                _ => (self.collector)(&node.header.kind.get_closing_string())
-            },
-            _ => {}
+                _ => {
+                    let mut closing = String::new();
+
+                    let mut reserved = closing_len(node);
+
+                    if self.is_pretty_block(&node.header.kind) {
+
+                        let has_blocks = self.open_blocks.pop().unwrap_or(false);
+
+                        let line_break = self.line_break();
+
+                        reserved += line_break.len();
+
+                        if has_blocks {
+                            closing.push_str(&line_break);
+                        }
+                    }
+
+                    if has_closing_tag(node) {
+                        closing.push_str(&node.header.kind.get_closing_string());
+                    }
+
+                    self.reserved -= reserved;
+                    self.emitted += closing.len();
+
+                    if !closing.is_empty() {
+                        (self.collector)(&closing)
+                    }
+                }
+            }
         }
     }
 
 }
 
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::parse;
+    use crate::visitors::testing::{to_html, to_html_with};
+
+    fn emit(src : &str, lazy_images : bool) -> String {
+
+        let (document, _) = parse(src);
+
+        to_html_with(document, |emitter| emitter.lazy_images = lazy_images).unwrap()
+    }
+
+    #[test]
+    fn registered_envs() {
+
+        fn open(env : &EnvNode, collector : &mut dyn FnMut(&str)) {
+
+            collector("<aside class=\"callout\">");
+
+            if let Some(title) = env.header.attr_text("title") {
+                collector(&format!("<p class=\"title\">{}</p>", encode(&title, false)));
+            }
+        }
+
+        fn close(_env : &EnvNode, collector : &mut dyn FnMut(&str)) {
+            collector("</aside>");
+        }
+
+        let (document, _) = parse(r#"<Callout title="Note">some <b>text</b></Callout><Widget/>"#);
+
+        let html = to_html_with(document, |emitter| {
+            emitter.unknown_tags = UnknownTagPolicy::Drop;
+            emitter.register("Callout", open, close);
+        }).unwrap();
+
+        assert_eq!(html, r#"<aside class="callout"><p class="title">Note</p>some <b>text</b></aside>"#);
+    }
+
+    #[test]
+    fn comments() {
+
+        assert_eq!(emit("<p>a/** note -->*/b</p>", false), "<p>ab</p>");
+
+        let (document, _) = parse("<p>a/** note -->*/b</p>");
+
+        let html = to_html_with(document, |emitter| emitter.comments = true).unwrap();
+
+        assert_eq!(html, "<p>a<!-- note --&gt;-->b</p>");
+    }
+
+    #[test]
+    fn error_nodes() {
+
+        let document = || NodeBuilder::env("p")
+            .child(NodeBuilder::text("before "))
+            .child(Node::new(NodeKind::Leaf(LeafNode::Error("<x> is \"broken\"".to_string())), NodePosition::Inserted))
+            .build();
+
+        let error = to_html(document()).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "<x> is \"broken\"");
+
+        let html = to_html_with(document(), |emitter| emitter.strict = false).unwrap();
+
+        assert_eq!(html, r#"<p>before <span class="vtx-error" title="&lt;x&gt; is &quot;broken&quot;">⚠</span></p>"#);
+
+        // attributes with an error node as their value
+        let document = || NodeBuilder::env("a")
+            .attrs(EnvNodeAttrs::from([
+                ("href".to_string(), Some(Node::new(NodeKind::Leaf(LeafNode::Error("broken".to_string())), NodePosition::Inserted))),
+                ("title".to_string(), Some(NodeBuilder::text("t"))),
+            ]))
+            .child(NodeBuilder::text("link"))
+            .build();
+
+        let error = to_html(document()).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "broken");
+
+        let html = to_html_with(document(), |emitter| emitter.strict = false).unwrap();
+
+        assert_eq!(html, r#"<a title="t">link</a>"#);
+    }
+
+    #[test]
+    fn raw_bytes() {
+
+        let raw = |bytes : &[u8]| Node::new(NodeKind::Leaf(LeafNode::RawBytes(bytes.to_vec())), NodePosition::Inserted);
+
+        let document = NodeBuilder::env("p")
+            .child(NodeBuilder::text("a < b "))
+            .child(raw("<b>&amp;</b> ä".as_bytes()))
+            .child(raw(b" \xFF"))
+            .build();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, "<p>a &lt; b <b>&amp;</b> ä \u{FFFD}</p>");
+    }
+
+    #[test]
+    fn raw_html() {
+
+        let svg = r##"<svg viewBox="0 0 10 10"><circle cx="5" cy="5" r="4" fill="#f00"/></svg>"##;
+
+        assert_eq!(
+            emit(&format!("<p>a < b</p><Html>{}</Html><p>after</p>", svg), false),
+            format!("<p>a &lt; b</p>{}<p>after</p>", svg)
+        );
+
+        // the output limit counts the raw contents
+        let (document, _) = parse(&format!("<Html>{}</Html>", svg));
+
+        let error = to_html_with(document, |emitter| emitter.max_bytes = Some(svg.len() - 1)).unwrap_err();
+
+        assert!(matches!(error.root_cause(), VisitError::OutputLimitExceeded(_)));
+    }
 
+    #[test]
+    fn boolean_attributes() {
+
+        assert_eq!(emit(r#"<input disabled="true"/>"#, false), "<input disabled>");
+        assert_eq!(emit(r#"<input disabled="false"/>"#, false), "<input>");
+        assert_eq!(emit(r#"<input disabled/>"#, false), "<input disabled>");
+        assert_eq!(emit(r#"<input disabled=" true "/>"#, false), "<input disabled>");
+
+        // other values and attributes are kept
+        assert_eq!(
+            emit(r#"<input disabled="disabled" value="true" aria-hidden="true" data-open="false"/>"#, false), 
+            r#"<input disabled="disabled" value="true" aria-hidden="true" data-open="false">"#
+        );
+
+        let (document, _) = parse(r#"<input disabled="true" hidden="false"/>"#);
+
+        let html = to_html_with(document, |emitter| emitter.boolean_attrs = false).unwrap();
+
+        assert_eq!(html, r#"<input disabled="true" hidden="false">"#);
+    }
+
+    #[test]
+    fn attribute_order() {
+
+        // attributes are emitted in source order
+        let html = emit(r#"<x b="1" a="2" c/>"#, false);
+
+        assert!(html.starts_with(r#"<x b="1" a="2" c/>"#));
+    }
+
+    #[test]
+    fn max_bytes() {
+
+        use crate::visitors::components::components;
+        use crate::visitors::variables::Variables;
+
+        // every level expands the previous one five times
+        let src = concat!(
+            "<Component A>lol lol lol lol lol</Component>",
+            "<Component B><p><A/><A/><A/><A/><A/></p></Component>",
+            "<Component C><p><B/><B/><B/><B/><B/></p></Component>",
+            "<Component D><p><C/><C/><C/><C/><C/></p></Component>",
+            "<D/>",
+        );
+
+        let render = |max_bytes| {
+
+            let (document, _) = parse(src);
+
+            let (register, insert) = components();
+
+            let document = visit::transform(document, &mut vec![Box::new(register)], 1).unwrap();
+            let document = visit::transform(document, &mut vec![Box::new(insert)], 1).unwrap();
+            let document = visit::transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap();
+
+            to_html_with(document, |emitter| emitter.max_bytes = max_bytes)
+        };
+
+        let html = render(None).unwrap();
+
+        assert_eq!(html.matches("lol").count(), 5 * 125);
+
+        let error = render(Some(1000)).unwrap_err();
+
+        assert!(matches!(error.root_cause(), VisitError::OutputLimitExceeded(1000)));
+
+        // the limit is inclusive
+        assert_eq!(render(Some(html.len())).unwrap(), html);
+    }
+
+    #[test]
+    fn lazy_images() {
+
+        let html = emit(r#"<img src="a.png"/> <img src="b.png" loading="eager"/>"#, true);
+
+        assert!(html.contains(r#"<img src="a.png" loading="lazy" decoding="async">"#));
+
+        // author-specified values are respected
+        assert!(html.contains(r#"<img src="b.png" loading="eager" decoding="async">"#));
+
+        let html = emit(r#"<img src="a.png"/>"#, false);
+
+        assert!(html.contains(r#"<img src="a.png">"#));
+    }
+
+    #[test]
+    fn encoding() {
+
+        assert_eq!(encode("plain text", false), Cow::Borrowed("plain text"));
+        assert_eq!(encode("<&>\"'/", false), "&lt;&amp;&gt;&quot;'/");
+        assert_eq!(encode("ä 🦀", false), "ä 🦀");
+        assert_eq!(encode("ä 🦀 <", true), "&#xE4; &#x1F980; &lt;");
+
+        let (document, _) = parse("<p title=\"Ä & ö\">Grüße 🦀</p>");
+
+        let html = to_html_with(document, |emitter| emitter.ascii_only = true).unwrap();
+
+        assert_eq!(html, "<p title=\"&#xC4; &amp; &#xF6;\">Gr&#xFC;&#xDF;e &#x1F980;</p>");
+    }
+
+    #[test]
+    fn pretty() {
+
+        let (document, _) = parse(concat!(
+            "<div><h1>Title</h1><p>Some <em>text</em>.</p>",
+            "<ul><li>a</li><li>b<ul><li>c</li></ul></li></ul><hr/>",
+            "<pre>  x\n<b>y</b></pre><Code>\n  z\n</Code></div><p>$x$</p>",
+        ));
+
+        let html = to_html_with(document, |emitter| emitter.indent = Some(2)).unwrap();
+
+        assert_eq!(html, concat!(
+            "<div>\n",
+            "  <h1>Title</h1>\n",
+            "  <p>Some <em>text</em>.</p>\n",
+            "  <ul>\n",
+            "    <li>a</li>\n",
+            "    <li>b\n",
+            "      <ul>\n",
+            "        <li>c</li>\n",
+            "      </ul>\n",
+            "    </li>\n",
+            "  </ul>\n",
+            "  <hr>\n",
+            "  <pre>  x\n<b>y</b></pre>\n",
+            "  <Code>\n  z\n</Code>\n",
+            "</div>\n",
+            "<p><Eq>x</Eq></p>",
+        ));
+    }
+
+    #[test]
+    fn pretty_pre() {
+
+        let src = "<pre><span>a </span><div> b\n  c</div></pre>";
+
+        let (document, _) = parse(src);
+
+        let html = to_html_with(document, |emitter| emitter.indent = Some(2)).unwrap();
+
+        assert_eq!(html, src);
+    }
+
+    #[test]
+    fn self_closing() {
+
+        assert_eq!(emit("<x a=\"1\"/><x></x><p/>", false), "<x a=\"1\"/><x></x><p></p>");
+
+        assert_eq!(
+            emit("<p>a<br/>b<br>c</p><img src=\"a.png\"/><hr>", false), 
+            "<p>a<br>b<br>c</p><img src=\"a.png\"><hr>"
+        );
+    }
+
+    #[test]
+    fn unknown_tags() {
+
+        let emit = |unknown_tags| {
+
+            let (document, _) = parse("<p>a<Widget size=\"2\">b<em>c</em></Widget>d</p><my-widget>e</my-widget>");
+
+            to_html_with(document, |emitter| emitter.unknown_tags = unknown_tags).unwrap()
+        };
+
+        assert_eq!(
+            emit(UnknownTagPolicy::Keep), 
+            "<p>a<Widget size=\"2\">b<em>c</em></Widget>d</p><my-widget>e</my-widget>"
+        );
+
+        assert_eq!(emit(UnknownTagPolicy::Drop), "<p>ad</p><my-widget>e</my-widget>");
+
+        assert_eq!(emit(UnknownTagPolicy::Unwrap), "<p>ab<em>c</em>d</p><my-widget>e</my-widget>");
+    }
+
+    #[test]
+    fn headings() {
+
+        let emit = |src : &str, heading_anchors| {
+
+            let (document, _) = parse(src);
+
+            to_html_with(document, |emitter| {
+                emitter.heading_ids = true;
+                emitter.heading_anchors = heading_anchors;
+            }).unwrap()
+        };
+
+        assert_eq!(
+            emit("# Intro\n###### Deep *dive*\n<h2>Intro</h2>\n", false),
+            "<h1 id=\"intro\">Intro</h1><h6 id=\"deep-dive\">Deep <em>dive</em></h6><h2 id=\"intro-1\">Intro</h2>\n"
+        );
+
+        assert_eq!(
+            emit("<h3 id=\"custom\">Title</h3>", true),
+            "<h3 id=\"custom\"><a class=\"anchor\" href=\"#custom\">#</a>Title</h3>"
+        );
+    }
+
+    #[test]
+    fn heading_levels() {
+
+        // levels beyond 6 are clamped to h6
+        assert_eq!(
+            emit("# a\n### c\n###### f\n####### g\n", false),
+            "<h1>a</h1><h3>c</h3><h6>f</h6><h6>g</h6>"
+        );
+    }
+
+}
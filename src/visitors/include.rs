@@ -0,0 +1,254 @@
+//!
+//! Imports the contents of other .vtx files.
+//!
+//! <Import src="chapter1.vtx"/>
+//! <Import src="glossary.vtx" select="#terms"/>
+//!
+//! Paths are resolved relative to the base directory of the visitor. The imported
+//! document replaces the import node. With select, only the element whose id or label
+//! matches the selector is imported.
+//!
+//! <include src="..."/> is an alias of <Import src="..."/>.
+//!
+//! Imports within imported files are resolved recursively, relative to the directory
+//! of the importing file. A file that (indirectly) imports itself is an error.
+//!
+
+use std::path::{Path, PathBuf};
+
+use crate::document::visit::transform;
+use crate::document::{
+    EnvNode,
+    EnvNodeHeader,
+    EnvNodeHeaderKind,
+    EnvNodeKind,
+    Node,
+    NodeId,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor, VisitError}
+};
+use crate::parse::{parse, TokenKind};
+
+pub struct Include {
+    /** Directory that paths in src are relative to. */
+    pub base_dir: PathBuf,
+    /** Canonical paths of the files that are currently being imported. */
+    importing: Vec<PathBuf>,
+}
+
+///
+/// Parses the file at path into a document.
+///
+pub fn parse_file(path : &Path) -> Result<Node, VisitError> {
+
+    let src = std::fs::read_to_string(path).map_err(
+        |error| VisitError::Unknown(format!("Cannot read \"{}\": {}", path.display(), error))
+    )?;
+
+    let (document, tokens) = parse(&src);
+
+    if let Some(token) = tokens.errors().first() {
+
+        let message = match &token.kind {
+            TokenKind::Error(error) => error.to_string(),
+            _ => "Syntax error.".to_string(),
+        };
+
+        return Err(VisitError::Unknown(format!(
+            "Syntax error in \"{}\" at {}: {}",
+            path.display(),
+            token.position,
+            message
+        )));
+    }
+
+    Ok(document)
+}
+
+///
+/// Finds the element with the id or label given by selector ("#name" or "name").
+///
+fn select(document : Node, selector : &str) -> Option<Node> {
+
+    let name = selector.strip_prefix('#').unwrap_or(selector);
+
+    document
+        .find_by_attr("id", Some(name))
+        .into_iter()
+        .chain(document.find_by_attr("label", Some(name)))
+        .next()
+        .cloned()
+}
+
+impl Include {
+
+    pub fn new(base_dir : impl Into<PathBuf>) -> Self {
+        Self { base_dir: base_dir.into(), importing: Vec::new() }
+    }
+
+    ///
+    /// Creates a visitor for the document read from path.
+    /// Paths are relative to its directory and importing the file itself is an error.
+    ///
+    pub fn for_file(path : &Path) -> Self {
+        Self {
+            base_dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            importing: path.canonicalize().into_iter().collect(),
+        }
+    }
+
+    fn import(&self, header : &EnvNodeHeader) -> Result<NodeKind, VisitError> {
+
+        let src = header.attr_text("src").ok_or(
+            VisitError::Unknown("Import requires a src attribute.".to_string())
+        )?;
+
+        let path = self.base_dir.join(&src);
+
+        let document = parse_file(&path)?;
+
+        let canonical = path.canonicalize().map_err(
+            |error| VisitError::Unknown(format!("Cannot read \"{}\": {}", path.display(), error))
+        )?;
+
+        if self.importing.contains(&canonical) {
+            return Err(VisitError::Unknown(format!("Cyclic import of \"{}\".", path.display())));
+        }
+
+        // resolve the imports of the imported file before splicing it in
+        let mut nested = Include {
+            base_dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            importing: self.importing.iter().cloned().chain([canonical]).collect(),
+        };
+
+        let document = transform(document, &mut vec![Box::new(&mut nested)], 2)?;
+
+        match header.attr_text("select") {
+            Some(selector) => select(document, &selector)
+                .map(|node| NodeKind::new_fragment([node].into()))
+                .ok_or(VisitError::Unknown(format!(
+                    "No element with id or label \"{}\" in \"{}\".",
+                    selector.strip_prefix('#').unwrap_or(&selector),
+                    src
+                ))),
+            None => match document.kind {
+                NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => Ok(NodeKind::new_fragment(children)),
+                _ => unreachable!("Documents are always open."),
+            },
+        }
+    }
+
+}
+
+impl Visitor for Include {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+        match &node.kind {
+            NodeKind::Env(EnvNode { header, .. })
+                if matches!(&header.kind, EnvNodeHeaderKind::Other(name) if name == "Import" || name == "include") => {
+
+                let kind = self.import(header).map_err(
+                    |error| error.context(header, &node.position)
+                )?;
+
+                Ok(Action::replace(Node { kind, ..node }))
+            },
+            _ => Ok(Action::keep(node)),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::visitors::testing::emit_html;
+
+    ///
+    /// Creates a new directory in the system temp dir containing the files.
+    ///
+    fn fixture(name : &str, files : &[(&str, &str)]) -> PathBuf {
+
+        let dir = std::env::temp_dir().join(format!("vtx-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        for (path, content) in files {
+
+            let path = dir.join(path);
+
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        dir
+    }
+
+    fn emit(src : &str, base_dir : &Path) -> Result<String, VisitError> {
+
+        emit_html(src, &mut vec![Box::new(Include::new(base_dir))], 2)
+    }
+
+    #[test]
+    fn import_section() {
+
+        let dir = fixture("import", &[(
+            "doc.vtx",
+            "<section label=\"a\">A</section><section label=\"b\">B <em>b</em></section><p id=\"c\">C</p>"
+        )]);
+
+        assert_eq!(
+            emit("<Import src=\"doc.vtx\"/>", &dir).unwrap(),
+            "<section label=\"a\">A</section><section label=\"b\">B <em>b</em></section><p id=\"c\">C</p>"
+        );
+
+        assert_eq!(
+            emit("<div><Import src=\"doc.vtx\" select=\"#b\"/></div>", &dir).unwrap(),
+            "<div><section label=\"b\">B <em>b</em></section></div>"
+        );
+
+        assert_eq!(emit("<Import src=\"doc.vtx\" select=\"c\"/>", &dir).unwrap(), "<p id=\"c\">C</p>");
+
+        let error = emit("\n<div><Import src=\"doc.vtx\" select=\"#x\"/></div>", &dir).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "in <div> at 2:1: in <Import> at 2:6: No element with id or label \"x\" in \"doc.vtx\"."
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_recursive() {
+
+        use crate::render::transpile_path;
+
+        let dir = fixture("include", &[
+            ("main.vtx", "<p><include src=\"part/a.vtx\"/></p>"),
+            ("part/a.vtx", "A <include src=\"b.vtx\"/>"),
+            ("part/b.vtx", "<em>B</em>"),
+            ("missing.vtx", "<include src=\"part/c.vtx\"/>"),
+            ("cycle.vtx", "<include src=\"part/cycle.vtx\"/>"),
+            ("part/cycle.vtx", "<div><include src=\"../cycle.vtx\"/></div>"),
+        ]);
+
+        assert_eq!(transpile_path(&dir.join("main.vtx"), &mut []).unwrap(), "<p>A <em>B</em></p>");
+
+        let error = transpile_path(&dir.join("missing.vtx"), &mut []).unwrap_err().to_string();
+
+        assert!(error.starts_with("in <include> at 1:1: Cannot read \""), "{}", error);
+        assert!(error.contains(&dir.join("part/c.vtx").display().to_string()), "{}", error);
+
+        let error = transpile_path(&dir.join("cycle.vtx"), &mut []).unwrap_err();
+
+        assert_eq!(
+            error.root_cause().to_string(),
+            format!("Cyclic import of \"{}\".", dir.join("part/../cycle.vtx").display())
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+}
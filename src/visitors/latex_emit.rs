@@ -0,0 +1,286 @@
+//!
+//! Emits documents as LaTeX.
+//!
+//! # Title              -> \section{Title}
+//! <Eq label="x">..</Eq> -> \begin{equation}\label{x}..\end{equation}
+//! $x$                  -> $x$
+//! <ref x/>             -> \ref{x}
+//! - item               -> \begin{itemize}\item item\end{itemize}
+//!
+//! The contents of equations and <Code> are emitted as they are, all other text is escaped.
+//! Envs without a LaTeX equivalent are handled according to the fallback policy.
+//!
+
+use crate::document::*;
+use visit::{Action, VisitError, TransformResult, Visitor};
+
+pub struct LatexEmitter<F : FnMut(&str)> {
+    ///
+    /// Called for every sub-string in the emitted LaTeX.
+    ///
+    pub collector: F,
+    /** What to do with envs that have no LaTeX equivalent. */
+    pub fallback: LatexFallback,
+    /** Closing strings of the envs that are currently open. */
+    closing: Vec<String>,
+    /** Id of the equation or code env whose text is currently being emitted verbatim. */
+    verbatim: Option<NodeId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatexFallback {
+    /** Fail with a VisitError. */
+    Error,
+    /** Emit only the children of the env. */
+    Unwrap,
+    /** Emit the env as \begin{name}...\end{name}. */
+    Environment,
+}
+
+/// Sectioning commands for the heading levels 1-6.
+const SECTIONS : [&str; 6] = ["section", "subsection", "subsubsection", "paragraph", "subparagraph", "subparagraph"];
+
+///
+/// Escapes the characters with a special meaning in LaTeX.
+///
+fn escape(text : &str) -> String {
+
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+///
+/// Escapes the characters of a URL that end or break the argument of \href.
+/// Other characters, e.g. '_' and '~', are taken literally by hyperref.
+///
+fn escape_url(url : &str) -> String {
+
+    let mut escaped = String::with_capacity(url.len());
+
+    for c in url.chars() {
+        if matches!(c, '%' | '#' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/** \label{...} if the env has a label. */
+fn label(header : &EnvNodeHeader) -> String {
+    header.attr_text("label")
+        .map(|label| format!("\\label{{{}}}", label))
+        .unwrap_or_default()
+}
+
+impl<F : FnMut(&str)> LatexEmitter<F> {
+
+    /** Create new emitter with default settings. */
+    pub fn new(collector : F) -> Self {
+        Self {
+            collector,
+            fallback: LatexFallback::Error,
+            closing: Vec::new(),
+            verbatim: None,
+        }
+    }
+
+    ///
+    /// Returns the strings emitted before and after the children of the env.
+    ///
+    fn delimiters(&self, header : &EnvNodeHeader) -> Result<(String, String), VisitError> {
+
+        let name = header.kind.get_name();
+
+        let delimiters = match &header.kind {
+            EnvNodeHeaderKind::Module | EnvNodeHeaderKind::Fragment => (String::new(), String::new()),
+            EnvNodeHeaderKind::Eq(EquationKind::Inline) => ("$".to_string(), "$".to_string()),
+            // unlabeled equations are not numbered
+            EnvNodeHeaderKind::Eq(EquationKind::Block) => match header.attr_text("label") {
+                Some(_) => (format!("\\begin{{equation}}{}\n", label(header)), "\n\\end{equation}\n".to_string()),
+                None => ("\\begin{equation*}\n".to_string(), "\n\\end{equation*}\n".to_string()),
+            },
+            EnvNodeHeaderKind::Code => ("\\begin{verbatim}\n".to_string(), "\n\\end{verbatim}\n".to_string()),
+            kind if kind.heading_level().is_some() => (
+                format!("\\{}{{", SECTIONS[kind.heading_level().unwrap_or(1) - 1]),
+                format!("}}{}\n", label(header)),
+            ),
+            _ => match name {
+                "p" => (String::new(), "\n\n".to_string()),
+                "ul" => ("\\begin{itemize}\n".to_string(), "\\end{itemize}\n".to_string()),
+                "ol" => ("\\begin{enumerate}\n".to_string(), "\\end{enumerate}\n".to_string()),
+                "li" => ("\\item ".to_string(), "\n".to_string()),
+                "em" | "i" => ("\\emph{".to_string(), "}".to_string()),
+                "strong" | "b" => ("\\textbf{".to_string(), "}".to_string()),
+                "code" => ("\\texttt{".to_string(), "}".to_string()),
+                "br" => ("\\\\\n".to_string(), String::new()),
+                "a" => match header.attr_text("href") {
+                    Some(href) => (format!("\\href{{{}}}{{", escape_url(&href)), "}".to_string()),
+                    None => (String::new(), String::new()),
+                },
+                // the label is the name of the first attribute, e.g. <ref eq:x/>
+                "ref" => match header.attrs.first() {
+                    Some((label, _)) => (format!("\\ref{{{}}}", label), String::new()),
+                    None => return Err(VisitError::Unknown("Expected <ref label/>.".to_string())),
+                },
+                _ => match self.fallback {
+                    LatexFallback::Error => return Err(VisitError::Unknown(
+                        format!("<{}> cannot be emitted as LaTeX.", name)
+                    )),
+                    LatexFallback::Unwrap => (String::new(), String::new()),
+                    LatexFallback::Environment => (
+                        format!("\\begin{{{}}}{}\n", name, label(header)),
+                        format!("\n\\end{{{}}}\n", name),
+                    ),
+                },
+            },
+        };
+
+        Ok(delimiters)
+    }
+
+}
+
+impl<F : FnMut(&str)> Visitor for LatexEmitter<F> {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        match &node.kind {
+            NodeKind::Env(EnvNode { header, .. }) => {
+
+                let (open, close) = self.delimiters(header)?;
+
+                (self.collector)(&open);
+
+                self.closing.push(close);
+
+                if matches!(header.kind, EnvNodeHeaderKind::Eq(_) | EnvNodeHeaderKind::Code) && self.verbatim.is_none() {
+                    self.verbatim = Some(node.id);
+                }
+            },
+            NodeKind::Leaf(LeafNode::Text(text)) => match self.verbatim {
+                Some(_) => (self.collector)(text),
+                None => (self.collector)(&escape(text)),
+            },
+            NodeKind::Leaf(LeafNode::Comment(comment)) => {
+                for line in comment.lines() {
+                    (self.collector)(&format!("%{}\n", line));
+                }
+            },
+            _ => return Err(
+                VisitError::Unknown(
+                    "Encountered a node which cannot be emitted as LaTeX.".to_string()
+                )
+            ),
+        }
+
+        Ok(Action::keep(node))
+    }
+
+    fn leave(&mut self, node : &Node, original_id : NodeId, _parent_id : Option<NodeId>) {
+
+        if self.verbatim == Some(original_id) {
+            self.verbatim = None;
+        }
+
+        if let NodeKind::Env(_) = &node.kind {
+            if let Some(close) = self.closing.pop() {
+                (self.collector)(&close);
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::parse;
+    use crate::visitors::cleanup::Cleanup;
+    use crate::visitors::testing::to_latex_with;
+
+    fn emit(src : &str, fallback : LatexFallback) -> Result<String, VisitError> {
+
+        let (document, _) = parse(src);
+
+        let document = visit::transform(document, &mut vec![Box::new(Cleanup::default())], 2)?;
+
+        to_latex_with(document, |emitter| emitter.fallback = fallback)
+    }
+
+    #[test]
+    fn heading_and_equation() {
+
+        assert_eq!(
+            emit("# Costs & *Benefits*\n<Eq label=\"eq:x\">\n    e_1 = mc^2\n</Eq>\nSee <ref eq:x/> for $x_1$.\n", LatexFallback::Error).unwrap(),
+            concat!(
+                "\\section{Costs \\& \\emph{Benefits}}\n",
+                "\\begin{equation}\\label{eq:x}\n",
+                "\n    e_1 = mc^2\n",
+                "\n\\end{equation}\n",
+                "\nSee \\ref{eq:x} for $x_1$.\n",
+            )
+        );
+    }
+
+    #[test]
+    fn equation_contents() {
+
+        assert_eq!(emit("<Eq></Eq>", LatexFallback::Error).unwrap(), "\\begin{equation*}\n\n\\end{equation*}\n");
+
+        // equations are raw, so expressions are part of the math
+        assert_eq!(
+            emit("<Eq>a ${x} b</Eq>", LatexFallback::Error).unwrap(), 
+            "\\begin{equation*}\na ${x} b\n\\end{equation*}\n"
+        );
+    }
+
+    #[test]
+    fn lists_and_fallback() {
+
+        assert_eq!(
+            emit("- 50% off\n    1. nested\n- b\n", LatexFallback::Error).unwrap(),
+            "\\begin{itemize}\n\\item 50\\% off\\begin{enumerate}\n\\item nested\n\\end{enumerate}\n\n\\item b\n\\end{itemize}\n"
+        );
+
+        let src = "<Theorem label=\"t\">Text</Theorem>";
+
+        assert_eq!(
+            emit(src, LatexFallback::Error).unwrap_err().to_string(), 
+            "at 1:1: <Theorem> cannot be emitted as LaTeX."
+        );
+
+        assert_eq!(emit(src, LatexFallback::Unwrap).unwrap(), "Text");
+
+        assert_eq!(
+            emit(src, LatexFallback::Environment).unwrap(), 
+            "\\begin{Theorem}\\label{t}\nText\n\\end{Theorem}\n"
+        );
+    }
+
+    #[test]
+    fn links() {
+
+        assert_eq!(
+            emit("<a href=\"https://example.com/~user/a_b.html?q=50%#top\">a_b</a>", LatexFallback::Error).unwrap(),
+            "\\href{https://example.com/~user/a_b.html?q=50\\%\\#top}{a\\_b}"
+        );
+    }
+
+}
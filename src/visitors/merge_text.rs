@@ -0,0 +1,127 @@
+//!
+//! Merges adjacent text nodes into one, e.g. the pieces left behind by escapes and component expansion.
+//! Text is only merged within the children of an env, never across element or fragment boundaries.
+//! Should run after all transformers that insert text.
+//!
+
+use std::collections::VecDeque;
+
+use crate::document::{
+    EnvNode,
+    EnvNodeKind,
+    LeafNode,
+    Node,
+    NodeId,
+    NodeKind,
+    NodePosition,
+    visit::{Action, TransformResult, Visitor}
+};
+use crate::parse::Span;
+
+#[derive(Default)]
+pub struct MergeText;
+
+impl MergeText {
+
+    pub fn new() -> Self {
+        Self
+    }
+
+}
+
+fn is_text(node : &Node) -> bool {
+    matches!(node.kind, NodeKind::Leaf(LeafNode::Text(_)))
+}
+
+/** Span from the start of a to the end of b, if both are from the source. */
+fn merged_position(a : &NodePosition, b : &NodePosition) -> NodePosition {
+    match (a, b) {
+        (NodePosition::Source(a), NodePosition::Source(b)) => NodePosition::Source(Span::new(a.start, b.end)),
+        _ => NodePosition::Inserted,
+    }
+}
+
+fn merge(children : VecDeque<Node>) -> VecDeque<Node> {
+
+    let mut merged : VecDeque<Node> = VecDeque::with_capacity(children.len());
+
+    for child in children {
+
+        match (merged.back_mut(), child.kind) {
+            (Some(previous @ Node { kind: NodeKind::Leaf(LeafNode::Text(_)), .. }), NodeKind::Leaf(LeafNode::Text(text))) => {
+
+                previous.position = merged_position(&previous.position, &child.position);
+
+                if let NodeKind::Leaf(LeafNode::Text(previous)) = &mut previous.kind {
+                    previous.push_str(&text);
+                }
+            },
+            (_, kind) => merged.push_back(Node { kind, ..child }),
+        }
+    }
+
+    merged
+}
+
+impl Visitor for MergeText {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        let has_adjacent_text = match &node.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children
+                .iter()
+                .zip(children.iter().skip(1))
+                .any(|(a, b)| is_text(a) && is_text(b)),
+            _ => false,
+        };
+
+        if !has_adjacent_text {
+            return Ok(Action::keep(node));
+        }
+
+        let NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(children) }) = node.kind else {
+            unreachable!()
+        };
+
+        Ok(Action::replace(Node {
+            kind: NodeKind::Env(EnvNode::new_open(header, merge(children))),
+            ..node
+        }))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::document::NodeBuilder;
+
+    #[test]
+    fn merge_adjacent_text() {
+
+        let document = NodeBuilder::env("p")
+            .child(NodeBuilder::text("a"))
+            .child(NodeBuilder::text(" b"))
+            .child(NodeBuilder::text(" c"))
+            .child(NodeBuilder::env("em").child(NodeBuilder::text("d")).build())
+            .child(NodeBuilder::text("e"))
+            .child(NodeBuilder::fragment().child(NodeBuilder::text("f")).build())
+            .child(NodeBuilder::text("g"))
+            .build();
+
+        let document = transform(document, &mut vec![Box::new(MergeText::new())], 2).unwrap();
+
+        let expected = NodeBuilder::env("p")
+            .child(NodeBuilder::text("a b c"))
+            .child(NodeBuilder::env("em").child(NodeBuilder::text("d")).build())
+            .child(NodeBuilder::text("e"))
+            .child(NodeBuilder::fragment().child(NodeBuilder::text("f")).build())
+            .child(NodeBuilder::text("g"))
+            .build();
+
+        assert_eq!(document, expected);
+    }
+
+}
@@ -2,4 +2,21 @@
 pub mod html_emit;
 pub mod variables;
 pub mod cleanup;
-pub mod components;
\ No newline at end of file
+pub mod components;
+pub mod tabs;
+pub mod callouts;
+pub mod assets;
+pub mod conditions;
+pub mod references;
+pub mod accessibility;
+pub mod tag_map;
+pub mod toc;
+pub mod include;
+pub mod plain_text;
+pub mod latex_emit;
+pub mod highlight;
+pub mod merge_text;
+pub mod vtx_emit;
+
+#[cfg(test)]
+pub(crate) mod testing;
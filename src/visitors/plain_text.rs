@@ -0,0 +1,135 @@
+//!
+//! Extracts the text of a document, e.g. for search indexing or reading time estimates.
+//!
+//! The visitor does not modify the document. Code, comments, and attribute values are skipped.
+//! Block elements are separated by a space, inline elements are not.
+//!
+
+use crate::document::{
+    EnvNode,
+    EnvNodeHeaderKind,
+    LeafNode,
+    Node,
+    NodeId,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor}
+};
+use crate::visitors::html_emit::is_block_element;
+
+#[derive(Default)]
+pub struct PlainText {
+    /** Text extracted so far with collapsed whitespace. */
+    text: String,
+    /** Id of the env whose subtree is currently being skipped. */
+    skipping: Option<NodeId>,
+}
+
+impl PlainText {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /** Separates the following text from the text so far. */
+    fn push_space(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with(' ') {
+            self.text.push(' ');
+        }
+    }
+
+    fn push_text(&mut self, text : &str) {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                self.push_space();
+            } else {
+                self.text.push(c);
+            }
+        }
+    }
+
+    /** Number of words in the text so far. */
+    pub fn word_count(&self) -> usize {
+        self.text.split_whitespace().count()
+    }
+
+    pub fn into_string(mut self) -> String {
+
+        self.text.truncate(self.text.trim_end().len());
+
+        self.text
+    }
+
+}
+
+impl Visitor for PlainText {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        if self.skipping.is_some() {
+            return Ok(Action::keep(node));
+        }
+
+        match &node.kind {
+            NodeKind::Leaf(LeafNode::Text(text)) => self.push_text(text),
+            NodeKind::Env(EnvNode { header, .. }) => match &header.kind {
+                EnvNodeHeaderKind::Code | EnvNodeHeaderKind::ComponentDefinition => self.skipping = Some(node.id),
+                kind if is_block_element(kind) => self.push_space(),
+                _ => {},
+            },
+            _ => {},
+        }
+
+        Ok(Action::keep(node))
+    }
+
+    fn leave(&mut self, node : &Node, original_id : NodeId, _parent_id : Option<NodeId>) {
+
+        if self.skipping.is_some() {
+
+            if self.skipping == Some(original_id) {
+                self.skipping = None;
+            }
+
+            return;
+        }
+
+        if let NodeKind::Env(EnvNode { header, .. }) = &node.kind {
+            if is_block_element(&header.kind) {
+                self.push_space();
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::parse::parse;
+
+    #[test]
+    fn extract_text() {
+
+        let (document, _) = parse(concat!(
+            "# Title\n",
+            "Some **bold**ly  written te<em>x</em>t.\n",
+            "<Code>let x = 1;</Code>\n",
+            "/** note */\n",
+            "- item one\n",
+            "- item two\n",
+            "\n",
+            "<p>A <a href=\"https://example.com\">link</a>.</p><p>End</p>",
+        ));
+
+        let mut plain_text = PlainText::new();
+
+        transform(document, &mut vec![Box::new(&mut plain_text)], 1).unwrap();
+
+        assert_eq!(plain_text.word_count(), 12);
+
+        assert_eq!(plain_text.into_string(), "Title Some boldly written text. item one item two A link. End");
+    }
+
+}
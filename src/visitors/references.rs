@@ -0,0 +1,254 @@
+//!
+//! Numbering of labeled environments and resolution of references to them.
+//!
+//! <Eq label="eq:x">...</Eq>
+//! <Figure label="fig:y">...</Figure>
+//! See equation <ref eq:x/> and figure <ref fig:y/>.
+//!
+//! The first transform pass numbers all labeled environments (per environment name)
+//! and registers them by label. Subsequent passes replace <ref label/> with
+//! <a href="#label">number</a>. Labeled environments get their label as id to be linkable.
+//! The first pass reports every <ref> as changed, so that transform runs the next pass
+//! even if no labeled environment needed an id.
+//!
+//! The same visitor must therefore be used for (at least) two transform passes.
+//!
+
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use crate::document::{
+    EnvNode,
+    EnvNodeHeader,
+    EnvNodeHeaderKind,
+    LeafNode,
+    Node,
+    NodeBuilder,
+    NodeId,
+    NodeKind,
+    NodePosition,
+    visit::{Action, TransformResult, Visitor, VisitError}
+};
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    /** Name of the labeled environment, e.g. "Eq" or "Figure". */
+    pub kind: String,
+    /** Number of the environment among all labeled environments of the same kind (starting at 1). */
+    pub number: usize,
+    /** Position of the labeled environment. */
+    pub position: NodePosition,
+}
+
+pub type ReferenceRegistry = IndexMap<String, Reference>;
+
+#[derive(Default)]
+pub struct References {
+    /** Labeled environments in document order. */
+    registry: ReferenceRegistry,
+    /** Number of labeled environments per kind. */
+    counters: HashMap<String, usize>,
+    /** Set once the first pass has been completed. */
+    collected: bool,
+}
+
+impl References {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Labeled environments found in the first pass.
+    /// Can be used to build lists of figures, equations, etc.
+    ///
+    pub fn registry(&self) -> &ReferenceRegistry {
+        &self.registry
+    }
+
+    fn register(&mut self, node : Node, label : String) -> TransformResult {
+
+        let kind = match &node.kind {
+            NodeKind::Env(EnvNode { header, .. }) => header.kind.get_name().to_string(),
+            _ => unreachable!(),
+        };
+
+        if self.registry.contains_key(&label) {
+            return Err(VisitError::Unknown(format!("Duplicate label \"{}\".", label)));
+        }
+
+        let counter = self.counters.entry(kind.clone()).or_insert(0);
+
+        *counter += 1;
+
+        self.registry.insert(label.clone(), Reference {
+            kind,
+            number: *counter,
+            position: node.position.clone(),
+        });
+
+        match node.kind {
+            NodeKind::Env(EnvNode { mut header, kind: env_kind })
+                if !header.attrs.contains_key("id") => {
+
+                header.attrs.insert(
+                    "id".to_string(),
+                    Some(NodeBuilder::text(label))
+                );
+
+                Ok(Action::replace(Node {
+                    kind: NodeKind::Env(EnvNode { header, kind: env_kind }),
+                    ..node
+                }))
+            },
+            kind => Ok(Action::keep(Node { kind, ..node })),
+        }
+    }
+
+    fn resolve(&self, node : Node) -> TransformResult {
+
+        let label = match &node.kind {
+            NodeKind::Env(EnvNode { header, .. }) => header.attrs.keys().next().cloned(),
+            _ => None,
+        }.ok_or(VisitError::Unknown("Reference without label.".to_string()))?;
+
+        let reference = self.registry.get(&label).ok_or(
+            VisitError::Unknown(format!("Unknown label \"{}\".", label))
+        )?;
+
+        Ok(Action::replace(
+            NodeBuilder::env("a")
+                .attr("href", format!("#{}", label))
+                .child(NodeBuilder::text(reference.number.to_string()))
+                .position(node.position)
+                .build()
+        ))
+    }
+
+}
+
+fn get_label(node : &Node) -> Option<String> {
+    match &node.kind {
+        NodeKind::Env(EnvNode { header, .. }) => match header.attrs.get("label") {
+            Some(Some(Node { kind: NodeKind::Leaf(LeafNode::Text(label)), .. })) => Some(label.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_ref(node : &Node) -> bool {
+    matches!(
+        &node.kind,
+        NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), .. }, .. })
+            if name == "ref"
+    )
+}
+
+impl Visitor for References {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        if !self.collected {
+            match get_label(&node) {
+                Some(label) => self.register(node, label),
+                None if is_ref(&node) => Ok(Action::replace(node)),
+                None => Ok(Action::keep(node)),
+            }
+        } else if is_ref(&node) {
+            self.resolve(node)
+        } else {
+            Ok(Action::keep(node))
+        }
+    }
+
+    fn leave(&mut self, _node : &Node, _original_id : NodeId, parent_id : Option<NodeId>) {
+        // leaving the root completes a pass
+        if parent_id.is_none() {
+            self.collected = true;
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::{transform, transform_to_fixpoint};
+    use crate::parse::parse;
+    use crate::visitors::testing::to_html;
+
+    fn emit(src : &str, references : &mut References) -> Result<String, VisitError> {
+
+        let (document, _) = parse(src);
+
+        let document = transform(document, &mut vec![Box::new(&mut *references)], 2)?;
+
+        to_html(document)
+    }
+
+    #[test]
+    fn resolve_references() {
+
+        let mut references = References::new();
+
+        let html = emit(
+            concat!(
+                "See <ref fig:b/> and <ref eq:x/>.",
+                "<Figure label=\"fig:a\">A</Figure>",
+                "<Figure label=\"fig:b\">B</Figure>",
+                "<Eq label=\"eq:x\">x</Eq>",
+            ),
+            &mut references
+        ).unwrap();
+
+        // references may point forward
+        assert!(html.starts_with(r##"See <a href="#fig:b">2</a> and <a href="#eq:x">1</a>."##));
+
+        assert!(html.contains(r#"<Figure label="fig:b" id="fig:b">B</Figure>"#));
+
+        let figures = references
+            .registry()
+            .iter()
+            .filter(|(_, reference)| reference.kind == "Figure")
+            .map(|(label, reference)| (label.as_str(), reference.number))
+            .collect::<Vec<_>>();
+
+        assert_eq!(figures, [("fig:a", 1), ("fig:b", 2)]);
+    }
+
+    #[test]
+    fn invalid_labels() {
+
+        let error = emit("<ref eq:y/>", &mut References::new()).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "Unknown label \"eq:y\".");
+
+        let error = emit(
+            "<Eq label=\"eq:x\">x</Eq><Eq label=\"eq:x\">y</Eq>",
+            &mut References::new()
+        ).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "Duplicate label \"eq:x\".");
+    }
+
+    #[test]
+    fn labels_with_ids() {
+
+        // no labeled environment needs an id, so only <ref> changes in the first pass
+        let (document, _) = parse("see <ref eq:x/><Eq label=\"eq:x\" id=\"e\">x</Eq>");
+
+        let document = transform_to_fixpoint(document, &mut [Box::new(References::new())]).unwrap();
+
+        assert!(to_html(document).unwrap().starts_with(r##"see <a href="#eq:x">1</a>"##));
+
+        let (document, _) = parse("see <ref eq:y/>");
+
+        let error = transform_to_fixpoint(document, &mut [Box::new(References::new())]).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "Unknown label \"eq:y\".");
+    }
+
+}
@@ -0,0 +1,234 @@
+//!
+//! Expands tabbed content into accessible tab markup.
+//!
+//! <Tabs>
+//!     <Tab title="A">Contents of A</Tab>
+//!     <Tab title="B">Contents of B</Tab>
+//! </Tabs>
+//!
+//! becomes a tablist with one button per tab followed by one tabpanel per tab.
+//! The first tab is selected, all other panels are hidden.
+//! Switching tabs is done by an inline onclick handler on each button.
+//! A <style> element is inserted in front of the first tab container of the document.
+//!
+//! This must run before ComponentInsert as <Tabs> would otherwise be treated as a component.
+//!
+
+use std::collections::VecDeque;
+
+use crate::document::{
+    EnvNode,
+    EnvNodeKind,
+    EnvNodeHeader,
+    EnvNodeHeaderKind,
+    LeafNode,
+    Node,
+    NodeId,
+    NodeBuilder,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor, VisitError}
+};
+
+/// Selects the clicked tab and shows its panel, hides all others.
+const ONCLICK : &str = "var t=this;t.parentNode.querySelectorAll('[role=tab]').forEach(function(b){\
+var s=b===t;\
+b.setAttribute('aria-selected',s);\
+b.tabIndex=s?0:-1;\
+document.getElementById(b.getAttribute('aria-controls')).hidden=!s;\
+})";
+
+const STYLE : &str = ".tabs [role=tablist]{display:flex;gap:0.5em}\
+.tabs [role=tab][aria-selected=true]{font-weight:bold}";
+
+pub struct Tabs {
+    /** Number of tab containers expanded so far. Used to generate unique ids. */
+    count: usize,
+}
+
+impl Default for Tabs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tabs {
+
+    pub fn new() -> Self {
+        Self { count: 0 }
+    }
+
+}
+
+fn is_tab(node : &Node) -> bool {
+    matches!(
+        &node.kind,
+        NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), .. }, .. })
+            if name == "Tab"
+    )
+}
+
+///
+/// Checks if a child of <Tabs> may be dropped.
+///
+fn is_ignorable(node : &Node) -> bool {
+    match &node.kind {
+        NodeKind::Leaf(LeafNode::Text(text)) => text.chars().all(char::is_whitespace),
+        NodeKind::Leaf(LeafNode::Comment(_)) => true,
+        _ => false,
+    }
+}
+
+impl Visitor for Tabs {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+        match node.kind {
+            NodeKind::Env(
+                EnvNode {
+                    header: EnvNodeHeader {
+                        kind: EnvNodeHeaderKind::Other(ref name),
+                        ..
+                    },
+                    ..
+                }
+            ) if name == "Tabs" => { },
+            _ => return Ok(Action::keep(node))
+        };
+
+        let children = match node.kind {
+            NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) => children,
+            _ => VecDeque::new(),
+        };
+
+        let prefix = format!("tabs-{}", self.count);
+
+        let mut buttons = VecDeque::new();
+        let mut panels = VecDeque::new();
+
+        for child in children.into_iter().filter(|child| !is_ignorable(child)) {
+
+            if !is_tab(&child) {
+                return Err(VisitError::Unknown(
+                    "Only <Tab> elements are allowed in <Tabs>.".to_string()
+                ));
+            }
+
+            let (mut attrs, contents) = match child.kind {
+                NodeKind::Env(EnvNode { header, kind }) => (
+                    header.attrs,
+                    match kind {
+                        EnvNodeKind::Open(contents) => contents,
+                        EnvNodeKind::SelfClosing => VecDeque::new(),
+                    }
+                ),
+                _ => unreachable!(),
+            };
+
+            let title = attrs.shift_remove("title").flatten().ok_or(
+                VisitError::Unknown("Tab must have a title.".to_string())
+            )?;
+
+            let index = buttons.len();
+            let selected = index == 0;
+
+            let tab_id = format!("{}-tab-{}", prefix, index);
+            let panel_id = format!("{}-panel-{}", prefix, index);
+
+            buttons.push_back(
+                NodeBuilder::env("button")
+                    .attr("type", "button")
+                    .attr("role", "tab")
+                    .attr("id", &tab_id)
+                    .attr("aria-controls", &panel_id)
+                    .attr("aria-selected", if selected { "true" } else { "false" })
+                    .attr("tabindex", if selected { "0" } else { "-1" })
+                    .attr("onclick", ONCLICK)
+                    .child(title)
+                    .build()
+            );
+
+            let panel = NodeBuilder::env("div")
+                .attr("role", "tabpanel")
+                .attr("id", panel_id)
+                .attr("aria-labelledby", tab_id)
+                .attr("tabindex", "0");
+
+            let panel = if selected { panel } else { panel.flag("hidden") };
+
+            panels.push_back(panel.children(contents).build());
+        }
+
+        panels.push_front(NodeBuilder::env("div").attr("role", "tablist").children(buttons).build());
+
+        let container = NodeBuilder::env("div")
+            .attr("class", "tabs")
+            .attr("id", &prefix)
+            .children(panels)
+            .build();
+
+        let container = if self.count == 0 {
+            let style = NodeBuilder::env("style").child(NodeBuilder::text(STYLE)).build();
+
+            NodeBuilder::fragment().children([style, container]).build()
+        } else {
+            container
+        };
+
+        self.count += 1;
+
+        Ok(Action::replace(container))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::parse::parse;
+    use crate::visitors::testing::emit_html;
+
+    #[test]
+    fn two_tabs() {
+
+        let html = emit_html(
+            "<Tabs>\n    <Tab title=\"A\">Contents of A</Tab>\n    <Tab title=\"B\">Contents of B</Tab>\n</Tabs>\n",
+            &mut vec![Box::new(Tabs::new())],
+            1
+        ).unwrap();
+
+        // style is only inserted once, in front of the container
+        assert_eq!(html.matches("<style>").count(), 1);
+        assert!(html.contains(r#"</style><div class="tabs" id="tabs-0"><div role="tablist">"#));
+
+        assert!(html.contains(
+            r#"<button type="button" role="tab" id="tabs-0-tab-0" aria-controls="tabs-0-panel-0" aria-selected="true" tabindex="0""#
+        ));
+        assert!(html.contains(
+            r#"<button type="button" role="tab" id="tabs-0-tab-1" aria-controls="tabs-0-panel-1" aria-selected="false" tabindex="-1""#
+        ));
+
+        assert!(html.contains(
+            r#"<div role="tabpanel" id="tabs-0-panel-0" aria-labelledby="tabs-0-tab-0" tabindex="0">Contents of A</div>"#
+        ));
+        assert!(html.contains(
+            r#"<div role="tabpanel" id="tabs-0-panel-1" aria-labelledby="tabs-0-tab-1" tabindex="0" hidden>Contents of B</div>"#
+        ));
+
+        assert!(html.contains(">A</button>"));
+        assert!(html.contains(">B</button>"));
+    }
+
+    #[test]
+    fn invalid_children() {
+
+        let (document, _) = parse("<Tabs><p>Not a tab</p></Tabs>\n");
+
+        assert!(transform(document, &mut vec![Box::new(Tabs::new())], 1).is_err());
+
+        let (document, _) = parse("<Tabs><Tab>No title</Tab></Tabs>\n");
+
+        assert!(transform(document, &mut vec![Box::new(Tabs::new())], 1).is_err());
+    }
+
+}
@@ -0,0 +1,74 @@
+//!
+//! Renames element tags, e.g. a custom <Container> to <div>.
+//! Must run before ComponentInsert if it maps capitalized names.
+//!
+
+use std::collections::HashMap;
+
+use crate::document::{
+    EnvNode,
+    Node,
+    NodeId,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor}
+};
+
+pub struct TagMap {
+    /** New tag name by original tag name. */
+    pub tags: HashMap<String, String>,
+}
+
+impl TagMap {
+
+    pub fn new(tags : HashMap<String, String>) -> Self {
+        Self { tags }
+    }
+
+}
+
+impl Visitor for TagMap {
+
+    fn enter(&mut self, mut node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        let NodeKind::Env(EnvNode { header, .. }) = &mut node.kind else {
+            return Ok(Action::keep(node));
+        };
+
+        match self.tags.get(header.kind.get_name()) {
+            Some(new_name) => {
+                header.rename(new_name);
+
+                Ok(Action::replace(node))
+            },
+            None => Ok(Action::keep(node))
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::parse::parse;
+    use crate::visitors::testing::to_html;
+
+    #[test]
+    fn rename_tags() {
+
+        let (document, _) = parse(r#"<Container class="wide"><Em>Hi</Em> <b>there</b></Container>"#);
+
+        let tags = HashMap::from([
+            ("Container".to_string(), "div".to_string()),
+            ("Em".to_string(), "em".to_string()),
+        ]);
+
+        let document = transform(document, &mut vec![Box::new(TagMap::new(tags))], 1).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, r#"<div class="wide"><em>Hi</em> <b>there</b></div>"#);
+    }
+
+}
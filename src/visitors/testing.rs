@@ -0,0 +1,96 @@
+//!
+//! Helpers shared by the tests of the visitors.
+//!
+
+use crate::document::{
+    Node,
+    visit::{transform, VisitError, Visitor}
+};
+use crate::parse::parse;
+use crate::visitors::html_emit::HTMLEmitter;
+use crate::visitors::latex_emit::LatexEmitter;
+use crate::visitors::vtx_emit::VtxEmitter;
+
+/** Emits the node as HTML with the default emitter. */
+pub(crate) fn to_html(node : Node) -> Result<String, VisitError> {
+
+    to_html_with(node, |_| {})
+}
+
+///
+/// Emits the node as HTML with an emitter configured by the given function,
+/// e.g. to set options or register envs.
+///
+pub(crate) fn to_html_with(
+    node : Node,
+    configure : impl for<'a> FnOnce(&mut HTMLEmitter<Box<dyn FnMut(&str) + 'a>>)
+) -> Result<String, VisitError> {
+
+    let mut html = String::new();
+
+    let mut emitter = HTMLEmitter::new(Box::new(|s : &str| html.push_str(s)) as Box<dyn FnMut(&str)>);
+
+    configure(&mut emitter);
+
+    transform(node, &mut vec![Box::new(emitter)], 1)?;
+
+    Ok(html)
+}
+
+///
+/// Parses the source, runs the visitors on it and emits the result as HTML.
+///
+pub(crate) fn emit_html(
+    src : &str,
+    visitors : &mut Vec<Box<dyn Visitor + '_>>,
+    max_passes : u32
+) -> Result<String, VisitError> {
+
+    let (document, _) = parse(src);
+
+    to_html(transform(document, visitors, max_passes)?)
+}
+
+///
+/// Emits the node as LaTeX with an emitter configured by the given function.
+///
+pub(crate) fn to_latex_with(
+    node : Node,
+    configure : impl for<'a> FnOnce(&mut LatexEmitter<Box<dyn FnMut(&str) + 'a>>)
+) -> Result<String, VisitError> {
+
+    let mut latex = String::new();
+
+    let mut emitter = LatexEmitter::new(Box::new(|s : &str| latex.push_str(s)) as Box<dyn FnMut(&str)>);
+
+    configure(&mut emitter);
+
+    transform(node, &mut vec![Box::new(emitter)], 1)?;
+
+    Ok(latex)
+}
+
+/** Emits the node as vtx with the default emitter. */
+pub(crate) fn to_vtx(node : Node) -> Result<String, VisitError> {
+
+    to_vtx_with(node, |_| {})
+}
+
+///
+/// Emits the node as vtx with an emitter configured by the given function.
+///
+pub(crate) fn to_vtx_with(
+    node : Node,
+    configure : impl for<'a> FnOnce(&mut VtxEmitter<Box<dyn FnMut(&str) + 'a>>)
+) -> Result<String, VisitError> {
+
+    let mut vtx = String::new();
+
+    let mut emitter = VtxEmitter::new(Box::new(|s : &str| vtx.push_str(s)) as Box<dyn FnMut(&str)>);
+
+    configure(&mut emitter);
+
+    transform(node, &mut vec![Box::new(emitter)], 1)?;
+
+    Ok(vtx)
+}
@@ -0,0 +1,301 @@
+//!
+//! Table of contents generated from the headings of a document.
+//!
+//! <toc/>
+//! # Introduction
+//! ## Details
+//!
+//! The first transform pass collects all headings and assigns each a unique id
+//! derived from its text (unless it already has one). Subsequent passes replace
+//! <toc/> with a nested list of links to the headings.
+//! The first pass reports <toc/> as changed, so that transform runs the next pass
+//! even if no heading needed an id.
+//!
+//! The same visitor must therefore be used for (at least) two transform passes.
+//!
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::document::{
+    EnvNode,
+    EnvNodeHeader,
+    EnvNodeHeaderKind,
+    EnvNodeKind,
+    LeafNode,
+    Node,
+    NodeBuilder,
+    NodeId,
+    NodeKind,
+    visit::{Action, TransformResult, Visitor}
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TocEntry {
+    /** Heading level (1-6). */
+    pub level: usize,
+    /** Id of the heading. */
+    pub id: String,
+    /** Text content of the heading. */
+    pub title: String,
+}
+
+#[derive(Default)]
+pub struct TableOfContents {
+    /** Headings in document order. */
+    entries: Vec<TocEntry>,
+    /** Ids that have been assigned or found so far. */
+    ids: HashSet<String>,
+    /** Set once the first pass has been completed. */
+    collected: bool,
+}
+
+///
+/// Turns text into a string that can be used as an id, e.g. "Hello, World!" -> "hello-world".
+///
+pub fn slugify(text : &str) -> String {
+
+    let mut slug = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    match slug.trim_end_matches('-') {
+        "" => "section".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+///
+/// Concatenated text of all text nodes below the node.
+///
+pub fn text_content(node : &Node) -> String {
+    node.descendants()
+        .filter_map(|node| match &node.kind {
+            NodeKind::Leaf(LeafNode::Text(text)) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn text_attr(node : &Node, key : &str) -> Option<String> {
+    match &node.kind {
+        NodeKind::Env(EnvNode { header, .. }) => match header.attrs.get(key) {
+            Some(Some(Node { kind: NodeKind::Leaf(LeafNode::Text(value)), .. })) => Some(value.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+///
+/// Builds the items of a list starting at entries[*i] until an entry with a lower level is found.
+/// Entries with a higher level are nested into the previous item.
+///
+fn build_items(entries : &[TocEntry], i : &mut usize, level : usize) -> VecDeque<Node> {
+
+    let mut items : VecDeque<Node> = VecDeque::new();
+
+    while let Some(entry) = entries.get(*i).filter(|entry| entry.level >= level) {
+
+        if entry.level == level {
+            let link = NodeBuilder::env("a")
+                .attr("href", format!("#{}", entry.id))
+                .child(NodeBuilder::text(&entry.title))
+                .build();
+
+            items.push_back(NodeBuilder::env("li").child(link).build());
+
+            *i += 1;
+        } else {
+            let list = NodeBuilder::env("ul").children(build_items(entries, i, entry.level)).build();
+
+            match items.back_mut().map(|item| &mut item.kind) {
+                Some(NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. })) => {
+                    children.push_back(list)
+                },
+                // the list starts with a deeper heading
+                _ => items.push_back(NodeBuilder::env("li").child(list).build()),
+            }
+        }
+    }
+
+    items
+}
+
+impl TableOfContents {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /** Headings found in the first pass. */
+    pub fn entries(&self) -> &[TocEntry] {
+        &self.entries
+    }
+
+    fn unique_id(&mut self, title : &str) -> String {
+
+        let slug = slugify(title);
+
+        let mut id = slug.clone();
+
+        let mut n = 1;
+
+        while self.ids.contains(&id) {
+            id = format!("{}-{}", slug, n);
+            n += 1;
+        }
+
+        self.ids.insert(id.clone());
+
+        id
+    }
+
+    fn collect(&mut self, node : Node, level : usize) -> TransformResult {
+
+        let title = text_content(&node);
+
+        if let Some(id) = text_attr(&node, "id") {
+            self.ids.insert(id.clone());
+            self.entries.push(TocEntry { level, id, title });
+
+            return Ok(Action::keep(node));
+        }
+
+        let id = self.unique_id(&title);
+
+        self.entries.push(TocEntry { level, id: id.clone(), title });
+
+        match node.kind {
+            NodeKind::Env(EnvNode { mut header, kind }) => {
+
+                header.attrs.insert(
+                    "id".to_string(),
+                    Some(NodeBuilder::text(id))
+                );
+
+                Ok(Action::replace(Node { kind: NodeKind::Env(EnvNode { header, kind }), ..node }))
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /** Creates the nested list of all entries. */
+    pub fn to_list(&self) -> VecDeque<Node> {
+
+        let Some(level) = self.entries.iter().map(|entry| entry.level).min() else {
+            return VecDeque::new();
+        };
+
+        VecDeque::from([NodeBuilder::env("ul").children(build_items(&self.entries, &mut 0, level)).build()])
+    }
+
+}
+
+fn is_toc(node : &Node) -> bool {
+    matches!(
+        &node.kind,
+        NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), .. }, .. })
+            if name == "toc"
+    )
+}
+
+impl Visitor for TableOfContents {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        let level = match &node.kind {
+            NodeKind::Env(EnvNode { header, .. }) => header.kind.heading_level(),
+            _ => None,
+        };
+
+        match level {
+            Some(level) if !self.collected => self.collect(node, level),
+            _ if !self.collected && is_toc(&node) => Ok(Action::replace(node)),
+            _ if self.collected && is_toc(&node) => Ok(Action::replace(Node {
+                kind: NodeKind::new_fragment(self.to_list()),
+                ..node
+            })),
+            _ => Ok(Action::keep(node)),
+        }
+    }
+
+    fn leave(&mut self, _node : &Node, _original_id : NodeId, parent_id : Option<NodeId>) {
+        // leaving the root completes a pass
+        if parent_id.is_none() {
+            self.collected = true;
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::{transform, transform_to_fixpoint};
+    use crate::parse::parse;
+    use crate::visitors::testing::to_html;
+
+    #[test]
+    fn slugs() {
+
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  snake_case -- Über "), "snake-case-über");
+        assert_eq!(slugify("?!"), "section");
+    }
+
+    #[test]
+    fn table_of_contents() {
+
+        let (document, _) = parse(
+            "<toc/><h1>Intro</h1><h2>Details <em>here</em></h2><h2 id=\"x\">More</h2><h1>Intro</h1>"
+        );
+
+        let mut toc = TableOfContents::new();
+
+        let document = transform(document, &mut vec![Box::new(&mut toc)], 2).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(
+            html,
+            concat!(
+                "<ul>",
+                "<li><a href=\"#intro\">Intro</a><ul>",
+                "<li><a href=\"#details-here\">Details here</a></li>",
+                "<li><a href=\"#x\">More</a></li>",
+                "</ul></li>",
+                "<li><a href=\"#intro-1\">Intro</a></li>",
+                "</ul>",
+                "<h1 id=\"intro\">Intro</h1>",
+                "<h2 id=\"details-here\">Details <em>here</em></h2>",
+                "<h2 id=\"x\">More</h2>",
+                "<h1 id=\"intro-1\">Intro</h1>",
+            )
+        );
+    }
+
+    #[test]
+    fn headings_with_ids() {
+
+        // no heading needs an id, so only <toc/> changes in the first pass
+        let (document, _) = parse("<toc/><h1 id=\"a\">A</h1><h2 id=\"b\">B</h2>");
+
+        let document = transform_to_fixpoint(document, &mut [Box::new(TableOfContents::new())]).unwrap();
+
+        assert_eq!(
+            to_html(document).unwrap(),
+            concat!(
+                "<ul><li><a href=\"#a\">A</a><ul><li><a href=\"#b\">B</a></li></ul></li></ul>",
+                "<h1 id=\"a\">A</h1><h2 id=\"b\">B</h2>",
+            )
+        );
+    }
+
+}
@@ -1,11 +1,13 @@
-///
-/// Visitor/transformer for evaluating variable expressions.
-///
+//!
+//! Visitor/transformer for evaluating variable expressions.
+//!
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
+use crate::expr::{self, ExprError, Value};
 use crate::document::{
     EnvNode, 
+    EnvNodeAttrs,
     EnvNodeHeader, 
     EnvNodeHeaderKind, 
     EnvNodeKind, 
@@ -13,9 +15,13 @@ use crate::document::{
     Node, 
     NodeId, 
     NodeKind,
+    NodePosition,
     visit::{Action, TransformResult, VisitError, Visitor}
 };
 
+/// See Variables::expanding.
+const MAX_NESTED_EXPANSIONS : usize = 32;
+
 struct Scope {
     /// The Node this stack belongs to
     node_id: NodeId,
@@ -30,14 +36,27 @@ pub struct Variables {
     /// The stack does not grow if a node does not define any variables.
     /// The stack is popped when leaving the node.
     /// 
-    scopes: Vec<Scope>
+    scopes: Vec<Scope>,
+    ///
+    /// Ids of the values of the variables that are currently being expanded with the id of the expression node.
+    /// A value may be expanded within itself, e.g. a component used in its own children, 
+    /// but expanding it more than MAX_NESTED_EXPANSIONS times is treated as infinite recursion.
+    /// 
+    expanding: Vec<(NodeId, NodeId)>,
+}
+
+impl Default for Variables {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Variables {
 
     pub fn new() -> Self {
         Variables {
-            scopes: Vec::new()
+            scopes: Vec::new(),
+            expanding: Vec::new(),
         }
     }
 
@@ -51,7 +70,183 @@ impl Variables {
             }
         }
 
-        return None
+        None
+    }
+
+    ///
+    /// Resolves ${expr}. Anything but a plain variable name is evaluated as an expression,
+    /// unless a variable with exactly that name is defined, e.g. <var data-id="x"/>${data-id}.
+    ///
+    fn resolve_expression(&self, expr : &String) -> Result<Node, VisitError> {
+
+        if !expr::is_identifier(expr) && self.resolve(expr).is_none() {
+            return self.evaluate_expression(expr);
+        }
+
+        let value = self.resolve(expr).ok_or(
+            VisitError::Unknown(
+                format!("Cannot resolve variable \"{}\".", expr)
+            ),
+        )?;
+
+        Ok(value.instantiate())
+    }
+
+    ///
+    /// Evaluates an expression like ${count * 2} to a text node.
+    /// Only variables with a text value can be used in expressions.
+    ///
+    fn evaluate_expression(&self, expr : &str) -> Result<Node, VisitError> {
+
+        let value = expr::evaluate(expr, |name| match self.resolve(&name.to_string()) {
+            Some(Node { kind: NodeKind::Leaf(LeafNode::Text(text)), .. }) => Ok(Value::from_text(text)),
+            Some(_) => Err(ExprError::TypeMismatch(format!("variable \"{}\" is not text", name))),
+            None => Err(ExprError::UndefinedVariable(name.to_string())),
+        }).map_err(
+            |error| VisitError::Unknown(format!("In expression \"{}\": {}", expr, error))
+        )?;
+
+        Ok(Node::new(NodeKind::Leaf(LeafNode::Text(value.to_string())), NodePosition::Inserted))
+    }
+
+    ///
+    /// Substitutes the variable expressions in an attribute value.
+    /// Values that resolve to text only are merged into a single text node.
+    /// 
+    fn resolve_attr_value(&self, value : Node) -> Result<Node, VisitError> {
+        match value.kind {
+            NodeKind::Leaf(LeafNode::VariableExpression(expr)) => self.resolve_expression(&expr),
+            NodeKind::Env(EnvNode { 
+                header: header @ EnvNodeHeader { kind: EnvNodeHeaderKind::Fragment, .. },
+                kind: EnvNodeKind::Open(children),
+            }) => {
+                let children = children
+                    .into_iter()
+                    .map(|child| self.resolve_attr_value(child))
+                    .collect::<Result<Vec<Node>, VisitError>>()?;
+
+                let text = children
+                    .iter()
+                    .map(|child| match &child.kind {
+                        NodeKind::Leaf(LeafNode::Text(text)) => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Option<String>>();
+
+                let kind = match text {
+                    Some(text) => NodeKind::Leaf(LeafNode::Text(text)),
+                    None => NodeKind::Env(EnvNode::new_open(header, children.into())),
+                };
+
+                Ok(Node { kind, ..value })
+            },
+            kind => Ok(Node { kind, ..value }),
+        }
+    }
+
+    ///
+    /// Substitutes the variable expressions in the attribute values of an env node.
+    /// Returns true if any attribute has been changed.
+    /// 
+    fn resolve_attrs(&self, node : Node) -> Result<(Node, bool), VisitError> {
+
+        let contains_expression = |value : &Node| match &value.kind {
+            NodeKind::Leaf(LeafNode::VariableExpression(_)) => true,
+            NodeKind::Env(EnvNode { header, .. }) => header.kind == EnvNodeHeaderKind::Fragment,
+            _ => false,
+        };
+
+        match node.kind {
+            NodeKind::Env(EnvNode { header, kind }) 
+                if header.attrs.values().flatten().any(contains_expression) => {
+
+                let attrs = header.attrs
+                    .into_iter()
+                    .map(|(key, value)| Ok((
+                        key, 
+                        value.map(|value| self.resolve_attr_value(value)).transpose()?
+                    )))
+                    .collect::<Result<EnvNodeAttrs, VisitError>>()?;
+
+                Ok((
+                    Node {
+                        kind: NodeKind::Env(EnvNode { 
+                            header: EnvNodeHeader { attrs, ..header }, 
+                            kind 
+                        }),
+                        ..node
+                    },
+                    true
+                ))
+            },
+            kind => Ok((Node { kind, ..node }, false))
+        }
+    }
+
+    ///
+    /// Expands <for item in items>body</for> into one fragment per list item.
+    /// Each fragment defines the item variable before the body, so it is bound in its own scope.
+    /// 
+    fn expand_for(&self, node : Node) -> Result<Node, VisitError> {
+
+        let NodeKind::Env(EnvNode { header, kind }) = node.kind else {
+            unreachable!()
+        };
+
+        let keys = header.attrs
+            .iter()
+            .map(|(key, value)| value.is_none().then_some(key.as_str()))
+            .collect::<Option<Vec<&str>>>();
+
+        let (item, list) = match keys.as_deref() {
+            Some([item, "in", list]) => (*item, list.to_string()),
+            _ => return Err(VisitError::Unknown("Expected <for item in list>.".to_string())),
+        };
+
+        let items = match self.resolve(&list) {
+            Some(Node { 
+                kind: NodeKind::Env(EnvNode { 
+                    header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), .. }, 
+                    kind: items,
+                }), 
+                .. 
+            }) if name == "list" => match items {
+                EnvNodeKind::Open(items) => items.iter().collect(),
+                EnvNodeKind::SelfClosing => Vec::new(),
+            },
+            Some(_) => return Err(VisitError::Unknown(format!("Variable \"{}\" is not a list.", list))),
+            None => return Err(VisitError::Unknown(format!("Cannot resolve variable \"{}\".", list))),
+        };
+
+        let body = match kind {
+            EnvNodeKind::Open(children) => children,
+            EnvNodeKind::SelfClosing => VecDeque::new(),
+        };
+
+        let mut iterations = VecDeque::new();
+
+        for entry in items {
+
+            let value = match &entry.kind {
+                NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), .. }, kind }) 
+                    if name == "item" => match kind {
+                    EnvNodeKind::Open(children) if children.len() == 1 => children[0].clone(),
+                    EnvNodeKind::Open(children) => Node::new(NodeKind::new_fragment(children.clone()), NodePosition::Inserted),
+                    EnvNodeKind::SelfClosing => Node::new(NodeKind::new_fragment(VecDeque::new()), NodePosition::Inserted),
+                },
+                // whitespace between items
+                NodeKind::Leaf(LeafNode::Text(text)) if text.trim().is_empty() => continue,
+                _ => return Err(VisitError::Unknown(format!("List \"{}\" must only contain <item> elements.", list))),
+            };
+
+            let mut iteration = VecDeque::from([Node::new_variable_definition(item, value)]);
+
+            iteration.extend(body.iter().map(Node::instantiate));
+
+            iterations.push_back(Node::new(NodeKind::new_fragment(iteration), NodePosition::Inserted));
+        }
+
+        Ok(Node { kind: NodeKind::new_fragment(iterations), ..node })
     }
 
     pub fn define(&mut self, node_id: NodeId, name : String, value : Node) {
@@ -77,30 +272,49 @@ impl Variables {
 impl Visitor for Variables {
 
     fn enter(&mut self, node : Node, parent_id : Option<NodeId>) -> TransformResult {
+
+        let (node, attrs_resolved) = self.resolve_attrs(node)?;
+
+        // metadata from the frontmatter is defined for the entire document
+        if let NodeKind::Env(EnvNode { header: EnvNodeHeader { kind: EnvNodeHeaderKind::Module, attrs, .. }, .. }) = &node.kind {
+            for (key, value) in attrs {
+                if let Some(value) = value {
+                    self.define(node.id, key.clone(), value.clone());
+                }
+            }
+        }
+
         match &node.kind {
             // a variable is being used
             NodeKind::Leaf(LeafNode::VariableExpression(expr)) => {
-                
-                let value = self.resolve(&expr).ok_or(
-                    VisitError::Unknown(
-                        format!("Cannot resolve variable \"{}\".", expr)
-                    ),
-                )?;
 
-                Ok(Action::replace(Node {
-                    id: Node::generate_id(),
-                    ..value.clone()
-                }))
+                // expressions evaluate to text, which is not expanded any further
+                let definition_id = self.resolve(expr).map(|value| value.id);
+
+                let nested = |id| self.expanding.iter().filter(|(expanding, _)| *expanding == id).count();
+
+                if definition_id.is_some_and(|id| nested(id) == MAX_NESTED_EXPANSIONS) {
+                    return Err(VisitError::Unknown(
+                        format!("Recursive component or variable \"{}\".", expr)
+                    ));
+                }
+
+                let value = self.resolve_expression(expr)?;
+
+                // the value is visited next, until leave is called with the id of the expression
+                if let Some(definition_id) = definition_id {
+                    self.expanding.push((definition_id, node.id));
+                }
+
+                Ok(Action::replace(value))
             },
             // a variable is being defined
             NodeKind::Env(
                 EnvNode { 
                     header: EnvNodeHeader { 
-                        attrs, 
                         kind: EnvNodeHeaderKind::Other(name),
                         ..
                     },
-                    kind: env_node_kind,
                     ..
                     // TODO: should "var" be an internal type? 
                 }
@@ -109,49 +323,246 @@ impl Visitor for Variables {
                 // this is OK because var cannot be the root node of a document
                 let parent_id = parent_id.unwrap();
 
-                let (key, value) = attrs.iter().next().ok_or(
+                let NodeKind::Env(EnvNode { header, kind: env_node_kind }) = node.kind else {
+                    unreachable!()
+                };
+
+                let (key, value) = header.attrs.into_iter().next().ok_or(
                     VisitError::Unknown("Variable definition empty.".to_string())
                 )?;
 
-                let value = match &env_node_kind {
+                // the value is moved into the scope, it is only copied when used
+                let value = match env_node_kind {
                     // <var name>value</var>
-                    EnvNodeKind::Open(children) => {
-                        if children.len() == 1 {
-                            children.front()
-                        } else {
-                            dbg!(children);
-                            todo!("Variable definitions must have exactly one child.");
-                        }
-                    },
+                    EnvNodeKind::Open(mut children) if children.len() == 1 => children.pop_front(),
+                    // <var name>multiple <b>values</b></var> is bound as a fragment
+                    EnvNodeKind::Open(children) if !children.is_empty() => Some(
+                        Node::new(NodeKind::new_fragment(children), NodePosition::Inserted)
+                    ),
+                    EnvNodeKind::Open(_) => None,
                     // <var name="value" />
-                    EnvNodeKind::SelfClosing => value.as_ref(),
+                    EnvNodeKind::SelfClosing => value,
                 };
 
                 let value = value.ok_or(
                     VisitError::Unknown(
-                        String::from(
-                            format!("Empty variable definition for {}", key)
-                        )
+                        format!("Empty variable definition for {}", key)
                     )
                 )?;
 
-                self.define(parent_id, key.to_string(), value.clone());
+                self.define(parent_id, key, value);
 
-                Ok(Action::remove(node))
+                Ok(Action::remove(Node { kind: NodeKind::new_fragment(VecDeque::new()), ..node }))
             }
+            NodeKind::Env(
+                EnvNode { 
+                    header: EnvNodeHeader { kind: EnvNodeHeaderKind::Other(name), .. },
+                    ..
+                }
+            ) if name == "for" => Ok(Action::replace(self.expand_for(node)?)),
+            _ if attrs_resolved => Ok(Action::replace(node)),
             _ => Ok(Action::keep(node))
         }
 
     }
 
-    fn leave(&mut self, _ : &Node, node_id : NodeId, _ : Option<NodeId>) {
-        match self.scopes.last() {
-            Some(scope) 
-                if scope.node_id == node_id => { self.scopes.pop(); },
-            _ => {}
+    fn leave(&mut self, node : &Node, original_id : NodeId, _ : Option<NodeId>) {
+
+        if self.expanding.last().is_some_and(|(_, id)| *id == original_id) {
+            self.expanding.pop();
+        }
+
+        // scopes are owned by the node the children were visited under,
+        // which has a new id if enter replaced the node (e.g. by the value of an expression)
+        if self.scopes.last().is_some_and(|scope| scope.node_id == node.id || scope.node_id == original_id) {
+            self.scopes.pop();
         }
     }
 
 }
 
-// TODO: test
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::document::visit::transform;
+    use crate::parse::parse;
+    use crate::visitors::testing::{emit_html, to_html};
+
+    fn emit(src : &str) -> String {
+
+        emit_html(src, &mut vec![Box::new(Variables::new())], 1).unwrap()
+    }
+
+    #[test]
+    fn recursion() {
+
+        use crate::visitors::components::components;
+
+        let (document, _) = parse("<Component A>a ${A}</Component><p><A/></p>");
+
+        let (register, insert) = components();
+
+        let document = transform(document, &mut vec![Box::new(register)], 1).unwrap();
+        let document = transform(document, &mut vec![Box::new(insert)], 1).unwrap();
+
+        let error = transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "Recursive component or variable \"A\".");
+
+        // using the same variable multiple times is not recursion
+        assert_eq!(
+            emit("<var a=\"x\"/><var b><>${a}${a}</></var>${b}${b}"),
+            "xxxx"
+        );
+
+        // nor is using a component in its own children or those of another one
+        let (document, _) = parse("<Component A>[${children}]</Component><Component B>${children}</Component><A><B><A>x</A></B></A>");
+
+        let (register, insert) = components();
+
+        let document = transform(document, &mut vec![Box::new(register), Box::new(insert)], 2).unwrap();
+        let document = transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, "[[x]]");
+    }
+
+    #[test]
+    fn interpolation() {
+
+        assert_eq!(emit(r#"<var name="World"/>Hello ${name}!"#), "Hello World!");
+    }
+
+    #[test]
+    fn frontmatter() {
+
+        assert_eq!(
+            emit("---\ntitle: Notes\nlang: en\n---\n<p lang=\"${lang}\">${title}</p><var title=\"x\"/>${title}"),
+            "<p lang=\"en\">Notes</p>x"
+        );
+    }
+
+    #[test]
+    fn variables_in_attrs() {
+
+        assert_eq!(
+            emit(r#"<var baseUrl="example.com"/><a href="${baseUrl}?page=2" title='${baseUrl}'>Page</a>"#),
+            r#"<a href="example.com?page=2" title="example.com">Page</a>"#
+        );
+
+        assert_eq!(
+            emit(r#"<var n="2"/><var title="Intro"/><h1 title="Chapter ${n}: ${title}">${title}</h1>"#),
+            r#"<h1 title="Chapter 2: Intro">Intro</h1>"#
+        );
+
+        // escaped expressions are kept as text
+        assert_eq!(
+            emit(r#"<a href="\${baseUrl}">Page</a>"#),
+            r#"<a href="${baseUrl}">Page</a>"#
+        );
+
+        let (document, _) = parse(r#"<a href="${undefined}">Page</a>"#);
+
+        assert!(transform(document, &mut vec![Box::new(Variables::new())], 1).is_err());
+    }
+
+    #[test]
+    fn expressions() {
+
+        assert_eq!(emit(r#"<var a="2"/>${a + 1}"#), "3");
+        assert_eq!(emit(r#"<var count="4"/><p class="c${count * 2}">${count * 2.5}</p>"#), r#"<p class="c8">10</p>"#);
+
+        let error = |src : &str| {
+            let (document, _) = parse(src);
+            transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap_err().root_cause().to_string()
+        };
+
+        assert_eq!(error("${b + 1}"), "In expression \"b + 1\": Cannot resolve variable \"b\".");
+        assert!(error(r#"<var a="x"/>${a + 1}"#).contains("Type mismatch"));
+
+        // names that are not identifiers are only expressions if no such variable is defined
+        assert_eq!(emit(r#"<var a="3"/><var b="1"/>${a-b}"#), "2");
+        assert_eq!(emit(r#"<var a="3"/><var b="1"/><var a-b="x"/>${a-b}"#), "x");
+        assert_eq!(emit(r#"<var data-id="7"/>${data-id}"#), "7");
+        assert_eq!(error("${page.title}"), "In expression \"page.title\": Cannot resolve variable \"page\".");
+    }
+
+    #[test]
+    fn expression_in_condition() {
+
+        use std::collections::HashMap;
+        use crate::visitors::conditions::If;
+
+        let src = r#"<var count="4"/><if test="${count > 3}">many</if><if test="${count <= 3}">few</if>"#;
+
+        let (document, _) = parse(src);
+
+        let document = transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap();
+        let document = transform(document, &mut vec![Box::new(If::new(HashMap::new()))], 1).unwrap();
+
+        let html = to_html(document).unwrap();
+
+        assert_eq!(html, "many");
+    }
+
+    #[test]
+    fn for_loop() {
+
+        assert_eq!(
+            emit("<var items><list><item>a</item> <item><b>b</b></item> <item>c</item></list></var><ul><for item in items><li>${item}</li></for></ul>"),
+            "<ul><li>a</li><li><b>b</b></li><li>c</li></ul>"
+        );
+
+        // the loop variable is only defined within the loop
+        assert_eq!(
+            emit(r#"<var item="outer"/><var items><list><item>inner</item></list></var><for item in items>${item} </for>${item}"#),
+            "inner outer"
+        );
+
+        assert_eq!(emit("<var items><list/></var><for item in items>${item}</for>"), "");
+
+        let (document, _) = parse(r#"<var items="abc"/><for item in items>${item}</for>"#);
+
+        let error = transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap_err();
+
+        assert_eq!(error.root_cause().to_string(), "Variable \"items\" is not a list.");
+    }
+
+    #[test]
+    fn scopes() {
+
+        let error = |src : &str| {
+            let (document, _) = parse(src);
+            transform(document, &mut vec![Box::new(Variables::new())], 1).unwrap_err().root_cause().to_string()
+        };
+
+        // inner scopes shadow outer scopes
+        assert_eq!(
+            emit(r#"<var a="outer"/><p><var a="inner"/>${a}</p>${a}"#),
+            "<p>inner</p>outer"
+        );
+
+        // variables go out of scope when their env is left
+        assert_eq!(error(r#"<p><var a="x"/>${a}</p>${a}"#), "Cannot resolve variable \"a\".");
+
+        // the scope of a variable defined in an expanded value ends with the expansion
+        assert_eq!(emit(r#"<var b><><var a="x"/>${a}</></var>${b}"#), "x");
+        assert_eq!(error(r#"<var b><><var a="x"/>${a}</></var>${b}${a}"#), "Cannot resolve variable \"a\".");
+    }
+
+    #[test]
+    fn multiple_children() {
+
+        assert_eq!(
+            emit("<var note>line1\nline2 <b>x</b></var><p>${note}</p>"),
+            "<p>line1\nline2 <b>x</b></p>"
+        );
+
+        assert_eq!(
+            emit("<var pair><em>a</em><em>b</em></var>${pair}${pair}"),
+            "<em>a</em><em>b</em><em>a</em><em>b</em>"
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//!
+//! Emits documents as vtx source, e.g. after transforming them.
+//!
+//! Headings, equations and the frontmatter are emitted with their short forms,
+//! all other envs as tags. Attribute values keep the quotes they were parsed with.
+//!
+//! Characters with a meaning in vtx are escaped, except for the contents of raw envs.
+//! Block elements can be indented like with the HTML emitter, see VtxEmitter::indent.
+//! Ordered list markers like "1. " at the start of a line have no escape sequence and are emitted as they are.
+//!
+
+use crate::document::*;
+use crate::parse::ESCAPABLE_CHARS;
+use crate::parse::dynamic_parse::{component_name_definition_attrs, ContentParseMode, DynamicParserState, EnvParseAttrs};
+use crate::visitors::html_emit::{is_block_element, is_preformatted};
+use visit::{Action, VisitError, TransformResult, Visitor};
+
+pub struct VtxEmitter<F : FnMut(&str)> {
+    ///
+    /// Called for every sub-string in the emitted vtx.
+    ///
+    pub collector: F,
+    ///
+    /// Puts block elements on separate lines, indented by this many spaces per level.
+    /// The contents of raw envs (e.g. <Code>), <pre>, and equations are emitted as they are.
+    ///
+    pub indent: Option<usize>,
+    /** For each open block element: whether it contains block elements. */
+    open_blocks: Vec<bool>,
+    /** Id of the element whose contents are emitted without formatting. */
+    preformatted: Option<NodeId>,
+    /** Closing strings of the envs that are currently open. */
+    closing: Vec<String>,
+    /** Id of the raw env whose text is currently being emitted verbatim. */
+    verbatim: Option<NodeId>,
+    /** Keeps track of the components defined with content="raw". */
+    parser_state: DynamicParserState,
+    /** Whether only whitespace has been emitted since the last line break. */
+    line_start: bool,
+}
+
+///
+/// Escapes the characters with a meaning in vtx.
+/// Markers which only have a meaning at the start of a line are escaped only there.
+///
+fn escape(text : &str, mut line_start : bool) -> String {
+
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+
+        let marker = matches!(c, '#' | '-' | '>');
+
+        if ESCAPABLE_CHARS.contains(&c) && (!marker || line_start) {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+
+        line_start = c == '\n' || (line_start && c.is_whitespace());
+    }
+
+    escaped
+}
+
+fn text_of(node : &Node) -> Result<&str, VisitError> {
+    match &node.kind {
+        NodeKind::Leaf(LeafNode::Text(text)) => Ok(text),
+        _ => Err(VisitError::Unknown("Expected a text value.".to_string())),
+    }
+}
+
+///
+/// Returns the quoted value, e.g. "${name}.png".
+/// The recorded quote is used unless the value contains it, which cannot be escaped.
+///
+fn quoted_value(value : &Node, quote_style : QuoteStyle) -> Result<String, VisitError> {
+
+    let parts = match &value.kind {
+        NodeKind::Env(EnvNode { header, kind: EnvNodeKind::Open(children) })
+            if header.kind == EnvNodeHeaderKind::Fragment => children.iter().collect(),
+        _ => vec![value],
+    };
+
+    let mut content = String::new();
+
+    for part in parts {
+        match &part.kind {
+            NodeKind::Leaf(LeafNode::Text(text)) => {
+                for c in text.chars() {
+                    if matches!(c, '\\' | '$') {
+                        content.push('\\');
+                    }
+                    content.push(c);
+                }
+            },
+            NodeKind::Leaf(LeafNode::VariableExpression(expression)) => {
+                content.push_str(&format!("${{{}}}", expression));
+            },
+            _ => return Err(VisitError::Unknown("Encountered an attribute value which cannot be emitted as vtx.".to_string())),
+        }
+    }
+
+    let quote = match (quote_style, content.contains('"'), content.contains('\'')) {
+        (_, true, true) => return Err(VisitError::Unknown(
+            "Attribute values cannot contain both kinds of quotes.".to_string()
+        )),
+        (QuoteStyle::Double, false, _) | (QuoteStyle::Single, _, true) => '"',
+        _ => '\'',
+    };
+
+    Ok(format!("{quote}{content}{quote}"))
+}
+
+/** The attributes of the header as they appear in a tag, with a leading space. */
+fn tag_attrs(header : &EnvNodeHeader) -> Result<String, VisitError> {
+
+    let mut attrs = String::new();
+
+    for (key, value) in &header.attrs {
+
+        // added to every <Eq> by the parser
+        if matches!(header.kind, EnvNodeHeaderKind::Eq(_)) && key == "block" && value.is_none() {
+            continue;
+        }
+
+        match value {
+            None => attrs.push_str(&format!(" {}", key)),
+            // already reported by the parser
+            Some(Node { kind: NodeKind::Leaf(LeafNode::Error(_)), .. }) => {},
+            Some(value) => attrs.push_str(&format!(" {}={}", key, quoted_value(value, header.quote_style(key))?)),
+        }
+    }
+
+    Ok(attrs)
+}
+
+/** The attribute block of a heading, e.g. " {#intro .big}". */
+fn heading_attrs(header : &EnvNodeHeader) -> Result<String, VisitError> {
+
+    if header.attrs.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut parts = Vec::new();
+
+    for (key, value) in &header.attrs {
+
+        let value = value.as_ref().map(text_of).transpose()?.unwrap_or_default();
+
+        match key.as_str() {
+            "id" => parts.push(format!("#{}", value)),
+            "class" => parts.extend(value.split_whitespace().map(|class| format!(".{}", class))),
+            _ => return Err(VisitError::Unknown(
+                format!("Headings cannot have the attribute \"{}\" in vtx.", key)
+            )),
+        }
+    }
+
+    Ok(format!(" {{{}}}", parts.join(" ")))
+}
+
+/** The frontmatter block of the module attributes. */
+fn frontmatter(attrs : &EnvNodeAttrs) -> Result<String, VisitError> {
+
+    if attrs.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut block = String::from("---\n");
+
+    for (key, value) in attrs {
+        block.push_str(&format!("{}: {}\n", key, value.as_ref().map(text_of).transpose()?.unwrap_or_default()));
+    }
+
+    block.push_str("---\n");
+
+    Ok(block)
+}
+
+impl<F : FnMut(&str)> VtxEmitter<F> {
+
+    /** Create new emitter. */
+    pub fn new(collector : F) -> Self {
+        Self {
+            collector,
+            indent: None,
+            open_blocks: Vec::new(),
+            preformatted: None,
+            closing: Vec::new(),
+            verbatim: None,
+            parser_state: DynamicParserState::new(),
+            line_start: true,
+        }
+    }
+
+    fn emit(&mut self, s : &str) {
+
+        if let Some(last) = s.chars().last() {
+            self.line_start = last == '\n' || (self.line_start && s.chars().all(char::is_whitespace));
+        }
+
+        (self.collector)(s);
+    }
+
+    /** Checks if the element is put on a separate line. */
+    fn is_pretty_block(&self, kind : &EnvNodeHeaderKind) -> bool {
+        self.indent.is_some() && self.verbatim.is_none() && self.preformatted.is_none() && is_block_element(kind)
+    }
+
+    /** Starts a new line indented to the current depth unless the current line is still empty. */
+    fn line_break(&mut self) {
+
+        let indentation = " ".repeat(self.indent.unwrap_or(0) * self.open_blocks.len());
+
+        if self.line_start {
+            self.emit(&indentation);
+        } else {
+            self.emit(&format!("\n{}", indentation));
+        }
+    }
+
+    ///
+    /// Returns the strings emitted before and after the children of the env.
+    ///
+    fn delimiters(&self, env : &EnvNode) -> Result<(String, String), VisitError> {
+
+        let header = &env.header;
+
+        let delimiters = match (&header.kind, &env.kind) {
+            (EnvNodeHeaderKind::Module, _) => (frontmatter(&header.attrs)?, String::new()),
+            (EnvNodeHeaderKind::Fragment, _) => ("<>".to_string(), "</>".to_string()),
+            (EnvNodeHeaderKind::Eq(EquationKind::Inline), EnvNodeKind::Open(_)) if header.attrs.is_empty() => (
+                "$".to_string(), "$".to_string()
+            ),
+            (EnvNodeHeaderKind::Eq(EquationKind::Block), EnvNodeKind::Open(_)) if header.attrs.is_empty() => (
+                "$$".to_string(), "$$".to_string()
+            ),
+            (EnvNodeHeaderKind::Heading(level), EnvNodeKind::Open(_)) => {
+
+                if !self.line_start {
+                    return Err(VisitError::Unknown("Headings must start on a new line in vtx.".to_string()));
+                }
+
+                (format!("{} ", "#".repeat(level + 1)), format!("{}\n", heading_attrs(header)?))
+            },
+            (kind, EnvNodeKind::SelfClosing) => (format!("<{}{}/>", kind.get_name(), tag_attrs(header)?), String::new()),
+            (kind, EnvNodeKind::Open(_)) => (
+                format!("<{}{}>", kind.get_name(), tag_attrs(header)?),
+                kind.get_closing_string(),
+            ),
+        };
+
+        Ok(delimiters)
+    }
+
+    /** Mirrors Parser::add_component_definition to know which components are raw. */
+    fn register_component(&mut self, header : &EnvNodeHeader) {
+
+        if header.kind != EnvNodeHeaderKind::ComponentDefinition {
+            return;
+        }
+
+        if let (Some(name), Ok(attrs)) = (
+            component_name_definition_attrs(&header.attrs),
+            EnvParseAttrs::from_attrs(&header.attrs)
+        ) {
+            self.parser_state.set_env_parse_attrs(EnvNodeHeaderKind::Other(name.to_string()), attrs);
+        }
+    }
+
+}
+
+impl<F : FnMut(&str)> Visitor for VtxEmitter<F> {
+
+    fn enter(&mut self, node : Node, _parent_id : Option<NodeId>) -> TransformResult {
+
+        match &node.kind {
+            NodeKind::Env(env) => {
+
+                if self.is_pretty_block(&env.header.kind) {
+
+                    if let Some(has_blocks) = self.open_blocks.last_mut() {
+                        *has_blocks = true;
+                    }
+
+                    self.line_break();
+
+                    self.open_blocks.push(false);
+                }
+
+                let (open, close) = self.delimiters(env)?;
+
+                self.emit(&open);
+
+                self.closing.push(close);
+
+                self.register_component(&env.header);
+
+                let raw = !matches!(
+                    self.parser_state.get_env_parse_attrs(&env.header.kind).content(),
+                    ContentParseMode::Vtx
+                );
+
+                if raw && self.verbatim.is_none() {
+                    self.verbatim = Some(node.id);
+                }
+
+                if self.preformatted.is_none() && is_preformatted(&env.header.kind) {
+                    self.preformatted = Some(node.id);
+                }
+            },
+            NodeKind::Leaf(LeafNode::Text(text)) => match self.verbatim {
+                Some(_) => self.emit(text),
+                None => self.emit(&escape(text, self.line_start)),
+            },
+            NodeKind::Leaf(LeafNode::VariableExpression(expression)) => {
+                self.emit(&format!("${{{}}}", expression));
+            },
+            NodeKind::Leaf(LeafNode::Comment(comment)) => {
+                self.emit(&format!("/**{}*/", comment));
+            },
+            _ => return Err(
+                VisitError::Unknown(
+                    "Encountered a node which cannot be emitted as vtx.".to_string()
+                )
+            ),
+        }
+
+        Ok(Action::keep(node))
+    }
+
+    fn leave(&mut self, node : &Node, original_id : NodeId, _parent_id : Option<NodeId>) {
+
+        if self.verbatim == Some(original_id) {
+            self.verbatim = None;
+        }
+
+        if self.preformatted == Some(original_id) {
+            self.preformatted = None;
+        }
+
+        if let NodeKind::Env(env) = &node.kind {
+
+            if self.is_pretty_block(&env.header.kind) && self.open_blocks.pop().unwrap_or(false) {
+                self.line_break();
+            }
+
+            if let Some(close) = self.closing.pop() {
+                self.emit(&close);
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::parse;
+    use crate::visitors::testing::{to_vtx, to_vtx_with};
+
+    fn emit(src : &str) -> String {
+
+        let (document, _) = parse(src);
+
+        to_vtx(document).unwrap()
+    }
+
+    #[test]
+    fn keeps_quote_styles() {
+
+        let src = "<a href='x.html' title=\"y\" data-say='say \"hi\"' hidden>link</a>";
+
+        assert_eq!(emit(src), src);
+
+        let (document, _) = parse("<img src='a.png'/>");
+
+        let mut document = document;
+
+        let NodeKind::Env(EnvNode { kind: EnvNodeKind::Open(children), .. }) = &mut document.kind else {
+            panic!("Expected the module.");
+        };
+
+        let NodeKind::Env(EnvNode { header, .. }) = &mut children[0].kind else {
+            panic!("Expected <img>.");
+        };
+
+        // a value containing the recorded quote switches to the other one
+        header.attrs.insert("alt".to_string(), Some(Node::new(
+            NodeKind::Leaf(LeafNode::Text("it's".to_string())),
+            NodePosition::Inserted
+        )));
+
+        header.quotes.insert("alt".to_string(), QuoteStyle::Single);
+
+        assert_eq!(to_vtx(document).unwrap(), "<img src='a.png' alt=\"it's\"/>");
+    }
+
+    #[test]
+    fn escapes_text() {
+
+        assert_eq!(emit("a \\* b \\$5 \\\\ c - d"), "a \\* b \\$5 \\\\ c - d");
+        assert_eq!(emit("\\- a\n  \\# b"), "\\- a\n  \\# b");
+        assert_eq!(emit("<Eq>a_1 * b</Eq>"), "<Eq>a_1 * b</Eq>");
+    }
+
+    #[test]
+    fn round_trip() {
+
+        let src = concat!(
+            "---\ntitle: Round trip\n---\n",
+            "# Intro {#intro .big}\n",
+            "Some *emphasis*, **strong** and [a link](https://example.com) for ${name}.\n\n",
+            "- a \\< b\n    1. nested \\$\n- b\n\n",
+            "$x_1$ and \\(y\\)\n$$\ne = mc^2\n$$\n",
+            "<Eq label='eq:x'>a < b</Eq>\n",
+            "<Component Raw content=\"raw\"><>${children}</></Component>\n",
+            "<Raw><b>not an env</b></Raw>\n",
+            "<Code>let x = $y;</Code>\n",
+            "/** comment */<img src=\"${name}.png\" alt='a \\$ b'/>\n",
+        );
+
+        let (document, _) = parse(src);
+
+        let emitted = emit(src);
+
+        let (reparsed, _) = parse(&emitted);
+
+        assert_eq!(reparsed, document, "{}", emitted);
+    }
+
+    #[test]
+    fn indents() {
+
+        let (document, _) = parse("<div><section><p>Text</p><Code>\n  let x = 1;\n    y</Code></section></div>");
+
+        let vtx = to_vtx_with(document, |emitter| emitter.indent = Some(2)).unwrap();
+
+        assert_eq!(
+            vtx,
+            "<div>\n  <section>\n    <p>Text</p>\n    <Code>\n  let x = 1;\n    y</Code>\n  </section>\n</div>"
+        );
+    }
+
+}
@@ -0,0 +1,15 @@
+//!
+//! Browser entry point, enabled by the `wasm` feature.
+//!
+
+use wasm_bindgen::prelude::*;
+
+///
+/// Transpiles the source to HTML, see [crate::render::transpile_to_html].
+/// Errors are thrown as JS errors with a `line:col: message` text.
+///
+#[wasm_bindgen(js_name = transpileToHtml)]
+pub fn transpile_to_html(src : &str) -> Result<String, JsError> {
+
+    crate::render::transpile_to_html(src).map_err(|error| JsError::new(&error.to_string()))
+}